@@ -3,7 +3,7 @@ use simple_protocolbuffers::{read_delta_packed_int, DeltaPackedInt, PackedInt};
 use channelled_callbacks::{CallFinish, Callback, CallbackMerge, MergeTimings, Result as ccResult};
 use crate::elements::{Bbox, ElementType, MinimalBlock, Quadtree, PrimitiveBlock, WithId, WithTimestamp, SetCommon};
 use crate::pbfformat::{make_convert_minimal_block_parts, make_convert_primitive_block};
-use crate::pbfformat::{file_length, read_all_blocks_with_progbar};
+use crate::pbfformat::{file_length, is_stdin, read_all_blocks_with_progbar, BadBlocks};
 use crate::utils::LogTimes;
 
 
@@ -241,23 +241,23 @@ fn calc_collected_data_quadtrees(
     Ok((nodeqts, wayqts, relqts))
 }
 
-fn read_minimal_data(fname: &str, numchan: usize) -> (CollectedData, i64) {
-    
+fn read_minimal_data(fname: &str, numchan: usize) -> Result<(CollectedData, i64)> {
+    let bad_blocks = BadBlocks::abort();
     let conv: CallFinishFileBlocks = if numchan == 0 {
         let cc = Box::new(CollectTiles::new());
-        make_convert_minimal_block_parts(false, true, true, true, cc)
+        make_convert_minimal_block_parts(false, true, true, true, cc, &bad_blocks)
     } else {
         let mut convs: Vec<CallFinishFileBlocks> = Vec::new();
         for _ in 0..numchan {
             let c2 = Box::new(CollectTiles::new());
             convs.push(Box::new(Callback::new(make_convert_minimal_block_parts(
-                false, true, true, true, c2,
+                false, true, true, true, c2, &bad_blocks,
             ))));
         }
         Box::new(CallbackMerge::new(convs, Box::new(MergeTimings::new())))
     };
 
-    let (tm, _) = read_all_blocks_with_progbar(fname, conv, "read data");
+    let (tm, _) = read_all_blocks_with_progbar(fname, conv, "read data", &bad_blocks)?;
 
     let mut rd = CollectedData::new();
     let mut max_timestamp = 0;
@@ -274,24 +274,24 @@ fn read_minimal_data(fname: &str, numchan: usize) -> (CollectedData, i64) {
             _ => {}
         }
     }
-    (rd, max_timestamp)
+    Ok((rd, max_timestamp))
 }
 
-fn read_primitive_data(fname: &str, numchan: usize) -> (CollectedData, Vec<PrimitiveBlock>, i64) {
-    
+fn read_primitive_data(fname: &str, numchan: usize) -> Result<(CollectedData, Vec<PrimitiveBlock>, i64)> {
+    let bad_blocks = BadBlocks::abort();
     let conv: CallFinishFileBlocks = if numchan == 0 {
         let cc = Box::new(CollectTilesPrimitive::new());
-        make_convert_primitive_block(false, cc)
+        make_convert_primitive_block(false, cc, &bad_blocks)
     } else {
         let mut convs: Vec<CallFinishFileBlocks> = Vec::new();
         for _ in 0..numchan {
             let c2 = Box::new(CollectTilesPrimitive::new());
-            convs.push(make_convert_primitive_block(false, c2));
+            convs.push(make_convert_primitive_block(false, c2, &bad_blocks));
         }
         Box::new(CallbackMerge::new(convs, Box::new(MergeTimings::new())))
     };
 
-    let (tm, _) = read_all_blocks_with_progbar(fname, conv, "read data");
+    let (tm, _) = read_all_blocks_with_progbar(fname, conv, "read data", &bad_blocks)?;
 
     let mut rd = CollectedData::new();
     let mut max_timestamp = 0;
@@ -313,7 +313,7 @@ fn read_primitive_data(fname: &str, numchan: usize) -> (CollectedData, Vec<Primi
         }
     }
     blocks.sort_by_key(|b| b.index);
-    (rd, blocks, max_timestamp)
+    Ok((rd, blocks, max_timestamp))
 }
 
 pub fn run_calcqts_inmem(
@@ -328,7 +328,14 @@ pub fn run_calcqts_inmem(
     
     let outfn_ = match outfn {
         Some(o) => String::from(o),
-        None => format!("{}-qts.pbf", &fname[0..fname.len() - 4]),
+        None => {
+            if is_stdin(fname) {
+                return Err(Error::UserSelectionError(
+                    "must specify output filename when reading from stdin".to_string()
+                ));
+            }
+            format!("{}-qts.pbf", &fname[0..fname.len() - 4])
+        }
     };
     let outfn = &outfn_;
 
@@ -338,7 +345,7 @@ pub fn run_calcqts_inmem(
         ));
     }
 
-    let (data,max_timestamp) = read_minimal_data(fname, numchan);
+    let (data,max_timestamp) = read_minimal_data(fname, numchan)?;
     
     lt.add("read data");
     message!(
@@ -389,7 +396,7 @@ pub fn run_calcqts_addto_objs(
         ));
     }
 
-    let (data,mut blocks,max_timestamp) = read_primitive_data(fname, numchan);
+    let (data,mut blocks,max_timestamp) = read_primitive_data(fname, numchan)?;
     
     lt.add("read data");
     message!(
@@ -8,7 +8,7 @@ use std::sync::{Arc, Mutex};
 
 use simple_protocolbuffers::{un_zig_zag, IterTags, PbfTag};
 
-use crate::pbfformat::{file_length, pack_file_block, ReadFileBlocks,CompressionType};
+use crate::pbfformat::{file_length, is_stdin, pack_file_block, ReadFileBlocks,CompressionType};
 
 use channelled_callbacks::{CallFinish, Callback, CallbackSync, CallAll, ReplaceNoneWithTimings, Result as ccResult};
 use crate::elements::Quadtree;
@@ -41,10 +41,10 @@ fn calc_way_quadtrees_simple(
     qt_level: usize,
     qt_buffer: f64,
     numchan: usize
-) -> Box<QuadtreeSimple> {
+) -> Result<Box<QuadtreeSimple>> {
     let wb = Box::new(WayBoxesSimple::new(qt_level, qt_buffer));
 
-    let t = read_nodewaynodes(nodewaynodes, wb, 0, 0, "calc_way_quadtrees_simple", numchan);
+    let t = read_nodewaynodes(nodewaynodes, wb, 0, 0, "calc_way_quadtrees_simple", numchan)?;
 
     //message!("calc_way_quadtrees_simple {}", t);
     messenger().message(&format!("calc_way_quadtrees_simple {}", t));
@@ -55,7 +55,7 @@ fn calc_way_quadtrees_simple(
             _ => {}
         }
     }
-    o.unwrap()
+    Ok(o.unwrap())
 }
 
 fn calc_and_write_qts(
@@ -162,7 +162,7 @@ fn calc_way_quadtrees_split_part(
     qt_buffer: f64,
     numchan: usize,
     ram_gb: usize,
-) -> usize {
+) -> Result<usize> {
     let wb = Box::new(WayBoxesSplit::new(ram_gb as u64));
 
     let mut t = read_nodewaynodes(
@@ -172,7 +172,7 @@ fn calc_way_quadtrees_split_part(
         maxw,
         &format!("calc_way_quadtrees_split_part {} to {}", minw, maxw),
         numchan,
-    );
+    )?;
 
     let mut nb = 0;
 
@@ -184,7 +184,7 @@ fn calc_way_quadtrees_split_part(
             _ => {}
         }
     }
-    nb
+    Ok(nb)
 }
 
 fn calc_way_quadtrees_split_part_inmem(
@@ -196,7 +196,7 @@ fn calc_way_quadtrees_split_part_inmem(
     qt_buffer: f64,
     numchan: usize,
     ram_gb: usize,
-) -> usize {
+) -> Result<usize> {
     let wb = Box::new(WayBoxesSplit::new(ram_gb as u64));
 
     let mut t = read_nodewaynodes(
@@ -206,7 +206,7 @@ fn calc_way_quadtrees_split_part_inmem(
         maxw,
         &format!("calc_way_quadtrees_split_part_inmem {} to {}", minw, maxw),
         numchan,
-    );
+    )?;
 
     let mut nb = 0;
 
@@ -219,7 +219,7 @@ fn calc_way_quadtrees_split_part_inmem(
             _ => {}
         }
     }
-    nb
+    Ok(nb)
 }
 
 fn calc_way_quadtrees_split(
@@ -230,7 +230,7 @@ fn calc_way_quadtrees_split(
     splits: Vec<(i64,i64)>,
     numchan: usize,
     ram_gb: usize,
-) -> Box<QuadtreeSplit> {
+) -> Result<Box<QuadtreeSplit>> {
     let tempfn = format!("{}-wayqts", outfn);
     let wf = Arc::new(Mutex::new(Box::new(WrapWriteFile::new(WriteFile::new(
         &tempfn,
@@ -251,12 +251,12 @@ fn calc_way_quadtrees_split(
             qt_buffer,
             numchan,
             ram_gb,
-        );
+        )?;
         //trim_memory();
     }
 
     wf.lock().unwrap().finish().expect("?");
-    load_way_qts(&tempfn)
+    Ok(load_way_qts(&tempfn))
 }
 
 fn calc_way_quadtrees_split_inmem(
@@ -266,7 +266,7 @@ fn calc_way_quadtrees_split_inmem(
     splits: Vec<(i64,i64)>,
     numchan: usize,
     ram_gb: usize,
-) -> Box<QuadtreeSplit> {
+) -> Result<Box<QuadtreeSplit>> {
     let qts = Arc::new(Mutex::new(Box::new(QuadtreeSplit::new())));
 
     //for (a,b) in &vec![(0,350),(350,700),(700,0)] {
@@ -280,13 +280,13 @@ fn calc_way_quadtrees_split_inmem(
             qt_buffer,
             numchan,
             ram_gb,
-        );
+        )?;
         //trim_memory();
     }
 
     match Arc::try_unwrap(qts) {
         Ok(q) => match q.into_inner() {
-            Ok(p) => p,
+            Ok(p) => Ok(p),
             Err(_) => {
                 panic!("can't release Mutex");
             }
@@ -563,11 +563,11 @@ fn find_node_quadtrees_flatvec(
     qt_level: usize,
     qt_buffer: f64,
     numchan: usize,
-) -> (
+) -> Result<(
     Box<WriteQuadTree>,
     Box<dyn QuadtreeGetSet>,
     Box<QuadtreeSimple>,
-) {
+)> {
     let wqt_wrap = Box::new(DontFinish::new(wqt));
     let wqt_wrap2 = Box::new(FlattenCF::new(wqt_wrap));
     let eqt = Box::new(ExpandNodeQuadtree::new(
@@ -581,7 +581,7 @@ fn find_node_quadtrees_flatvec(
         0,
         "find_node_quadtrees_flatvec",
         numchan,
-    );
+    )?;
 
     message!("find_node_quadtrees_flatvec {}", t);
     let mut a: Option<Box<WriteQuadTree>> = None;
@@ -595,7 +595,7 @@ fn find_node_quadtrees_flatvec(
             _ => {}
         }
     }
-    (a.unwrap(), b.unwrap(), c.unwrap())
+    Ok((a.unwrap(), b.unwrap(), c.unwrap()))
 }
 
 fn find_node_quadtrees_simple(
@@ -606,11 +606,11 @@ fn find_node_quadtrees_simple(
     qt_level: usize,
     qt_buffer: f64,
     numchan: usize,
-) -> (
+) -> Result<(
     Box<WriteQuadTree>,
     Box<dyn QuadtreeGetSet>,
     Box<QuadtreeSimple>,
-) {
+)> {
     let wqt_wrap = Box::new(DontFinish::new(wqt));
     let wqt_wrap2 = Box::new(FlattenCF::new(wqt_wrap));
     let eqt = Box::new(ExpandNodeQuadtree::new(
@@ -624,7 +624,7 @@ fn find_node_quadtrees_simple(
         0,
         "find_node_quadtrees_simple",
         numchan,
-    );
+    )?;
 
     message!("find_node_quadtrees_simple {}", t);
     let mut a: Option<Box<WriteQuadTree>> = None;
@@ -638,7 +638,7 @@ fn find_node_quadtrees_simple(
             _ => {}
         }
     }
-    (a.unwrap(), b.unwrap(), c.unwrap())
+    Ok((a.unwrap(), b.unwrap(), c.unwrap()))
 }
 
 
@@ -651,7 +651,7 @@ fn calc_quadtrees_simple(
     qt_buffer: f64,
     numchan: usize,
     lt: &mut LogTimes,
-) {
+) -> Result<()> {
     let mut nqts = Box::new(QuadtreeSimple::new());
 
     let relmems = match relmems {
@@ -676,7 +676,7 @@ fn calc_quadtrees_simple(
     
     
     
-    let qts = calc_way_quadtrees_simple(nodewaynodes.clone(), qt_level, qt_buffer, numchan)
+    let qts = calc_way_quadtrees_simple(nodewaynodes.clone(), qt_level, qt_buffer, numchan)?
         as Box<dyn QuadtreeGetSet>;
     message!("have {} way quadtrees", qts.len());
     lt.add("calc_way_quadtrees_simple");
@@ -689,10 +689,11 @@ fn calc_quadtrees_simple(
         qt_level,
         qt_buffer,
         numchan,
-    );
+    )?;
     lt.add("find_node_quadtrees_simple");
     write_ways_rels(writeqts, qts, nqts, relmems);
     lt.add("write_ways_rels");
+    Ok(())
 }
 
 fn calc_quadtrees_flatvec(
@@ -705,7 +706,7 @@ fn calc_quadtrees_flatvec(
     numchan: usize,
     ram_gb: usize,
     lt: &mut LogTimes,
-) {
+) -> Result<()> {
     //trim_memory();
     
     
@@ -719,10 +720,10 @@ fn calc_quadtrees_flatvec(
         
     
     let qts = if qinmem {
-        calc_way_quadtrees_split_inmem(nodewaynodes.clone(), qt_level, qt_buffer, splits, numchan, ram_gb)
+        calc_way_quadtrees_split_inmem(nodewaynodes.clone(), qt_level, qt_buffer, splits, numchan, ram_gb)?
             as Box<dyn QuadtreeGetSet>
     } else {
-        calc_way_quadtrees_split(nodewaynodes.clone(), outfn, qt_level, qt_buffer, splits, numchan, ram_gb)
+        calc_way_quadtrees_split(nodewaynodes.clone(), outfn, qt_level, qt_buffer, splits, numchan, ram_gb)?
             as Box<dyn QuadtreeGetSet>
     };
     lt.add("calc_way_quadtrees_split");
@@ -749,7 +750,7 @@ fn calc_quadtrees_flatvec(
         qt_level,
         qt_buffer,
         numchan,
-    );
+    )?;
     //trim_memory();
     lt.add("find_node_quadtrees_flatvec");
 
@@ -769,6 +770,7 @@ fn calc_quadtrees_flatvec(
     //let relmems = load_relmems(&relmfn, true, true);
     write_ways_rels(writeqts, qts, nqts, rm);
     lt.add("write_ways_rels");
+    Ok(())
 }
 
 fn write_ways_rels(
@@ -934,7 +936,7 @@ pub fn run_calcqts_load_existing(
         numchan,
         8,
         &mut lt,
-    );
+    )?;
 
     /*
     let qts = calc_way_quadtrees_split_inmem(nodewaynodes.clone(), qt_level, qt_buffer, numchan)
@@ -1006,6 +1008,16 @@ pub fn run_calcqts(
     numchan: usize,
     ram_gb: usize,
 ) -> Result<(String,LogTimes, i64)> {
+    if is_stdin(fname) {
+        //the other modes read the input more than once
+        return match mode {
+            Mode::Choose | Mode::Inmem => run_calcqts_inmem(fname, outfn, qt_level, qt_buffer, numchan),
+            _ => Err(Error::UserSelectionError(
+                "only mode inmem can read from stdin".to_string()
+            )),
+        };
+    }
+    
     let mut use_simple = false;
     let fl = file_length(fname) / 1024 / 1024;
     match mode {
@@ -1116,7 +1128,7 @@ pub fn run_calcqts(
             qt_buffer,
             numchan,
             &mut lt,
-        );
+        )?;
     } else {
         
         /*let nodewaynodes = if ram_gb > 24 {
@@ -1146,7 +1158,7 @@ pub fn run_calcqts(
             numchan,
             ram_gb,
             &mut lt,
-        );
+        )?;
     }
     //message!("{}", lt);
     
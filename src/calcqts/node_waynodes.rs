@@ -3,7 +3,7 @@ use crate::elements::{MinimalBlock, MinimalNode};
 use crate::pbfformat::{
     file_length, make_convert_minimal_block_parts, pack_file_block,
     read_all_blocks_parallel_with_progbar, read_all_blocks_with_progbar_stop,
-    read_file_block_with_pos, unpack_file_block, FileBlock, HeaderType,
+    read_file_block_with_pos, unpack_file_block, BadBlocks, FileBlock, HeaderType,
     ReadFileBlocks, WriteFile, read_all_blocks_vec_with_progbar,CompressionType
 };
 use crate::{message,progress_percent};
@@ -167,13 +167,15 @@ fn read_waynodevals<T: CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType
                 ll.push((a, xx))
             }
 
-            Ok(read_all_blocks_parallel_with_progbar(
+            read_all_blocks_parallel_with_progbar(
                 &mut fbuf,
+                &[fname.clone()],
                 &ll,
                 vvm,
                 "read waynodevals",
                 file_length(&fname),
-            ))
+                &BadBlocks::abort(),
+            )
         }
         WayNodeVals::FlatFile(fname) => {
             
@@ -181,8 +183,8 @@ fn read_waynodevals<T: CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType
                 &fname,
                 vvm,
                 "read waynodevals",
-                
-            ).0)
+                &BadBlocks::abort(),
+            )?.0)
         }
     }
 }
@@ -690,14 +692,15 @@ pub fn read_nodewaynodes<T: CallFinish<CallType = Vec<NodeWayNodeComb>, ReturnTy
     maxw: i64,
     msg: &str,
     numchan: usize,
-) -> Timings {
+) -> Result<Timings> {
     let (infn, waynodevals, stop_after) = nodewaynodes;
-        
+    let bad_blocks = BadBlocks::abort();
+
     if numchan == 0 {
         let wn_iter = ChannelReadWayNodeFlatIter::filter_vals(waynodevals.clone(), minw, maxw);
         let combine = Box::new(CombineNodeWayNodeCB::new(wn_iter, eqt));
-        let convert = make_convert_minimal_block_parts(false, true, false, false, combine);
-        read_all_blocks_with_progbar_stop(&infn, stop_after, convert, msg).0
+        let convert = make_convert_minimal_block_parts(false, true, false, false, combine, &bad_blocks);
+        Ok(read_all_blocks_with_progbar_stop(&infn, stop_after, convert, msg, &bad_blocks)?.0)
     } else {
         let wbs = Box::new(Callback::new(eqt));
 
@@ -709,11 +712,11 @@ pub fn read_nodewaynodes<T: CallFinish<CallType = Vec<NodeWayNodeComb>, ReturnTy
         for c in combines {
             let c2 = Box::new(ReplaceNoneWithTimings::new(c));
             converts.push(Box::new(Callback::new(make_convert_minimal_block_parts(
-                false, true, false, false, c2,
+                false, true, false, false, c2, &bad_blocks,
             ))));
         }
         let conv_merge = Box::new(CallbackMerge::new(converts, Box::new(MergeTimings::new())));
-        read_all_blocks_with_progbar_stop(&infn, stop_after, conv_merge, msg).0
+        Ok(read_all_blocks_with_progbar_stop(&infn, stop_after, conv_merge, msg, &bad_blocks)?.0)
     }
 }
 
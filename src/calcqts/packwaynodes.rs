@@ -6,7 +6,7 @@ use simple_protocolbuffers::{
 use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, MergeTimings, ReplaceNoneWithTimings, Result as ccResult};
 use crate::elements::{MinimalBlock, Quadtree};
 use crate::pbfformat::{
-    pack_file_block, read_all_blocks_with_progbar, unpack_file_block, FileBlock, FileLocs,
+    pack_file_block, read_all_blocks_with_progbar, unpack_file_block, BadBlocks, FileBlock, FileLocs,
     HeaderType, WriteFile,CompressionType
 };
 
@@ -707,7 +707,7 @@ pub fn prep_way_nodes(infn: &str, numchan: usize) -> Result<(RelMems, WayNodeVal
             Box::new(CallbackMerge::new(pwn_par, Box::new(MergeTimings::new())))
         }
     };
-    let (tt, _) = read_all_blocks_with_progbar(infn, pwn, &progmsg);
+    let (tt, _) = read_all_blocks_with_progbar(infn, pwn, &progmsg, &BadBlocks::abort())?;
     message!("{}", tt);
     Ok(get_relmems_waynodes(tt))
 }
@@ -747,7 +747,7 @@ pub fn prep_way_nodes_tempfile(
             Box::new(CallbackMerge::new(pwn_par, Box::new(MergeTimings::new())))
         }
     };
-    let (tt, _) = read_all_blocks_with_progbar(infn, pwn, &progmsg);
+    let (tt, _) = read_all_blocks_with_progbar(infn, pwn, &progmsg, &BadBlocks::abort())?;
     message!("{}", tt);
     Ok(get_relmems_waynodes(tt))
 }
@@ -1,8 +1,6 @@
-use std::fs::File;
-
 use crate::pbfformat::{
-    file_length, read_all_blocks_parallel_with_progbar, read_all_blocks_prog_fpos,
-    FileBlock, ParallelFileLocs,
+    file_length, is_stdin, open_input, read_all_blocks_parallel_with_progbar,
+    read_all_blocks_prog_fpos, FileBlock, ParallelFileLocs,
 };
 
 use crate::update::{read_xml_change, ChangeBlock};
@@ -11,12 +9,13 @@ use channelled_callbacks::{CallFinish, Callback, CallbackMerge, MergeTimings, Re
 use crate::pbfformat::{
     make_convert_minimal_block, make_convert_primitive_block,
     make_read_minimal_blocks_combine_call_all, make_read_primitive_blocks_combine_call_all,
-    get_file_locs
+    make_read_primitive_blocks_combine_call_all_idset, get_file_locs, BadBlocks, History,
 };
+use crate::mergechanges::{prep_tag_filter, read_tag_filter};
 use crate::utils::ThreadTimer;
 
 use crate::elements::{
-    Changetype, MinimalBlock, MinimalNode, MinimalRelation, MinimalWay, Node, PrimitiveBlock,
+    Changetype, IdSet, IdSetAll, MinimalBlock, MinimalNode, MinimalRelation, MinimalWay, Node, PrimitiveBlock,
     Relation, Way,
 };
 use std::io::BufReader;
@@ -30,6 +29,7 @@ use crate::message;
 
 use std::collections::BTreeMap;
 use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct NodeCount {
@@ -741,7 +741,9 @@ pub fn call_count(fname: &str,
     use_primitive: bool,
     numchan: usize,
     filter_in: Option<&str>,
+    tag_filter_in: Option<&str>,
     tstamp: Option<&str>,
+    bad_blocks: &BadBlocks,
 ) -> Result<CountAny> {
     
     
@@ -753,13 +755,25 @@ pub fn call_count(fname: &str,
             Some(bbox)
         }
     };
+    let tag_filter = read_tag_filter(tag_filter_in)?;
 
-    let f = File::open(fname).expect("file not present");
+    if is_stdin(fname) && (filter.is_some() || tag_filter.is_some() || tstamp.is_some()) {
+        return Err(Error::UserSelectionError(
+            "can't filter or apply updates when reading from stdin".to_string()
+        ));
+    }
+    if tag_filter.is_some() && (fname.ends_with(".osc") || fname.ends_with(".osc.gz") || fname.ends_with(".pbfc")) {
+        return Err(Error::UserSelectionError(
+            "can't apply a tag filter to change files".to_string()
+        ));
+    }
+
+    let f = open_input(fname)?;
 
     if fname.ends_with(".osc") {
         let mut cn = CountChange::new();
         let mut fbuf = BufReader::with_capacity(1024 * 1024, f);
-        let data = read_xml_change(&mut fbuf).expect("failed to read osc");
+        let data = read_xml_change(&mut fbuf)?;
 
         cn.add_changeblock(&data);
         Ok(CountAny::CountChange(cn))
@@ -768,7 +782,7 @@ pub fn call_count(fname: &str,
         let fbuf = BufReader::with_capacity(1024 * 1024, f);
         let mut gzbuf = BufReader::new(flate2::bufread::GzDecoder::new(fbuf));
         //Box::new(gzbuf) as Box<dyn std::io::BufRead>
-        let data = read_xml_change(&mut gzbuf).expect("failed to read osc");
+        let data = read_xml_change(&mut gzbuf)?;
 
         cn.add_changeblock(&data);
         Ok(CountAny::CountChange(cn))
@@ -778,17 +792,15 @@ pub fn call_count(fname: &str,
         
         let mut fbuf = BufReader::new(f);
         let cc = Box::new(CountChangeMinimal::new());
-        let cn = Box::new(Callback::new(make_convert_minimal_block(true, cc)));
-        let (mut a, _) = read_all_blocks_prog_fpos(&mut fbuf, cn, pg);
+        let cn = Box::new(Callback::new(make_convert_minimal_block(true, cc, bad_blocks)));
+        let (mut a, _) = read_all_blocks_prog_fpos(&mut fbuf, cn, pg, bad_blocks)?;
         
         let cn = std::mem::take(&mut a.others).pop().unwrap().1;
 
         Ok(CountAny::CountChange(cn))
     //message!("{:?}", cn.relation.get(&Changetype::Create));
-    } else if std::fs::metadata(fname)
-        .expect("failed to open file")
-        .is_file()
-        && filter.is_none() && tstamp.is_none()
+    } else if (is_stdin(fname) || std::fs::metadata(fname)?.is_file())
+        && filter.is_none() && tag_filter.is_none() && tstamp.is_none()
     {
         let mut cc = Count::new();
 
@@ -805,14 +817,14 @@ pub fn call_count(fname: &str,
             let (a, _) = if use_primitive {
                 
                 let cm = Box::new(CountPrim::new());
-                let cc = make_convert_primitive_block(false, cm);
-                read_all_blocks_prog_fpos(&mut fbuf, cc, pg)
+                let cc = make_convert_primitive_block(false, cm, bad_blocks);
+                read_all_blocks_prog_fpos(&mut fbuf, cc, pg, bad_blocks)?
             } else {
                 
                 crate::logging::messenger().start_progress_bytes(&format!("count blocks minimal {}, numchan=0", fname), file_length(fname));
                 let cm = Box::new(CountMinimal::new());
-                let cc = make_convert_minimal_block(false, cm);
-                read_all_blocks_prog_fpos(&mut fbuf, cc, pg)
+                let cc = make_convert_minimal_block(false, cm, bad_blocks);
+                read_all_blocks_prog_fpos(&mut fbuf, cc, pg, bad_blocks)?
             };
             //pb.finish();
             cc.add_other(&a.others[0].1);
@@ -835,18 +847,18 @@ pub fn call_count(fname: &str,
                     
                     let cm = Box::new(CountPrim::new());
                     ccs.push(Box::new(Callback::new(make_convert_primitive_block(
-                        false, cm,
+                        false, cm, bad_blocks,
                     ))));
                 } else {
                     
                     let cm = Box::new(CountMinimal::new());
                     ccs.push(Box::new(Callback::new(make_convert_minimal_block(
-                        false, cm,
+                        false, cm, bad_blocks,
                     ))));
                 }
             }
             let cm = Box::new(CallbackMerge::new(ccs, Box::new(MergeTimings::new())));
-            let (a, _) = read_all_blocks_prog_fpos(&mut fbuf, cm, pg);
+            let (a, _) = read_all_blocks_prog_fpos(&mut fbuf, cm, pg, bad_blocks)?;
             //pb.finish();
             for (_, x) in a.others {
                 cc.add_other(&x);
@@ -860,9 +872,18 @@ pub fn call_count(fname: &str,
             None => None,
         };
         
-        let file_locs = get_file_locs(fname, filter, tstamp).expect("?");
+        let mut file_locs = get_file_locs(fname, filter, tstamp)?;
 
-        call_count_combine(fname, file_locs, use_primitive, numchan)
+        let ids = match &tag_filter {
+            Some(tf) => {
+                let ids = prep_tag_filter(&mut file_locs, tf, Arc::new(IdSetAll()), numchan, bad_blocks)?;
+                message!("have: {}", ids);
+                Some(ids)
+            }
+            None => None,
+        };
+
+        call_count_combine(fname, file_locs, use_primitive, numchan, ids, bad_blocks)
 
 
         
@@ -870,9 +891,9 @@ pub fn call_count(fname: &str,
     
 }
 
-pub fn call_count_combine(fname: &str, file_locs: ParallelFileLocs, use_primitive: bool, numchan: usize) -> Result<CountAny> {
+pub fn call_count_combine(fname: &str, file_locs: ParallelFileLocs, use_primitive: bool, numchan: usize, ids: Option<Arc<dyn IdSet>>, bad_blocks: &BadBlocks) -> Result<CountAny> {
     
-    let (mut fbufs, locsv, total_len) = file_locs;
+    let (mut fbufs, locsv, total_len, fnames) = file_locs;
     
     let mut pps: Vec<
         Box<
@@ -884,7 +905,19 @@ pub fn call_count_combine(fname: &str, file_locs: ParallelFileLocs, use_primitiv
         >,
     > = Vec::new();
     let msg: String;
-    if use_primitive {
+    if let Some(ids) = ids {
+        //only the full primitiveblock data can be filtered by id
+        msg = format!(
+            "count blocks combine primitive filtered {}, numchan={}",
+            fname, numchan
+        );
+        for _ in 0..numchan {
+            let cca = Box::new(CountPrim::new());
+            pps.push(Box::new(Callback::new(
+                make_read_primitive_blocks_combine_call_all_idset(cca, ids.clone(), true, History::None, bad_blocks),
+            )));
+        }
+    } else if use_primitive {
         msg = format!(
             "count blocks combine primitive {}, numchan={}",
             fname, numchan
@@ -892,7 +925,7 @@ pub fn call_count_combine(fname: &str, file_locs: ParallelFileLocs, use_primitiv
         for _ in 0..numchan {
             let cca = Box::new(CountPrim::new());
             pps.push(Box::new(Callback::new(
-                make_read_primitive_blocks_combine_call_all(cca),
+                make_read_primitive_blocks_combine_call_all(cca, History::None, bad_blocks),
             )));
         }
     } else {
@@ -903,12 +936,12 @@ pub fn call_count_combine(fname: &str, file_locs: ParallelFileLocs, use_primitiv
         for _ in 0..numchan {
             let cca = Box::new(CountMinimal::new());
             pps.push(Box::new(Callback::new(
-                make_read_minimal_blocks_combine_call_all(cca),
+                make_read_minimal_blocks_combine_call_all(cca, bad_blocks),
             )));
         }
     }
     let readb = Box::new(CallbackMerge::new(pps, Box::new(MergeTimings::new())));
-    let a = read_all_blocks_parallel_with_progbar(&mut fbufs, &locsv, readb, &msg, total_len);
+    let a = read_all_blocks_parallel_with_progbar(&mut fbufs, &fnames, &locsv, readb, &msg, total_len, bad_blocks)?;
 
     let mut cc = Count::new();
     for (_, y) in &a.others {
@@ -923,12 +956,14 @@ pub fn run_count(
     use_primitive: bool,
     numchan: usize,
     filter_in: Option<&str>,
-    tstamp: Option<&str>
+    tag_filter_in: Option<&str>,
+    tstamp: Option<&str>,
+    bad_blocks: &BadBlocks,
 ) -> Result<()> {
     
     
     //crate::logging::messenger().message(&format!("{}", call_count(fname,use_primitive, numchan, filter_in)?));
-    message!("{}", call_count(fname,use_primitive, numchan, filter_in, tstamp)?);
+    message!("{}", call_count(fname,use_primitive, numchan, filter_in, tag_filter_in, tstamp, bad_blocks)?);
     Ok(())
 }
     
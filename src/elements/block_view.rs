@@ -0,0 +1,557 @@
+use simple_protocolbuffers::{un_zig_zag, IterTags, PbfTag};
+
+use crate::elements::{Changetype, ElementType, Member, Node, Quadtree, Relation, Tag, Way};
+use crate::utils::{Error, Result};
+
+//values in packed fields are read lazily, so unlike read_packed_int these
+//don't allocate
+#[derive(Clone)]
+struct Packed<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Packed<'a> {
+    fn new(data: &'a [u8]) -> Packed<'a> {
+        Packed { data: data, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for Packed<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let mut res = 0u64;
+        let mut shift = 0;
+        while self.pos < self.data.len() && shift < 64 {
+            let b = self.data[self.pos];
+            self.pos += 1;
+            res |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Some(res);
+            }
+            shift += 7;
+        }
+        None
+    }
+}
+
+#[derive(Clone)]
+struct DeltaPacked<'a> {
+    inner: Packed<'a>,
+    curr: i64,
+}
+
+impl<'a> DeltaPacked<'a> {
+    fn new(data: &'a [u8]) -> DeltaPacked<'a> {
+        DeltaPacked {
+            inner: Packed::new(data),
+            curr: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for DeltaPacked<'a> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let v = self.inner.next()?;
+        self.curr += un_zig_zag(v);
+        Some(self.curr)
+    }
+}
+
+/// Block of elements which are decoded on demand from the uncompressed
+/// block data. Only ids, locations and quadtrees are read up front: tags,
+/// way refs and relation members are read when requested, and strings are
+/// borrowed from the block's string table. Element info isn't read, use
+/// [`PrimitiveBlock`](crate::elements::PrimitiveBlock) if it's needed.
+pub struct BlockView {
+    pub index: i64,
+    pub location: u64,
+    pub quadtree: Quadtree,
+    pub start_date: i64,
+    pub end_date: i64,
+    data: Vec<u8>,
+    strings: Vec<(usize, usize)>,
+    groups: Vec<(Changetype, usize, usize)>,
+}
+
+fn offset(data: &[u8], part: &[u8]) -> usize {
+    part.as_ptr() as usize - data.as_ptr() as usize
+}
+
+fn find_changetype(data: &[u8], ischange: bool) -> Result<Changetype> {
+    if !ischange {
+        return Ok(Changetype::Normal);
+    }
+    for x in IterTags::new(data) {
+        match x {
+            PbfTag::Value(10, ct) => {
+                return Changetype::try_from_int(ct);
+            }
+            _ => {}
+        }
+    }
+    Ok(Changetype::Normal)
+}
+
+impl BlockView {
+    pub fn new(index: i64, location: u64) -> BlockView {
+        BlockView {
+            index: index,
+            location: location,
+            quadtree: Quadtree::empty(),
+            start_date: 0,
+            end_date: 0,
+            data: Vec::new(),
+            strings: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn read(index: i64, location: u64, data: Vec<u8>, ischange: bool) -> Result<BlockView> {
+        let mut res = BlockView::new(index, location);
+
+        for x in IterTags::new(&data) {
+            match x {
+                PbfTag::Data(1, d) => {
+                    for y in IterTags::new(d) {
+                        match y {
+                            PbfTag::Data(1, s) => {
+                                let p = offset(&data, s);
+                                res.strings.push((p, p + s.len()));
+                            }
+                            _ => return Err(Error::PbfDataError("unexpected item".to_string())),
+                        }
+                    }
+                }
+                PbfTag::Data(2, d) => {
+                    let p = offset(&data, d);
+                    res.groups.push((find_changetype(d, ischange)?, p, p + d.len()));
+                }
+
+                PbfTag::Value(32, qt) => res.quadtree = Quadtree::new(un_zig_zag(qt)),
+                PbfTag::Value(33, sd) => res.start_date = sd as i64,
+                PbfTag::Value(34, ed) => res.end_date = ed as i64,
+
+                PbfTag::Value(17, granularity) => {
+                    if granularity != 100 {
+                        return Err(Error::PbfDataError(format!("unexpected granuality [{}!=100]", granularity)));
+                    }
+                }
+                PbfTag::Value(18, date_granularity) => {
+                    if date_granularity != 1000 {
+                        return Err(Error::PbfDataError(format!("unexpected date_granularity [{}!=1000]", date_granularity)));
+                    }
+                }
+                PbfTag::Value(19, lat_offset) => {
+                    if lat_offset != 0 {
+                        return Err(Error::PbfDataError(format!("unexpected lat_offset [{}!=0]", lat_offset)));
+                    }
+                }
+                PbfTag::Value(20, lon_offset) => {
+                    if lon_offset != 0 {
+                        return Err(Error::PbfDataError(format!("unexpected lon_offset [{}!=0]", lon_offset)));
+                    }
+                }
+
+                _ => return Err(Error::PbfDataError("unexpected item".to_string())),
+            }
+        }
+        res.data = data;
+        Ok(res)
+    }
+
+    /// Returns string `i` of the block's string table, or an error if it is
+    /// missing or isn't valid utf8.
+    pub fn string<'a>(&'a self, i: u64) -> Result<&'a str> {
+        match self.strings.get(i as usize) {
+            Some((a, b)) => Ok(std::str::from_utf8(&self.data[*a..*b])?),
+            None => Err(Error::PbfDataError(format!(
+                "string {} out of range [{} strings]",
+                i,
+                self.strings.len()
+            ))),
+        }
+    }
+
+    fn group_items<'a>(&'a self) -> impl Iterator<Item = (Changetype, PbfTag<'a>)> + 'a {
+        self.groups
+            .iter()
+            .flat_map(move |&(ct, a, b)| IterTags::new(&self.data[a..b]).map(move |t| (ct, t)))
+    }
+
+    pub fn nodes<'a>(&'a self) -> impl Iterator<Item = NodeView<'a>> + 'a {
+        self.group_items().flat_map(move |(ct, t)| -> Box<dyn Iterator<Item = NodeView<'a>> + 'a> {
+            match t {
+                PbfTag::Data(1, d) => Box::new(std::iter::once(NodeView::read(self, ct, d))),
+                PbfTag::Data(2, d) => Box::new(DenseNodes::new(self, ct, d)),
+                _ => Box::new(std::iter::empty()),
+            }
+        })
+    }
+
+    pub fn ways<'a>(&'a self) -> impl Iterator<Item = WayView<'a>> + 'a {
+        self.group_items().filter_map(move |(ct, t)| match t {
+            PbfTag::Data(3, d) => Some(WayView::read(self, ct, d)),
+            _ => None,
+        })
+    }
+
+    pub fn relations<'a>(&'a self) -> impl Iterator<Item = RelationView<'a>> + 'a {
+        self.group_items().filter_map(move |(ct, t)| match t {
+            PbfTag::Data(4, d) => Some(RelationView::read(self, ct, d)),
+            _ => None,
+        })
+    }
+}
+
+impl std::fmt::Debug for BlockView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BlockView {} at {} [{} bytes, {} groups]", self.index, self.location, self.data.len(), self.groups.len())
+    }
+}
+
+/// Iterator over the (key, val) tags of an element. Returns an error if a
+/// string index isn't in the block's string table.
+#[derive(Clone)]
+pub struct TagsIter<'a> {
+    block: &'a BlockView,
+    keys: Packed<'a>,
+    //dense nodes store keys and vals interleaved, with each node's tags
+    //ending with a zero
+    vals: Option<Packed<'a>>,
+}
+
+impl<'a> TagsIter<'a> {
+    fn new(block: &'a BlockView, keys: &'a [u8], vals: &'a [u8]) -> TagsIter<'a> {
+        TagsIter {
+            block: block,
+            keys: Packed::new(keys),
+            vals: Some(Packed::new(vals)),
+        }
+    }
+
+    fn dense(block: &'a BlockView, kvs: Packed<'a>) -> TagsIter<'a> {
+        TagsIter {
+            block: block,
+            keys: kvs,
+            vals: None,
+        }
+    }
+}
+
+impl<'a> Iterator for TagsIter<'a> {
+    type Item = Result<(&'a str, &'a str)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, v) = match self.vals.as_mut() {
+            Some(vals) => (self.keys.next()?, vals.next()?),
+            None => {
+                let k = self.keys.next()?;
+                if k == 0 {
+                    return None;
+                }
+                (k, self.keys.next()?)
+            }
+        };
+        let k = match self.block.string(k) {
+            Ok(k) => k,
+            Err(e) => return Some(Err(e)),
+        };
+        let v = match self.block.string(v) {
+            Ok(v) => v,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Ok((k, v)))
+    }
+}
+
+fn find_tag<'a>(tags: TagsIter<'a>, key: &str) -> Result<Option<&'a str>> {
+    for t in tags {
+        let (k, v) = t?;
+        if k == key {
+            return Ok(Some(v));
+        }
+    }
+    Ok(None)
+}
+
+fn to_tags(tags: TagsIter) -> Result<Vec<Tag>> {
+    let mut res = Vec::new();
+    for t in tags {
+        let (k, v) = t?;
+        res.push(Tag::new(k, v));
+    }
+    Ok(res)
+}
+
+#[derive(Clone)]
+pub struct NodeView<'a> {
+    block: &'a BlockView,
+    pub changetype: Changetype,
+    pub id: i64,
+    pub lon: i32,
+    pub lat: i32,
+    pub quadtree: Quadtree,
+    tags: TagsIter<'a>,
+}
+
+impl<'a> NodeView<'a> {
+    fn read(block: &'a BlockView, changetype: Changetype, data: &'a [u8]) -> NodeView<'a> {
+        let mut res = NodeView {
+            block: block,
+            changetype: changetype,
+            id: 0,
+            lon: 0,
+            lat: 0,
+            quadtree: Quadtree::empty(),
+            tags: TagsIter::new(block, &[], &[]),
+        };
+        let mut keys: &[u8] = &[];
+        let mut vals: &[u8] = &[];
+        for t in IterTags::new(data) {
+            match t {
+                PbfTag::Value(1, i) => res.id = i as i64,
+                PbfTag::Data(2, d) => keys = d,
+                PbfTag::Data(3, d) => vals = d,
+                PbfTag::Value(8, lat) => res.lat = un_zig_zag(lat) as i32,
+                PbfTag::Value(9, lon) => res.lon = un_zig_zag(lon) as i32,
+                PbfTag::Value(20, q) => res.quadtree = Quadtree::new(un_zig_zag(q)),
+                _ => {}
+            }
+        }
+        res.tags = TagsIter::new(block, keys, vals);
+        res
+    }
+
+    pub fn tags(&self) -> TagsIter<'a> {
+        self.tags.clone()
+    }
+
+    pub fn get_tag(&self, key: &str) -> Result<Option<&'a str>> {
+        find_tag(self.tags(), key)
+    }
+
+    /// Decodes this node into an owned [`Node`], without info.
+    pub fn to_node(&self) -> Result<Node> {
+        let mut n = Node::new(self.id, self.changetype);
+        n.lon = self.lon;
+        n.lat = self.lat;
+        n.quadtree = self.quadtree;
+        n.tags = to_tags(self.tags())?;
+        Ok(n)
+    }
+}
+
+struct DenseNodes<'a> {
+    block: &'a BlockView,
+    changetype: Changetype,
+    ids: DeltaPacked<'a>,
+    lats: DeltaPacked<'a>,
+    lons: DeltaPacked<'a>,
+    qts: DeltaPacked<'a>,
+    kvs: Packed<'a>,
+}
+
+impl<'a> DenseNodes<'a> {
+    fn new(block: &'a BlockView, changetype: Changetype, data: &'a [u8]) -> DenseNodes<'a> {
+        let mut res = DenseNodes {
+            block: block,
+            changetype: changetype,
+            ids: DeltaPacked::new(&[]),
+            lats: DeltaPacked::new(&[]),
+            lons: DeltaPacked::new(&[]),
+            qts: DeltaPacked::new(&[]),
+            kvs: Packed::new(&[]),
+        };
+        for t in IterTags::new(data) {
+            match t {
+                PbfTag::Data(1, d) => res.ids = DeltaPacked::new(d),
+                PbfTag::Data(8, d) => res.lats = DeltaPacked::new(d),
+                PbfTag::Data(9, d) => res.lons = DeltaPacked::new(d),
+                PbfTag::Data(10, d) => res.kvs = Packed::new(d),
+                PbfTag::Data(20, d) => res.qts = DeltaPacked::new(d),
+                _ => {}
+            }
+        }
+        res
+    }
+}
+
+impl<'a> Iterator for DenseNodes<'a> {
+    type Item = NodeView<'a>;
+
+    fn next(&mut self) -> Option<NodeView<'a>> {
+        let id = self.ids.next()?;
+        let tags = TagsIter::dense(self.block, self.kvs.clone());
+        //skip past this node's tags
+        loop {
+            match self.kvs.next() {
+                None | Some(0) => break,
+                Some(_) => {
+                    self.kvs.next();
+                }
+            }
+        }
+        Some(NodeView {
+            block: self.block,
+            changetype: self.changetype,
+            id: id,
+            lat: self.lats.next().unwrap_or(0) as i32,
+            lon: self.lons.next().unwrap_or(0) as i32,
+            quadtree: match self.qts.next() {
+                Some(q) => Quadtree::new(q),
+                None => Quadtree::empty(),
+            },
+            tags: tags,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct WayView<'a> {
+    block: &'a BlockView,
+    pub changetype: Changetype,
+    pub id: i64,
+    pub quadtree: Quadtree,
+    keys: &'a [u8],
+    vals: &'a [u8],
+    refs: &'a [u8],
+    lats: &'a [u8],
+    lons: &'a [u8],
+}
+
+impl<'a> WayView<'a> {
+    fn read(block: &'a BlockView, changetype: Changetype, data: &'a [u8]) -> WayView<'a> {
+        let mut res = WayView {
+            block: block,
+            changetype: changetype,
+            id: 0,
+            quadtree: Quadtree::empty(),
+            keys: &[],
+            vals: &[],
+            refs: &[],
+            lats: &[],
+            lons: &[],
+        };
+        for t in IterTags::new(data) {
+            match t {
+                PbfTag::Value(1, i) => res.id = i as i64,
+                PbfTag::Data(2, d) => res.keys = d,
+                PbfTag::Data(3, d) => res.vals = d,
+                PbfTag::Data(8, d) => res.refs = d,
+                PbfTag::Data(9, d) => res.lats = d,
+                PbfTag::Data(10, d) => res.lons = d,
+                PbfTag::Value(20, q) => res.quadtree = Quadtree::new(un_zig_zag(q)),
+                _ => {}
+            }
+        }
+        res
+    }
+
+    pub fn tags(&self) -> TagsIter<'a> {
+        TagsIter::new(self.block, self.keys, self.vals)
+    }
+
+    pub fn get_tag(&self, key: &str) -> Result<Option<&'a str>> {
+        find_tag(self.tags(), key)
+    }
+
+    pub fn refs(&self) -> impl Iterator<Item = i64> + 'a {
+        DeltaPacked::new(self.refs)
+    }
+
+    /// Node locations stored on the way (the LocationsOnWays feature), if
+    /// present.
+    pub fn lonlats(&self) -> impl Iterator<Item = (i32, i32)> + 'a {
+        DeltaPacked::new(self.lons)
+            .zip(DeltaPacked::new(self.lats))
+            .map(|(ln, lt)| (ln as i32, lt as i32))
+    }
+
+    /// Decodes this way into an owned [`Way`], without info.
+    pub fn to_way(&self) -> Result<Way> {
+        let mut w = Way::new(self.id, self.changetype);
+        w.quadtree = self.quadtree;
+        w.tags = to_tags(self.tags())?;
+        w.refs = self.refs().collect();
+        w.lonlats = self.lonlats().collect();
+        Ok(w)
+    }
+}
+
+#[derive(Clone)]
+pub struct RelationView<'a> {
+    block: &'a BlockView,
+    pub changetype: Changetype,
+    pub id: i64,
+    pub quadtree: Quadtree,
+    keys: &'a [u8],
+    vals: &'a [u8],
+    roles: &'a [u8],
+    refs: &'a [u8],
+    types: &'a [u8],
+}
+
+impl<'a> RelationView<'a> {
+    fn read(block: &'a BlockView, changetype: Changetype, data: &'a [u8]) -> RelationView<'a> {
+        let mut res = RelationView {
+            block: block,
+            changetype: changetype,
+            id: 0,
+            quadtree: Quadtree::empty(),
+            keys: &[],
+            vals: &[],
+            roles: &[],
+            refs: &[],
+            types: &[],
+        };
+        for t in IterTags::new(data) {
+            match t {
+                PbfTag::Value(1, i) => res.id = i as i64,
+                PbfTag::Data(2, d) => res.keys = d,
+                PbfTag::Data(3, d) => res.vals = d,
+                PbfTag::Data(8, d) => res.roles = d,
+                PbfTag::Data(9, d) => res.refs = d,
+                PbfTag::Data(10, d) => res.types = d,
+                PbfTag::Value(20, q) => res.quadtree = Quadtree::new(un_zig_zag(q)),
+                _ => {}
+            }
+        }
+        res
+    }
+
+    pub fn tags(&self) -> TagsIter<'a> {
+        TagsIter::new(self.block, self.keys, self.vals)
+    }
+
+    pub fn get_tag(&self, key: &str) -> Result<Option<&'a str>> {
+        find_tag(self.tags(), key)
+    }
+
+    /// Iterates over the (role, type, ref) of each member. Returns an error
+    /// if a role isn't in the block's string table or a type is unknown.
+    pub fn members(&self) -> impl Iterator<Item = Result<(&'a str, ElementType, i64)>> + 'a {
+        let block = self.block;
+        Packed::new(self.roles)
+            .zip(Packed::new(self.types))
+            .zip(DeltaPacked::new(self.refs))
+            .map(move |((r, t), i)| -> Result<(&'a str, ElementType, i64)> {
+                Ok((block.string(r)?, ElementType::try_from_int(t)?, i))
+            })
+    }
+
+    /// Decodes this relation into an owned [`Relation`], without info.
+    pub fn to_relation(&self) -> Result<Relation> {
+        let mut r = Relation::new(self.id, self.changetype);
+        r.quadtree = self.quadtree;
+        r.tags = to_tags(self.tags())?;
+        for m in self.members() {
+            let (role, t, i) = m?;
+            r.members.push(Member::new(role.to_string(), t, i));
+        }
+        Ok(r)
+    }
+}
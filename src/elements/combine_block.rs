@@ -1,4 +1,4 @@
-use crate::elements::{Changetype, Node, PrimitiveBlock, Relation, Way};
+use crate::elements::{Changetype, Info, Node, PrimitiveBlock, Relation, SetCommon, Way, WithInfo};
 use crate::elements::{MinimalBlock, MinimalNode, MinimalRelation, MinimalWay};
 
 use std::cmp::Ordering;
@@ -319,6 +319,109 @@ fn apply_change_clone<T: WithIdAndChangetype + Ord + Clone>(left: &[T], right: &
     res
 }
 
+//full-history blocks may have several versions of each id: objects are
+//matched on (id, version) rather than just id. Remove entries (objects
+//moved to another tile) are clones of the moved version, which may already
+//be in the block, so they are kept alongside it rather than replacing it
+
+fn history_key<T: WithIdAndChangetype + WithInfo>(o: &T) -> (i64, i64) {
+    match o.get_info() {
+        Some(info) => (o.get_id(), info.version),
+        None => (o.get_id(), 0),
+    }
+}
+
+fn combine_history<T: WithIdAndChangetype + WithInfo>(left: Vec<T>, right: Vec<T>) -> Vec<T> {
+    let mut res = Vec::with_capacity(left.len() + right.len());
+
+    let mut left_iter = left.into_iter().peekable();
+    let mut right_iter = right.into_iter().peekable();
+
+    loop {
+        let ord = match (left_iter.peek(), right_iter.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(l), Some(r)) => history_key(l).cmp(&history_key(r)),
+        };
+        match ord {
+            Ordering::Less => res.push(left_iter.next().unwrap()),
+            Ordering::Equal => {
+                let l = left_iter.next().unwrap();
+                let r = right_iter.next().unwrap();
+                if l.get_changetype() == Changetype::Remove || r.get_changetype() == Changetype::Remove {
+                    res.push(l);
+                }
+                res.push(r);
+            }
+            Ordering::Greater => res.push(right_iter.next().unwrap()),
+        }
+    }
+    res
+}
+
+fn check_changetype_history<T: WithIdAndChangetype + WithInfo + SetCommon>(o: &mut T) -> bool {
+    match o.get_changetype() {
+        Changetype::Normal => {
+            return true;
+        }
+        //a Remove leaves a deleted version in the old tile, so that
+        //history_at stops returning the object from the old tile
+        Changetype::Delete | Changetype::Remove => {
+            let mut info = match o.get_info() {
+                Some(info) => info.clone(),
+                None => Info::new(),
+            };
+            info.visible = false;
+            o.set_info(info);
+        }
+        _ => {}
+    }
+    o.set_changetype(Changetype::Normal);
+    true
+}
+
+fn apply_change_history<T: WithIdAndChangetype + WithInfo + SetCommon>(
+    left: Vec<T>,
+    right: Vec<T>,
+) -> Vec<T> {
+    let mut res = combine_history(left, right);
+    res.retain_mut(|o| check_changetype_history(o));
+    res
+}
+
+fn history_at<T: WithIdAndChangetype + WithInfo>(objs: Vec<T>, timestamp: i64) -> Vec<T> {
+    let mut res = Vec::new();
+    let mut curr: Option<T> = None;
+    for o in objs {
+        if curr.as_ref().map_or(false, |c| c.get_id() != o.get_id()) {
+            check_visible_add(&mut res, &mut curr);
+        }
+        let ts = match o.get_info() {
+            Some(info) => info.timestamp,
+            None => 0,
+        };
+        if ts <= timestamp {
+            curr = Some(o);
+        }
+    }
+    if curr.is_some() {
+        check_visible_add(&mut res, &mut curr);
+    }
+    res
+}
+
+fn check_visible_add<T: WithInfo>(res: &mut Vec<T>, obj: &mut Option<T>) {
+    let r = obj.take().unwrap();
+    let visible = match r.get_info() {
+        Some(info) => info.visible,
+        None => true,
+    };
+    if visible {
+        res.push(r);
+    }
+}
+
 pub fn combine_block_primitive(
     mut left: PrimitiveBlock,
     mut right: PrimitiveBlock,
@@ -460,3 +563,102 @@ pub fn merge_changes_minimal(orig: MinimalBlock, mut changes: Vec<MinimalBlock>)
 
     apply_change_minimal(orig, merged_change)
 }
+
+/// Like [`combine_block_primitive`], but for full-history blocks: all
+/// versions from both blocks are kept, with `right` replacing any identical
+/// (id, version) from `left`.
+pub fn combine_block_primitive_history(
+    mut left: PrimitiveBlock,
+    mut right: PrimitiveBlock,
+) -> PrimitiveBlock {
+    left.nodes = combine_history(
+        std::mem::take(&mut left.nodes),
+        std::mem::take(&mut right.nodes),
+    );
+    left.ways = combine_history(
+        std::mem::take(&mut left.ways),
+        std::mem::take(&mut right.ways),
+    );
+    left.relations = combine_history(
+        std::mem::take(&mut left.relations),
+        std::mem::take(&mut right.relations),
+    );
+    left.end_date = right.end_date;
+    left
+}
+
+/// Adds the versions in the change block `right` to the full-history block
+/// `left`. Deleted objects, and objects moved to another tile, are kept as
+/// versions with `Info::visible` set to false.
+pub fn apply_change_primitive_history(
+    mut left: PrimitiveBlock,
+    mut right: PrimitiveBlock,
+) -> PrimitiveBlock {
+    left.nodes = apply_change_history(
+        std::mem::take(&mut left.nodes),
+        std::mem::take(&mut right.nodes),
+    );
+    left.ways = apply_change_history(
+        std::mem::take(&mut left.ways),
+        std::mem::take(&mut right.ways),
+    );
+    left.relations = apply_change_history(
+        std::mem::take(&mut left.relations),
+        std::mem::take(&mut right.relations),
+    );
+    left.end_date = right.end_date;
+    left
+}
+
+/// Returns the state of the full-history block `pb` at `timestamp`: the last
+/// version of each object at or before `timestamp`, unless that version is
+/// deleted.
+pub fn history_at_primitive(mut pb: PrimitiveBlock, timestamp: i64) -> PrimitiveBlock {
+    pb.nodes = history_at(std::mem::take(&mut pb.nodes), timestamp);
+    pb.ways = history_at(std::mem::take(&mut pb.ways), timestamp);
+    pb.relations = history_at(std::mem::take(&mut pb.relations), timestamp);
+    pb.end_date = timestamp;
+    pb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elements::Quadtree;
+
+    fn node(id: i64, version: i64, timestamp: i64, changetype: Changetype) -> Node {
+        let mut n = Node::new(id, changetype);
+        let mut info = Info::new();
+        info.version = version;
+        info.timestamp = timestamp;
+        n.info = Some(info);
+        n
+    }
+
+    #[test]
+    fn history_tile_move() {
+        let mut old_tile = PrimitiveBlock::new(0, 0);
+        old_tile.nodes.push(node(1, 1, 100, Changetype::Normal));
+        old_tile.nodes.push(node(1, 2, 200, Changetype::Normal));
+        old_tile.nodes.push(node(2, 1, 100, Changetype::Normal));
+
+        //node 1 moves to another tile in version 3: the old tile gets a
+        //Remove, node 2 moves without a new version
+        let mut change = PrimitiveBlock::new(0, 0);
+        let mut n1 = node(1, 3, 300, Changetype::Remove);
+        n1.quadtree = Quadtree::new(0);
+        change.nodes.push(n1);
+        change.nodes.push(node(2, 1, 100, Changetype::Remove));
+
+        let res = apply_change_primitive_history(old_tile, change);
+        assert_eq!(res.nodes.len(), 5);
+        assert!(res.nodes.iter().all(|n| n.changetype == Changetype::Normal));
+
+        let at = history_at(res.nodes.clone(), 250);
+        assert_eq!(at.len(), 1);
+        assert_eq!(at[0].info.as_ref().unwrap().version, 2);
+
+        let at = history_at(res.nodes, 350);
+        assert!(at.is_empty());
+    }
+}
@@ -11,10 +11,11 @@ use crate::elements::traits::*;
 use core::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
 
 pub fn read_common<'a, T: SetCommon + WithId>(
     obj: &mut T,
-    strings: &Vec<String>,
+    strings: &Vec<Arc<str>>,
     data: &'a [u8],
     minimal: bool,
 ) -> Result<Vec<PbfTag<'a>>> {
@@ -182,10 +183,10 @@ impl PackStringTable {
         PackStringTable { strings: strings }
     }
 
-    pub fn call(&mut self, s: &String) -> u64 {
+    pub fn call(&mut self, s: &str) -> u64 {
         if !self.strings.contains_key(s) {
             let x = self.strings.len() as u64;
-            self.strings.insert(s.clone(), x);
+            self.strings.insert(s.to_string(), x);
             x
         } else {
             *self.strings.get(s).unwrap()
@@ -2,10 +2,10 @@
 use std::io::Write;
 use crate::utils::{Error, Result};
 use std::cmp::{Ordering};
-use crate::elements::{Element,Node,Way,Relation,Tag,Info,PrimitiveBlock};
-/*use crate::geometry::{
+use crate::elements::{Element,ElementType,Node,Way,Relation,Tag,Info,PrimitiveBlock,WithId,WithType};
+use crate::geometry::{
     ComplicatedPolygonGeometry, LinestringGeometry, PointGeometry, SimplePolygonGeometry,
-};*/
+};
 use std::fs::File;
 use crate::message;
 
@@ -22,23 +22,6 @@ pub enum ElementCompare {
 }
 
 
-/*
-fn pointgeometry_compare(_left: PointGeometry,_right: PointGeometry) -> Result<ElementCompare> {
-    Err(Error::new(ErrorKind::Other, "not impl for geometry types"))
-}
-
-fn linestringgeometry_compare(_left: LinestringGeometry,_right: LinestringGeometry) -> Result<ElementCompare> {
-    Err(Error::new(ErrorKind::Other, "not impl for geometry types"))
-}
-
-fn simplepolygongeometry_compare(_left: SimplePolygonGeometry,_right: SimplePolygonGeometry) -> Result<ElementCompare> {
-    Err(Error::new(ErrorKind::Other, "not impl for geometry types"))
-}
-
-fn compliatedpolygongeometry_compare(_left: ComplicatedPolygonGeometry,_right: ComplicatedPolygonGeometry) -> Result<ElementCompare> {
-    Err(Error::new(ErrorKind::Other, "not impl for geometry types"))
-}
-*/
 fn different_info(left: &Option<Info>, right: &Option<Info>) -> bool {
     match (left,right) {
         (None,None) => false,
@@ -62,6 +45,83 @@ fn tags_different(left: &Vec<Tag>, right: &Vec<Tag>) -> bool {
     return false;
 }   
 
+fn changed_user_name(left: Option<Info>, right: Option<Info>) -> ElementCompare {
+    match (left, right) {
+        (Some(li),Some(ri)) => {
+            if li.user != ri.user {
+                ElementCompare::ChangedUserName(li.user,ri.user)
+            } else {
+                ElementCompare::Same
+            }
+        },
+        (_,_) => ElementCompare::Same
+    }
+}
+
+fn pointgeometry_compare(left: PointGeometry, right: PointGeometry) -> Result<ElementCompare> {
+    if left.id != right.id {
+        Err(Error::InvalidDataError(format!("different elements")))
+    } else if different_info(&left.info, &right.info) {
+        Ok(ElementCompare::DifferentInfo(Element::PointGeometry(left), Element::PointGeometry(right)))
+    } else if tags_different(&left.tags, &right.tags) {
+        Ok(ElementCompare::DifferentTags(Element::PointGeometry(left), Element::PointGeometry(right)))
+    } else if left.lonlat != right.lonlat {
+        Ok(ElementCompare::DifferentData(Element::PointGeometry(left), Element::PointGeometry(right)))
+    } else if left.quadtree != right.quadtree {
+        Ok(ElementCompare::DifferentQuadtree(Element::PointGeometry(left), Element::PointGeometry(right)))
+    } else {
+        Ok(changed_user_name(left.info, right.info))
+    }
+}
+
+fn linestringgeometry_compare(left: LinestringGeometry, right: LinestringGeometry) -> Result<ElementCompare> {
+    if left.id != right.id {
+        Err(Error::InvalidDataError(format!("different elements")))
+    } else if different_info(&left.info, &right.info) {
+        Ok(ElementCompare::DifferentInfo(Element::LinestringGeometry(left), Element::LinestringGeometry(right)))
+    } else if tags_different(&left.tags, &right.tags) {
+        Ok(ElementCompare::DifferentTags(Element::LinestringGeometry(left), Element::LinestringGeometry(right)))
+    } else if left.refs != right.refs || left.lonlats != right.lonlats {
+        Ok(ElementCompare::DifferentData(Element::LinestringGeometry(left), Element::LinestringGeometry(right)))
+    } else if left.quadtree != right.quadtree {
+        Ok(ElementCompare::DifferentQuadtree(Element::LinestringGeometry(left), Element::LinestringGeometry(right)))
+    } else {
+        Ok(changed_user_name(left.info, right.info))
+    }
+}
+
+fn simplepolygongeometry_compare(left: SimplePolygonGeometry, right: SimplePolygonGeometry) -> Result<ElementCompare> {
+    if left.id != right.id {
+        Err(Error::InvalidDataError(format!("different elements")))
+    } else if different_info(&left.info, &right.info) {
+        Ok(ElementCompare::DifferentInfo(Element::SimplePolygonGeometry(left), Element::SimplePolygonGeometry(right)))
+    } else if tags_different(&left.tags, &right.tags) {
+        Ok(ElementCompare::DifferentTags(Element::SimplePolygonGeometry(left), Element::SimplePolygonGeometry(right)))
+    } else if left.refs != right.refs || left.lonlats != right.lonlats {
+        Ok(ElementCompare::DifferentData(Element::SimplePolygonGeometry(left), Element::SimplePolygonGeometry(right)))
+    } else if left.quadtree != right.quadtree {
+        Ok(ElementCompare::DifferentQuadtree(Element::SimplePolygonGeometry(left), Element::SimplePolygonGeometry(right)))
+    } else {
+        Ok(changed_user_name(left.info, right.info))
+    }
+}
+
+fn complicatedpolygongeometry_compare(left: ComplicatedPolygonGeometry, right: ComplicatedPolygonGeometry) -> Result<ElementCompare> {
+    if left.id != right.id {
+        Err(Error::InvalidDataError(format!("different elements")))
+    } else if different_info(&left.info, &right.info) {
+        Ok(ElementCompare::DifferentInfo(Element::ComplicatedPolygonGeometry(left), Element::ComplicatedPolygonGeometry(right)))
+    } else if tags_different(&left.tags, &right.tags) {
+        Ok(ElementCompare::DifferentTags(Element::ComplicatedPolygonGeometry(left), Element::ComplicatedPolygonGeometry(right)))
+    } else if left.parts != right.parts {
+        Ok(ElementCompare::DifferentData(Element::ComplicatedPolygonGeometry(left), Element::ComplicatedPolygonGeometry(right)))
+    } else if left.quadtree != right.quadtree {
+        Ok(ElementCompare::DifferentQuadtree(Element::ComplicatedPolygonGeometry(left), Element::ComplicatedPolygonGeometry(right)))
+    } else {
+        Ok(changed_user_name(left.info, right.info))
+    }
+}
+
 fn node_compare(left: Node, right: Node) -> Result<ElementCompare> {
     
     if left.id != right.id {
@@ -153,10 +213,10 @@ pub fn element_compare(left: Option<Element>, right: Option<Element>) -> Result<
                 (Element::Node(left), Element::Node(right)) => node_compare(left,right),
                 (Element::Way(left), Element::Way(right)) => way_compare(left,right),
                 (Element::Relation(left), Element::Relation(right)) => relation_compare(left,right),
-               /* (Element::PointGeometry(left), Element::PointGeometry(right)) => pointgeometry_compare(left,right),
+                (Element::PointGeometry(left), Element::PointGeometry(right)) => pointgeometry_compare(left,right),
                 (Element::LinestringGeometry(left), Element::LinestringGeometry(right)) => linestringgeometry_compare(left,right),
                 (Element::SimplePolygonGeometry(left), Element::SimplePolygonGeometry(right)) => simplepolygongeometry_compare(left,right),
-                (Element::ComplicatedPolygonGeometry(left), Element::ComplicatedPolygonGeometry(right)) => compliatedpolygongeometry_compare(left,right),*/
+                (Element::ComplicatedPolygonGeometry(left), Element::ComplicatedPolygonGeometry(right)) => complicatedpolygongeometry_compare(left,right),
                 (_, _) => Err(Error::InvalidDataError(format!("different element types!!")))
             }
         }
@@ -199,22 +259,59 @@ fn check_left_right(left_ele: &mut Option<Element>, right_ele: &mut Option<Eleme
     
 }
 
-pub fn compare_element_iters<T: Iterator<Item=Element>>(mut left_iter: T, mut right_iter: T, max_result_len: usize) -> Result<(Vec<ElementCompare>,HashSet<(String,String)>,usize)> {
-    
-    let mut left_ele = left_iter.next();
-    let mut right_ele = right_iter.next();
+//returns the next element of iter, checking that it is after the previous one
+fn next_in_order<T: Iterator<Item=Element>>(iter: &mut T, prev: &mut Option<(ElementType, i64)>, side: &str) -> Result<Option<Element>> {
+    match iter.next() {
+        None => Ok(None),
+        Some(e) => {
+            let key = (e.get_type(), e.get_id());
+            if let Some(p) = prev {
+                if *p >= key {
+                    return Err(Error::InvalidDataError(format!(
+                        "{} input not in id order: {:?} {} after {:?} {}",
+                        side, key.0, key.1, p.0, p.1
+                    )));
+                }
+            }
+            *prev = Some(key);
+            Ok(Some(e))
+        }
+    }
+}
+
+//calls call with the comparison of each element of left_iter and right_iter,
+//including unchanged elements. Returns an error if either iterator is not in
+//element order.
+fn compare_element_iters_all<T: Iterator<Item=Element>, F: FnMut(ElementCompare) -> Result<()>>(mut left_iter: T, mut right_iter: T, mut call: F) -> Result<()> {
     
-    let mut res: Vec<ElementCompare> = Vec::new();
-    
-    let mut changed_users = HashSet::new();
-    let mut count=0;
+    let mut left_prev = None;
+    let mut right_prev = None;
+    let mut left_ele = next_in_order(&mut left_iter, &mut left_prev, "left")?;
+    let mut right_ele = next_in_order(&mut right_iter, &mut right_prev, "right")?;
     
     loop {
         if left_ele.is_none() && right_ele.is_none() {
             break;
         }
         
-        match check_left_right(&mut left_ele, &mut right_ele)? {
+        call(check_left_right(&mut left_ele, &mut right_ele)?)?;
+        
+        if left_ele.is_none() { left_ele = next_in_order(&mut left_iter, &mut left_prev, "left")?; }
+        if right_ele.is_none() { right_ele = next_in_order(&mut right_iter, &mut right_prev, "right")?; }
+        
+    }
+    Ok(())
+}
+
+pub fn compare_element_iters<T: Iterator<Item=Element>>(left_iter: T, right_iter: T, max_result_len: usize) -> Result<(Vec<ElementCompare>,HashSet<(String,String)>,usize)> {
+    
+    let mut res: Vec<ElementCompare> = Vec::new();
+    
+    let mut changed_users = HashSet::new();
+    let mut count=0;
+    
+    compare_element_iters_all(left_iter, right_iter, |c| {
+        match c {
             ElementCompare::Same => {},
             ElementCompare::ChangedUserName(ln,rn) => { changed_users.insert((ln,rn)); },
             p => {
@@ -228,52 +325,58 @@ pub fn compare_element_iters<T: Iterator<Item=Element>>(mut left_iter: T, mut ri
                 }
                 count+=1;
             }
-        }   
-        
-        if left_ele.is_none() { left_ele = left_iter.next(); }
-        if right_ele.is_none() { right_ele = right_iter.next(); }
-        
-    }
+        }
+        Ok(())
+    })?;
     
     Ok((res, changed_users, count))
                     
 }
                 
-pub fn compare_element_iters_json<T: Iterator<Item=Element>>(mut left_iter: T, mut right_iter: T, outfn: &str) -> Result<(HashSet<(String,String)>,usize)> {
+pub fn compare_element_iters_json<T: Iterator<Item=Element>>(left_iter: T, right_iter: T, outfn: &str) -> Result<(HashSet<(String,String)>,usize)> {
     
     let mut outf = File::create(outfn)?;
     
-    let mut left_ele = left_iter.next();
-    let mut right_ele = right_iter.next();
     let mut changed_users = HashSet::new();
     let mut count=0;
     
-    loop {
-        if left_ele.is_none() && right_ele.is_none() {
-            break;
-        }
-        
-        match check_left_right(&mut left_ele, &mut right_ele)? {
+    compare_element_iters_all(left_iter, right_iter, |c| {
+        match c {
             ElementCompare::Same => {},
             ElementCompare::ChangedUserName(ln,rn) => { changed_users.insert((ln,rn)); },
             p => {
-                let mut x = serde_json::to_vec(&p).expect("?");
+                let mut x = serde_json::to_vec(&p)?;
                 x.push(10); //newline character
-                outf.write_all(&x).expect("?");
+                outf.write_all(&x)?;
                 
                 count+=1;
             }
-        }   
-        
-        if left_ele.is_none() { left_ele = left_iter.next(); }
-        if right_ele.is_none() { right_ele = right_iter.next(); }
-        
-    }
+        }
+        Ok(())
+    })?;
     
     Ok((changed_users, count))
                     
 }
     
+/// Calls call with each difference between left_iter and right_iter, returning
+/// the number of differences found. Both iterators must be in element order.
+pub fn compare_element_iters_call<T: Iterator<Item=Element>, F: FnMut(ElementCompare) -> Result<()>>(left_iter: T, right_iter: T, mut call: F) -> Result<usize> {
+    
+    let mut count=0;
+    compare_element_iters_all(left_iter, right_iter, |c| {
+        match c {
+            ElementCompare::Same => Ok(()),
+            p => {
+                count+=1;
+                call(p)
+            }
+        }
+    })?;
+    
+    Ok(count)
+}
+    
 pub fn compare_primitiveblock(left: PrimitiveBlock, right: PrimitiveBlock) -> Result<(Vec<ElementCompare>,HashSet<(String,String)>,usize)> {
     let mrs=100000000000;
     let left_iter = left.into_iter();
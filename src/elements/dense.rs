@@ -11,6 +11,7 @@ use simple_protocolbuffers::{
 };
 
 use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
 
 use crate::elements::idset::IdSet;
 
@@ -26,7 +27,7 @@ pub struct Dense {}
 impl Dense {
     pub fn read(
         changetype: Changetype,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         data: &[u8],
         minimal: bool,
         idset: Option<&dyn IdSet>,
@@ -43,6 +44,7 @@ impl Dense {
         let mut ts = Vec::new();
         let mut ui = Vec::new();
         let mut us = Vec::new();
+        let mut vis = Vec::new();
 
         for x in IterTags::new(&data) {
             match x {
@@ -56,6 +58,7 @@ impl Dense {
                                 PbfTag::Data(3, d) => cs = read_delta_packed_int(&d),
                                 PbfTag::Data(4, d) => ui = read_delta_packed_int(&d),
                                 PbfTag::Data(5, d) => us = read_delta_packed_int(&d),
+                                PbfTag::Data(6, d) => vis = read_packed_int(&d),
                                 _ => {
                                     return Err(Error::new(
                                         ErrorKind::Other,
@@ -136,6 +139,12 @@ impl Dense {
                     format!("dense nodes: {} ids but {} users", ids.len(), us.len()),
                 ));
             }
+            if vis.len() > 0 && vis.len() != ids.len() {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("dense nodes: {} ids but {} visibles", ids.len(), vis.len()),
+                ));
+            }
         }
 
         let mut kvs_idx = 0;
@@ -166,7 +175,10 @@ impl Dense {
                         info.user_id = ui[i];
                     }
                     if us.len() > 0 {
-                        info.user = strings[us[i] as usize].clone();
+                        info.user = strings[us[i] as usize].to_string();
+                    }
+                    if vis.len() > 0 {
+                        info.visible = vis[i] != 0;
                     }
                     nd.info = Some(info);
 
@@ -201,6 +213,7 @@ impl Dense {
         let mut csv = Vec::with_capacity(feats.len());
         let mut uiv = Vec::with_capacity(feats.len());
         let mut usv = Vec::with_capacity(feats.len());
+        let mut visv = Vec::with_capacity(feats.len());
         for n in feats {
             match &n.info {
                 Some(info) => {
@@ -209,6 +222,7 @@ impl Dense {
                     csv.push(info.changeset);
                     uiv.push(info.user_id);
                     usv.push(pack_strings.call(&info.user) as i64);
+                    visv.push(if info.visible { 1 } else { 0 });
                 }
                 None => {
                     vsv.push(0);
@@ -216,6 +230,7 @@ impl Dense {
                     csv.push(0);
                     uiv.push(0);
                     usv.push(0);
+                    visv.push(1);
                 }
             }
         }
@@ -224,6 +239,12 @@ impl Dense {
         let cs = pack_delta_int_ref(csv.iter());
         let ui = pack_delta_int_ref(uiv.iter());
         let us = pack_delta_int_ref(usv.iter());
+        //only needed for full-history files with deleted versions
+        let vis = if visv.iter().any(|v| *v == 0) {
+            Some(pack_int_ref(visv.iter()))
+        } else {
+            None
+        };
 
         /*let vs = pack_int(feats.iter().map( |n| { n.info.as_ref().unwrap_or_else(default_info).version as u64 }));
         let ts = pack_delta_int(feats.iter().map( |n| { n.info.as_ref().unwrap_or_else(default_info).timestamp }));
@@ -243,6 +264,10 @@ impl Dense {
         pack_data(&mut res, 3, &cs);
         pack_data(&mut res, 4, &ui);
         pack_data(&mut res, 5, &us);
+        match &vis {
+            Some(vis) => pack_data(&mut res, 6, vis),
+            None => {}
+        }
         Ok(res)
     }
 
@@ -3,6 +3,7 @@ use crate::elements::common;
 use simple_protocolbuffers::{pack_value, IterTags, PbfTag};
 
 use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize)]
 pub struct Info {
@@ -11,6 +12,9 @@ pub struct Info {
     pub timestamp: i64,
     pub user_id: i64,
     pub user: String,
+    /// False for deleted versions in full-history files
+    /// (the HistoricalInformation feature).
+    pub visible: bool,
 }
 
 impl Info {
@@ -21,10 +25,11 @@ impl Info {
             timestamp: 0,
             user_id: 0,
             user: String::from(""),
+            visible: true,
         }
     }
 
-    pub fn read(strings: &Vec<String>, data: &[u8]) -> Result<Info> {
+    pub fn read(strings: &Vec<Arc<str>>, data: &[u8]) -> Result<Info> {
         let mut res = Info::new();
         for x in IterTags::new(&data) {
             match x {
@@ -36,8 +41,9 @@ impl Info {
                     if v as usize >= strings.len() {
                         return Err(Error::new(ErrorKind::Other, "info user idx out of range"));
                     }
-                    res.user = strings[v as usize].clone();
+                    res.user = strings[v as usize].to_string();
                 }
+                PbfTag::Value(6, v) => res.visible = v != 0,
                 _ => {
                     return Err(Error::new(
                         ErrorKind::Other,
@@ -69,6 +75,9 @@ impl Info {
         pack_value(&mut res, 3, self.changeset as u64);
         pack_value(&mut res, 4, self.user_id as u64);
         pack_value(&mut res, 5, pack_strings.call(&self.user));
+        if !self.visible {
+            pack_value(&mut res, 6, 0);
+        }
         Ok(res)
     }
 }
@@ -98,6 +98,8 @@ pub struct MinimalWay {
     pub timestamp: i64,
     pub quadtree: Quadtree,
     pub refs_data: Vec<u8>,
+    pub lats_data: Vec<u8>,
+    pub lons_data: Vec<u8>,
 }
 impl MinimalWay {
     pub fn new() -> MinimalWay {
@@ -108,6 +110,8 @@ impl MinimalWay {
             timestamp: 0,
             quadtree: Quadtree::empty(),
             refs_data: Vec::new(),
+            lats_data: Vec::new(),
+            lons_data: Vec::new(),
         }
     }
 }
@@ -436,6 +440,8 @@ impl MinimalBlock {
                     }
                 }
                 PbfTag::Data(8, d) => wy.refs_data = d.to_vec(),
+                PbfTag::Data(9, d) => wy.lats_data = d.to_vec(),
+                PbfTag::Data(10, d) => wy.lons_data = d.to_vec(),
                 PbfTag::Value(20, i) => wy.quadtree = Quadtree::new(un_zig_zag(i)),
                 _ => {}
             }
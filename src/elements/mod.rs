@@ -1,3 +1,4 @@
+mod block_view;
 mod combine_block;
 mod common;
 mod compareelements;
@@ -16,8 +17,10 @@ mod way;
 pub use combine_block::{
     apply_change_minimal, apply_change_primitive, combine_block_minimal, combine_block_primitive,
     merge_changes_minimal, merge_changes_primitive, combine_block_primitive_clone, apply_change_primitive_clone,
+    apply_change_primitive_history, combine_block_primitive_history, history_at_primitive,
 };
 
+pub use block_view::{BlockView, NodeView, RelationView, TagsIter, WayView};
 pub use idset::{IdSet, IdSetAll, IdSetBool, IdSetSet};
 pub use minimal_block::{MinimalBlock, MinimalNode, MinimalRelation, MinimalWay, QuadtreeBlock};
 pub use primitive_block::{Block, Info, Member, Node, PrimitiveBlock, Relation, Tag, Way, read_stringtable};
@@ -25,7 +28,7 @@ pub use primitive_block::{Block, Info, Member, Node, PrimitiveBlock, Relation, T
 pub use common::{pack_head, PackStringTable, read_common};
 pub use quadtree::{
     coordinate_as_float, coordinate_as_integer, latitude_mercator, latitude_un_mercator, Bbox,
-    Quadtree, EARTH_WIDTH,zoom
+    Quadtree, QuadtreeCovering, TileOverlap, EARTH_WIDTH,zoom
 };
 pub use traits::*;
-pub use compareelements::{compare_element_iters,compare_element_iters_json,compare_primitiveblock,ElementCompare};
+pub use compareelements::{compare_element_iters,compare_element_iters_call,compare_element_iters_json,compare_primitiveblock,ElementCompare};
@@ -8,6 +8,7 @@ use crate::elements::traits::*;
 
 use core::cmp::Ordering;
 use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
 
 
 
@@ -43,7 +44,7 @@ impl Node {
 
     pub fn read(
         changetype: Changetype,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         data: &[u8],
         minimal: bool,
     ) -> Result<Node> {
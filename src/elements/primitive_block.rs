@@ -17,6 +17,7 @@ use crate::elements::dense::Dense;
 use crate::elements::traits::{Changetype, Element, ElementType, WithChangetype,WithQuadtree};
 
 use crate::utils::{Error, Result};
+use std::sync::Arc;
 
 pub trait Block {
     type Element: WithQuadtree;
@@ -58,12 +59,12 @@ impl std::fmt::Debug for PrimitiveBlock {
 }
 
 
-pub fn read_stringtable(data: &[u8]) -> Result<Vec<String>> {
+pub fn read_stringtable(data: &[u8]) -> Result<Vec<Arc<str>>> {
     let mut res = Vec::new();
     for x in IterTags::new(&data) {
         match x {
             PbfTag::Data(1, d) => {
-                let s = std::str::from_utf8(d).unwrap().to_string();
+                let s: Arc<str> = Arc::from(std::str::from_utf8(d).unwrap());
                 res.push(s);
             }
 
@@ -149,6 +150,7 @@ impl Block for PrimitiveBlock {
                 self.relations.push(r);
                 Ok(())
             }
+            _ => Err(Error::InvalidDataError(format!("can't add geometry to PrimitiveBlock"))),
         }
     }
     fn sort(&mut self) {
@@ -339,7 +341,7 @@ impl PrimitiveBlock {
 
     fn read_group(
         &mut self,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         changetype: Changetype,
         data: &[u8],
         minimal: bool,
@@ -369,7 +371,7 @@ impl PrimitiveBlock {
 
     fn read_node(
         &mut self,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         changetype: Changetype,
         data: &[u8],
         minimal: bool,
@@ -389,7 +391,7 @@ impl PrimitiveBlock {
     }
     fn read_way(
         &mut self,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         changetype: Changetype,
         data: &[u8],
         minimal: bool,
@@ -409,7 +411,7 @@ impl PrimitiveBlock {
     }
     fn read_relation(
         &mut self,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         changetype: Changetype,
         data: &[u8],
         minimal: bool,
@@ -429,7 +431,7 @@ impl PrimitiveBlock {
     }
     fn read_dense(
         &mut self,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         changetype: Changetype,
         data: &[u8],
         minimal: bool,
@@ -445,6 +447,13 @@ impl PrimitiveBlock {
     }
 
     pub fn pack(&self, include_qts: bool, as_change: bool) -> Result<Vec<u8>> {
+        self.pack_with_locations(include_qts, as_change, false)
+    }
+
+    /// As [`PrimitiveBlock::pack`], also writing way node locations if
+    /// include_locations is set. Use this only for files with the
+    /// LocationsOnWays feature.
+    pub fn pack_with_locations(&self, include_qts: bool, as_change: bool, include_locations: bool) -> Result<Vec<u8>> {
         let mut pack_strings = Box::new(PackStringTable::new());
 
         let mut groups = Vec::new();
@@ -452,7 +461,7 @@ impl PrimitiveBlock {
             groups.extend(self.pack_nodes(&mut pack_strings, include_qts, as_change)?);
         }
         if self.ways.len() > 0 {
-            groups.extend(self.pack_ways(&mut pack_strings, include_qts, as_change)?);
+            groups.extend(self.pack_ways(&mut pack_strings, include_qts, as_change, include_locations)?);
         }
         if self.relations.len() > 0 {
             groups.extend(self.pack_relations(&mut pack_strings, include_qts, as_change)?);
@@ -522,13 +531,14 @@ impl PrimitiveBlock {
         prep_strings: &mut Box<PackStringTable>,
         include_qts: bool,
         as_change: bool,
+        include_locations: bool,
     ) -> Result<Vec<Vec<u8>>> {
         if as_change {
             let mut pp = Vec::new();
             for (a, b, c) in find_splits(&self.ways) {
                 let mut res = Vec::new();
                 for w in &self.ways[b..c] {
-                    pack_data(&mut res, 3, &w.pack(prep_strings, include_qts)?);
+                    pack_data(&mut res, 3, &w.pack(prep_strings, include_qts, include_locations)?);
                 }
                 pack_value(&mut res, 10, a.as_int());
                 pp.push(res);
@@ -538,7 +548,7 @@ impl PrimitiveBlock {
 
         let mut res = Vec::new();
         for w in &self.ways {
-            pack_data(&mut res, 3, &w.pack(prep_strings, include_qts)?);
+            pack_data(&mut res, 3, &w.pack(prep_strings, include_qts, include_locations)?);
         }
         return Ok(vec![res]);
     }
@@ -1,4 +1,5 @@
 use simple_protocolbuffers::{IterTags, PbfTag};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io::{Error, ErrorKind, Result};
 
@@ -149,6 +150,18 @@ impl Bbox {
             && self.maxlat >= 900000000
     }
 
+    fn check(self) -> Result<Bbox> {
+        if self.minlat > self.maxlat {
+            return Err(Error::new(ErrorKind::Other, format!("minlat greater than maxlat in {}", self)));
+        }
+        if self.minlon < -1800000000 || self.minlon > 1800000000 || self.maxlon < -1800000000 || self.maxlon > 1800000000 {
+            return Err(Error::new(ErrorKind::Other, format!("longitude out of range in {}", self)));
+        }
+        Ok(self)
+    }
+
+    /// Reads `minlon,minlat,maxlon,maxlat`. A minlon greater than maxlon
+    /// gives a bbox crossing the antimeridian.
     pub fn from_str(fstr: &str) -> Result<Bbox> {
         let vv: Vec<&str> = fstr.split(",").collect();
         if vv.len() != 4 {
@@ -162,7 +175,7 @@ impl Bbox {
             }
             //vvi.push(v.parse().unwrap());
         }
-        Ok(Bbox::new(vvi[0], vvi[1], vvi[2], vvi[3]))
+        Bbox::new(vvi[0], vvi[1], vvi[2], vvi[3]).check()
     }
     
     pub fn from_str_alt(instr: &str) -> Result<Bbox> {
@@ -173,7 +186,7 @@ impl Bbox {
             let maxlon = i32_from_str(&caps[3])?;
             let maxlat = i32_from_str(&caps[4])?;
             println!("Bbox::new({},{},{},{})",minlon,minlat,maxlon,maxlat);
-            return Ok(Bbox::new(minlon,minlat,maxlon,maxlat));
+            return Bbox::new(minlon,minlat,maxlon,maxlat).check();
         }
         
         let four_floats = Regex::new(r"(\-?[0-9]*\.?[0-9]*),(\-?[0-9]*\.?[0-9]*),(\-?[0-9]*\.?[0-9]*),(\-?[0-9]*\.?[0-9]*)").or(Err(Error::new(ErrorKind::Other,"??")))?;
@@ -183,7 +196,7 @@ impl Bbox {
             let maxlon = coordinate_as_integer(f64_from_str(&caps[3])?);
             let maxlat = coordinate_as_integer(f64_from_str(&caps[4])?);
             println!("Bbox::new({},{},{},{})",minlon,minlat,maxlon,maxlat);
-            return Ok(Bbox::new(minlon,minlat,maxlon,maxlat));
+            return Bbox::new(minlon,minlat,maxlon,maxlat).check();
         }
         
         let tile_spec = Regex::new(r"tile:(\-?[0-9]*\.?[0-9]*),(\-?[0-9]*\.?[0-9]*),(\-?[0-9]*\.?[0-9]*)(:(\-?[0-9]*\.?[0-9]*))?").or(Err(Error::new(ErrorKind::Other,"??")))?;
@@ -228,7 +241,27 @@ impl Bbox {
         (self.minlon,self.minlat,self.maxlon,self.maxlat)
     }
     
-    pub fn contains(&self, other: &Bbox) -> bool {
+    /// True for bboxes crossing the antimeridian, which are given with
+    /// minlon greater than maxlon (e.g. `1760000000,-210000000,-1780000000,-120000000`
+    /// for Fiji). Empty bboxes, with minlat greater than maxlat, never cross.
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.minlon > self.maxlon && self.minlat <= self.maxlat
+    }
+
+    /// Splits a bbox crossing the antimeridian into its parts east and west
+    /// of it. Other bboxes are returned unchanged.
+    pub fn split_antimeridian(&self) -> Vec<Bbox> {
+        if self.crosses_antimeridian() {
+            vec![
+                Bbox::new(self.minlon, self.minlat, 1800000000, self.maxlat),
+                Bbox::new(-1800000000, self.minlat, self.maxlon, self.maxlat),
+            ]
+        } else {
+            vec![self.clone()]
+        }
+    }
+
+    fn contains_simple(&self, other: &Bbox) -> bool {
         if self.minlon > other.minlon {
             return false;
         }
@@ -243,22 +276,39 @@ impl Bbox {
         }
         true
     }
+
+    pub fn contains(&self, other: &Bbox) -> bool {
+        if !self.crosses_antimeridian() && !other.crosses_antimeridian() {
+            return self.contains_simple(other);
+        }
+        let parts = self.split_antimeridian();
+        other
+            .split_antimeridian()
+            .iter()
+            .all(|o| parts.iter().any(|p| p.contains_simple(o)))
+    }
+
     pub fn contains_point(&self, ln: i32, lt: i32) -> bool {
-        if self.minlon > ln {
+        if self.minlat > lt {
             return false;
         }
-        if self.minlat > lt {
+        if self.maxlat < lt {
             return false;
         }
-        if self.maxlon < ln {
+        if self.crosses_antimeridian() {
+            return ln >= self.minlon || ln <= self.maxlon;
+        }
+        if self.minlon > ln {
             return false;
         }
-        if self.maxlat < lt {
+        if self.maxlon < ln {
             return false;
         }
         true
     }
 
+    /// Doesn't wrap round the antimeridian: the result always has minlon at
+    /// most maxlon.
     pub fn expand(&mut self, lon: i32, lat: i32) {
         if lon < self.minlon {
             self.minlon = lon;
@@ -274,7 +324,7 @@ impl Bbox {
         }
     }
 
-    pub fn overlaps(&self, other: &Bbox) -> bool {
+    fn overlaps_simple(&self, other: &Bbox) -> bool {
         if self.minlon > other.maxlon {
             return false;
         }
@@ -289,6 +339,27 @@ impl Bbox {
         }
         return true;
     }
+
+    pub fn overlaps(&self, other: &Bbox) -> bool {
+        if !self.crosses_antimeridian() && !other.crosses_antimeridian() {
+            return self.overlaps_simple(other);
+        }
+        let parts = self.split_antimeridian();
+        other
+            .split_antimeridian()
+            .iter()
+            .any(|o| parts.iter().any(|p| p.overlaps_simple(o)))
+    }
+
+    pub fn tile_overlap(&self, tile: &Bbox) -> TileOverlap {
+        if self.contains(tile) {
+            TileOverlap::Inside
+        } else if self.overlaps(tile) {
+            TileOverlap::Partial
+        } else {
+            TileOverlap::Outside
+        }
+    }
 }
 
 impl fmt::Display for Bbox {
@@ -409,6 +480,51 @@ impl Quadtree {
         (self.0 & 31) as usize
     }
 
+    /// Returns the four tiles one level below this one, or nothing at the
+    /// maximum depth of 20.
+    pub fn children(&self) -> Vec<Quadtree> {
+        let d = self.depth();
+        if self.0 < 0 || d >= 20 {
+            return Vec::new();
+        }
+        let base = self.0 - (d as i64);
+        (0..4)
+            .map(|q| Quadtree(base | (q << (61 - 2 * d)) | (d as i64 + 1)))
+            .collect()
+    }
+
+    /// Returns the tiles at the same depth which share an edge or corner with
+    /// this one, wrapping round at the antimeridian.
+    pub fn neighbours(&self) -> Vec<Quadtree> {
+        if self.0 < 0 || self.depth() == 0 {
+            return Vec::new();
+        }
+        let (x, y, z) = self.as_tuple().xyz();
+        let n = 1i64 << z;
+
+        let mut res = Vec::with_capacity(8);
+        for dy in -1..=1 {
+            let ny = y as i64 + dy;
+            if ny < 0 || ny >= n {
+                continue;
+            }
+            for dx in -1..=1 {
+                let nx = (x as i64 + dx).rem_euclid(n);
+                let q = Quadtree::from_xyz(nx as u32, ny as u32, z);
+                if q != *self && !res.contains(&q) {
+                    res.push(q);
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns the minimal set of tiles, no deeper than `maxlevel`, which
+    /// cover `bbox`.
+    pub fn covering(bbox: &Bbox, maxlevel: usize) -> Vec<Quadtree> {
+        QuadtreeCovering::new(maxlevel, 0.0, |t| bbox.tile_overlap(t)).tiles()
+    }
+
     pub fn as_string(&self) -> String {
         if self.0 < 0 {
             return String::from("NULL");
@@ -555,6 +671,117 @@ impl serde::Serialize for Quadtree {
     }
 }
 
+/// How a tile's bbox relates to an area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOverlap {
+    Outside,
+    Partial,
+    Inside,
+}
+
+/// Set of disjoint tiles covering an area, each marked as either inside the
+/// area or only partly overlapping it. Tiles are only split while they
+/// partly overlap the area and are above `maxlevel`, and any four children
+/// all inside the area are replaced with their parent.
+#[derive(Debug, Clone)]
+pub struct QuadtreeCovering {
+    tiles: BTreeMap<Quadtree, bool>,
+    maxlevel: usize,
+}
+
+impl QuadtreeCovering {
+    /// Finds the covering using `test`, which is passed the bbox of each
+    /// tile, expanded by `buffer` as in [`Quadtree::as_bbox`].
+    pub fn new<F: Fn(&Bbox) -> TileOverlap>(maxlevel: usize, buffer: f64, test: F) -> QuadtreeCovering {
+        let mut tiles = Vec::new();
+        add_covering(Quadtree::new(0), usize::min(maxlevel, 20), buffer, &test, &mut tiles);
+        QuadtreeCovering {
+            tiles: tiles.into_iter().collect(),
+            maxlevel: maxlevel,
+        }
+    }
+
+    pub fn from_bbox(bbox: &Bbox, maxlevel: usize, buffer: f64) -> QuadtreeCovering {
+        QuadtreeCovering::new(maxlevel, buffer, |t| bbox.tile_overlap(t))
+    }
+
+    pub fn maxlevel(&self) -> usize {
+        self.maxlevel
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn tiles(&self) -> Vec<Quadtree> {
+        self.tiles.keys().cloned().collect()
+    }
+
+    /// Iterates over the tiles, with true for tiles inside the area.
+    pub fn iter(&self) -> impl Iterator<Item = (&Quadtree, &bool)> {
+        self.tiles.iter()
+    }
+
+    /// Returns whether tile `qt` overlaps the area. This is `None` when `qt`
+    /// is below a tile at `maxlevel` which only partly overlaps the area, or
+    /// has no tiles of the covering below it, in which case `qt` must be
+    /// tested directly: the buffer of a shallow tile reaches further than the
+    /// buffers of the covering tiles.
+    pub fn check(&self, qt: &Quadtree) -> Option<bool> {
+        for d in 0..=qt.depth() {
+            match self.tiles.get(&qt.round(d)) {
+                Some(true) => {
+                    return Some(true);
+                }
+                Some(false) => {
+                    return if d == qt.depth() { Some(true) } else { None };
+                }
+                None => {}
+            }
+        }
+        //tiles below qt come directly after it in quadtree order
+        match self.tiles.range(*qt..).next() {
+            Some((c, _)) if qt.is_parent(c) => Some(true),
+            _ => None,
+        }
+    }
+}
+
+fn add_covering<F: Fn(&Bbox) -> TileOverlap>(
+    qt: Quadtree,
+    maxlevel: usize,
+    buffer: f64,
+    test: &F,
+    res: &mut Vec<(Quadtree, bool)>,
+) {
+    match test(&qt.as_bbox(buffer)) {
+        TileOverlap::Outside => {}
+        TileOverlap::Inside => {
+            res.push((qt, true));
+        }
+        TileOverlap::Partial => {
+            if qt.depth() >= maxlevel {
+                res.push((qt, false));
+                return;
+            }
+            let n = res.len();
+            for c in qt.children() {
+                add_covering(c, maxlevel, buffer, test, res);
+            }
+            if res.len() == n + 4
+                && res[n..].iter().all(|(c, i)| *i && c.depth() == qt.depth() + 1)
+            {
+                res.truncate(n);
+                res.push((qt, true));
+            }
+        }
+    }
+}
+
 fn find_quad(min_x: f64, min_y: f64, max_x: f64, max_y: f64, buffer: f64) -> i64 {
     if (min_x < (-1.0 - buffer))
         || (min_y < (-1.0 - buffer))
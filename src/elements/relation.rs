@@ -12,6 +12,7 @@ use crate::elements::traits::*;
 use crate::elements::IdSet;
 use core::cmp::Ordering;
 use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
 
 #[derive(Debug, Eq, Clone,serde::Serialize)]
 pub struct Relation {
@@ -52,7 +53,7 @@ impl Relation {
 
     pub fn read(
         changetype: Changetype,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         data: &[u8],
         minimal: bool,
     ) -> Result<Relation> {
@@ -90,7 +91,7 @@ impl Relation {
                     });
                 } else {
                     let m = Member {
-                        role: strings[roles[i] as usize].clone(),
+                        role: strings[roles[i] as usize].to_string(),
                         mem_type: ElementType::from_int(types[i]),
                         mem_ref: refs[i],
                     };
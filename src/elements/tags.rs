@@ -1,11 +1,18 @@
+use std::sync::Arc;
+
+/// Key and value strings are shared with the string table of the block they
+/// were read from, so cloning a tag doesn't allocate.
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, Ord, PartialOrd)]
 pub struct Tag {
-    pub key: String,
-    pub val: String,
+    pub key: Arc<str>,
+    pub val: Arc<str>,
 }
 
 impl Tag {
-    pub fn new(key: String, val: String) -> Tag {
-        Tag { key, val }
+    pub fn new<K: Into<Arc<str>>, V: Into<Arc<str>>>(key: K, val: V) -> Tag {
+        Tag {
+            key: key.into(),
+            val: val.into(),
+        }
     }
 }
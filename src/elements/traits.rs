@@ -1,8 +1,7 @@
 use crate::elements::{Info, Quadtree, Tag};
 use crate::elements::{Node, Relation, Way};
-/*use crate::geometry::{
-    ComplicatedPolygonGeometry, LinestringGeometry, PointGeometry, SimplePolygonGeometry,
-};*/
+use crate::geometry::{LinestringGeometry, PointGeometry, SimplePolygonGeometry};
+use crate::geometry::ComplicatedPolygonGeometry;
 
 use std::cmp::Ordering;
 
@@ -11,10 +10,10 @@ pub enum Element {
     Node(Node),
     Way(Way),
     Relation(Relation),
-    /*PointGeometry(PointGeometry),
+    PointGeometry(PointGeometry),
     LinestringGeometry(LinestringGeometry),
     SimplePolygonGeometry(SimplePolygonGeometry),
-    ComplicatedPolygonGeometry(ComplicatedPolygonGeometry),*/
+    ComplicatedPolygonGeometry(ComplicatedPolygonGeometry),
 }
 
 impl WithType for Element {
@@ -23,10 +22,10 @@ impl WithType for Element {
             Element::Node(_) => ElementType::Node,
             Element::Way(_) => ElementType::Way,
             Element::Relation(_) => ElementType::Relation,
-            /*Element::PointGeometry(_) => ElementType::PointGeometry,
+            Element::PointGeometry(_) => ElementType::PointGeometry,
             Element::LinestringGeometry(_) => ElementType::LinestringGeometry,
             Element::SimplePolygonGeometry(_) => ElementType::SimplePolygonGeometry,
-            Element::ComplicatedPolygonGeometry(_) => ElementType::ComplicatedPolygonGeometry,*/
+            Element::ComplicatedPolygonGeometry(_) => ElementType::ComplicatedPolygonGeometry,
         }
     }
 }
@@ -37,10 +36,10 @@ impl WithId for Element {
             Element::Node(n) => n.id,
             Element::Way(n) => n.id,
             Element::Relation(n) => n.id,
-            /*Element::PointGeometry(n) => n.id,
+            Element::PointGeometry(n) => n.id,
             Element::LinestringGeometry(n) => n.id,
             Element::SimplePolygonGeometry(n) => n.id,
-            Element::ComplicatedPolygonGeometry(n) => n.id,*/
+            Element::ComplicatedPolygonGeometry(n) => n.id,
         }
     }
 }
@@ -80,6 +79,15 @@ impl ElementType {
             }
         }
     }
+    /// As [`ElementType::from_int`], returning an error for unknown values.
+    pub fn try_from_int(t: u64) -> crate::utils::Result<ElementType> {
+        match t {
+            0 => Ok(ElementType::Node),
+            1 => Ok(ElementType::Way),
+            2 => Ok(ElementType::Relation),
+            _ => Err(crate::utils::Error::PbfDataError(format!("unexpected element type {}", t))),
+        }
+    }
     pub fn as_int(&self) -> u64 {
         match self {
             ElementType::Node => 0,
@@ -131,6 +139,19 @@ impl Changetype {
         }
     }
 
+    /// As [`Changetype::from_int`], returning an error for unknown values.
+    pub fn try_from_int(ct: u64) -> crate::utils::Result<Changetype> {
+        match ct {
+            0 => Ok(Changetype::Normal),
+            1 => Ok(Changetype::Delete),
+            2 => Ok(Changetype::Remove),
+            3 => Ok(Changetype::Unchanged),
+            4 => Ok(Changetype::Modify),
+            5 => Ok(Changetype::Create),
+            _ => Err(crate::utils::Error::PbfDataError(format!("unexpected changetype {}", ct))),
+        }
+    }
+
     pub fn as_int(&self) -> u64 {
         match self {
             Changetype::Normal => 0,
@@ -208,10 +229,10 @@ impl WithQuadtree for Element {
             Element::Node(n) => n.get_quadtree(),
             Element::Way(n) => n.get_quadtree(),
             Element::Relation(n) => n.get_quadtree(),
-            /*Element::PointGeometry(n) => n.get_quadtree(),
+            Element::PointGeometry(n) => n.get_quadtree(),
             Element::LinestringGeometry(n) => n.get_quadtree(),
             Element::SimplePolygonGeometry(n) => n.get_quadtree(),
-            Element::ComplicatedPolygonGeometry(n) => n.get_quadtree(),*/
+            Element::ComplicatedPolygonGeometry(n) => n.get_quadtree(),
         }
     }
 }
@@ -1,5 +1,5 @@
 use simple_protocolbuffers::{
-    data_length, pack_data, pack_delta_int_ref, pack_value, read_delta_packed_int, PbfTag,
+    data_length, pack_data, pack_delta_int, pack_delta_int_ref, pack_value, read_delta_packed_int, PbfTag,
 };
 
 use crate::elements::common::{
@@ -11,7 +11,8 @@ use crate::elements::tags::Tag;
 use crate::elements::traits::*;
 
 use core::cmp::Ordering;
-use std::io::Result;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
 
 #[derive(Debug, Eq, Clone,serde::Serialize)]
 pub struct Way {
@@ -20,6 +21,10 @@ pub struct Way {
     pub info: Option<Info>,
     pub tags: Vec<Tag>,
     pub refs: Vec<i64>,
+    
+    /// Node locations as (lon, lat) pairs, one for each entry in refs. This
+    /// is only populated for files with the LocationsOnWays feature.
+    pub lonlats: Vec<(i32, i32)>,
 
     pub quadtree: Quadtree,
 }
@@ -32,12 +37,13 @@ impl Way {
             info: None,
             tags: Vec::new(),
             refs: Vec::new(),
+            lonlats: Vec::new(),
             quadtree: Quadtree::empty(),
         }
     }
     pub fn read(
         changetype: Changetype,
-        strings: &Vec<String>,
+        strings: &Vec<Arc<str>>,
         data: &[u8],
         minimal: bool,
     ) -> Result<Way> {
@@ -45,22 +51,55 @@ impl Way {
 
         let rem = read_common(&mut w, &strings, data, minimal)?;
 
+        let mut lats = Vec::new();
+        let mut lons = Vec::new();
         for t in rem {
             match t {
                 PbfTag::Data(8, d) => w.refs = read_delta_packed_int(&d),
+                PbfTag::Data(9, d) => lats = read_delta_packed_int(&d),
+                PbfTag::Data(10, d) => lons = read_delta_packed_int(&d),
                 _ => {}
             }
         }
+        if !lats.is_empty() || !lons.is_empty() {
+            if lats.len() != w.refs.len() || lons.len() != w.refs.len() {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("way {}: {} refs but {} lats, {} lons", w.id, w.refs.len(), lats.len(), lons.len()),
+                ));
+            }
+            w.lonlats = lons.iter().zip(lats.iter()).map(|(ln, lt)| (*ln as i32, *lt as i32)).collect();
+        }
         Ok(w)
     }
+    /// Packs the way. lonlats are only written if include_locations is set,
+    /// which should be the case only for files with the LocationsOnWays
+    /// feature.
     pub fn pack(
         &self,
         pack_strings: &mut Box<PackStringTable>,
         include_qts: bool,
+        include_locations: bool,
     ) -> Result<Vec<u8>> {
         let refs = pack_delta_int_ref(self.refs.iter());
+        
+        let (lats, lons) = if !include_locations || self.lonlats.is_empty() {
+            (Vec::new(), Vec::new())
+        } else {
+            if self.lonlats.len() != self.refs.len() {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("way {}: {} refs but {} locations", self.id, self.refs.len(), self.lonlats.len()),
+                ));
+            }
+            (
+                pack_delta_int(self.lonlats.iter().map(|(_, lt)| *lt as i64)),
+                pack_delta_int(self.lonlats.iter().map(|(ln, _)| *ln as i64)),
+            )
+        };
 
-        let l = pack_length(&self.tags, pack_strings, include_qts) + data_length(8, refs.len());
+        let l = pack_length(&self.tags, pack_strings, include_qts) + data_length(8, refs.len())
+            + data_length(9, lats.len()) + data_length(10, lons.len());
 
         let mut res = Vec::with_capacity(l);
         pack_head(&self.id, &self.info, &self.tags, &mut res, pack_strings)?;
@@ -69,6 +108,10 @@ impl Way {
         } else {
             pack_data(&mut res, 8, &refs);
         }
+        if !lats.is_empty() {
+            pack_data(&mut res, 9, &lats);
+            pack_data(&mut res, 10, &lons);
+        }
         pack_tail(&self.quadtree, &mut res, include_qts)?;
         Ok(res)
 
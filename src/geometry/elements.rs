@@ -0,0 +1,371 @@
+use crate::elements::{
+    Bbox, Element, ElementType, Info, Quadtree, Tag, WithId, WithInfo, WithQuadtree, WithTags, WithType,
+};
+
+/// Coordinate pair as integer values of 10^-7 degrees, as for
+/// [`Node`](crate::elements::Node).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct LonLat {
+    pub lon: i32,
+    pub lat: i32,
+}
+
+impl LonLat {
+    pub fn new(lon: i32, lat: i32) -> LonLat {
+        LonLat { lon: lon, lat: lat }
+    }
+}
+
+fn lonlats_bbox(lonlats: &[LonLat]) -> Bbox {
+    let mut bx = Bbox::empty();
+    for ll in lonlats {
+        bx.expand(ll.lon, ll.lat);
+    }
+    bx
+}
+
+/// Tagged node.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PointGeometry {
+    pub id: i64,
+    pub info: Option<Info>,
+    pub tags: Vec<Tag>,
+    pub lonlat: LonLat,
+    pub quadtree: Quadtree,
+}
+
+impl PointGeometry {
+    pub fn new(id: i64, info: Option<Info>, tags: Vec<Tag>, lonlat: LonLat, quadtree: Quadtree) -> PointGeometry {
+        PointGeometry {
+            id: id,
+            info: info,
+            tags: tags,
+            lonlat: lonlat,
+            quadtree: quadtree,
+        }
+    }
+
+    pub fn bbox(&self) -> Bbox {
+        Bbox::from_point(self.lonlat.lon, self.lonlat.lat)
+    }
+}
+
+/// Tagged way which isn't a polygon, with the location of each node.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LinestringGeometry {
+    pub id: i64,
+    pub info: Option<Info>,
+    pub tags: Vec<Tag>,
+    pub refs: Vec<i64>,
+    pub lonlats: Vec<LonLat>,
+    pub quadtree: Quadtree,
+}
+
+impl LinestringGeometry {
+    pub fn new(
+        id: i64,
+        info: Option<Info>,
+        tags: Vec<Tag>,
+        refs: Vec<i64>,
+        lonlats: Vec<LonLat>,
+        quadtree: Quadtree,
+    ) -> LinestringGeometry {
+        LinestringGeometry {
+            id: id,
+            info: info,
+            tags: tags,
+            refs: refs,
+            lonlats: lonlats,
+            quadtree: quadtree,
+        }
+    }
+
+    pub fn bbox(&self) -> Bbox {
+        lonlats_bbox(&self.lonlats)
+    }
+}
+
+/// Closed way with polygon tags. The ring is stored as given by the way, so
+/// the first and last entries of `refs` and `lonlats` are the same.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SimplePolygonGeometry {
+    pub id: i64,
+    pub info: Option<Info>,
+    pub tags: Vec<Tag>,
+    pub refs: Vec<i64>,
+    pub lonlats: Vec<LonLat>,
+    pub quadtree: Quadtree,
+}
+
+impl SimplePolygonGeometry {
+    pub fn new(
+        id: i64,
+        info: Option<Info>,
+        tags: Vec<Tag>,
+        refs: Vec<i64>,
+        lonlats: Vec<LonLat>,
+        quadtree: Quadtree,
+    ) -> SimplePolygonGeometry {
+        SimplePolygonGeometry {
+            id: id,
+            info: info,
+            tags: tags,
+            refs: refs,
+            lonlats: lonlats,
+            quadtree: quadtree,
+        }
+    }
+
+    pub fn bbox(&self) -> Bbox {
+        lonlats_bbox(&self.lonlats)
+    }
+
+    /// Returns true if the ring is anticlockwise.
+    pub fn is_anticlockwise(&self) -> bool {
+        ring_signed_area(&self.lonlats) > 0.0
+    }
+}
+
+/// Twice the signed area of the ring `lonlats`, in square degrees * 10^14.
+/// Positive for anticlockwise rings.
+pub fn ring_signed_area(lonlats: &[LonLat]) -> f64 {
+    let mut res = 0.0;
+    for p in lonlats.windows(2) {
+        res += (p[0].lon as f64) * (p[1].lat as f64) - (p[1].lon as f64) * (p[0].lat as f64);
+    }
+    res
+}
+
+/// Closed ring assembled from one or more ways.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Ring {
+    pub way_ids: Vec<i64>,
+    pub refs: Vec<i64>,
+    pub lonlats: Vec<LonLat>,
+}
+
+impl Ring {
+    pub fn is_anticlockwise(&self) -> bool {
+        ring_signed_area(&self.lonlats) > 0.0
+    }
+
+    pub fn reverse(&mut self) {
+        self.way_ids.reverse();
+        self.refs.reverse();
+        self.lonlats.reverse();
+    }
+
+    /// Returns true if `ll` is inside the ring.
+    pub fn contains_point(&self, ll: &LonLat) -> bool {
+        let (x, y) = (ll.lon as f64, ll.lat as f64);
+        let mut inside = false;
+        for p in self.lonlats.windows(2) {
+            let (x0, y0) = (p[0].lon as f64, p[0].lat as f64);
+            let (x1, y1) = (p[1].lon as f64, p[1].lat as f64);
+            if (y0 > y) != (y1 > y) && x < x0 + (y - y0) * (x1 - x0) / (y1 - y0) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+/// Exterior ring, which is anticlockwise, and the clockwise interior rings
+/// it contains.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct PolygonPart {
+    pub exterior: Ring,
+    pub interiors: Vec<Ring>,
+}
+
+impl PolygonPart {
+    pub fn new(exterior: Ring) -> PolygonPart {
+        PolygonPart {
+            exterior: exterior,
+            interiors: Vec::new(),
+        }
+    }
+}
+
+/// Polygon assembled from the member ways of a multipolygon or boundary
+/// relation.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ComplicatedPolygonGeometry {
+    pub id: i64,
+    pub info: Option<Info>,
+    pub tags: Vec<Tag>,
+    pub parts: Vec<PolygonPart>,
+    pub quadtree: Quadtree,
+}
+
+impl ComplicatedPolygonGeometry {
+    pub fn new(
+        id: i64,
+        info: Option<Info>,
+        tags: Vec<Tag>,
+        parts: Vec<PolygonPart>,
+        quadtree: Quadtree,
+    ) -> ComplicatedPolygonGeometry {
+        ComplicatedPolygonGeometry {
+            id: id,
+            info: info,
+            tags: tags,
+            parts: parts,
+            quadtree: quadtree,
+        }
+    }
+
+    pub fn bbox(&self) -> Bbox {
+        let mut bx = Bbox::empty();
+        for p in &self.parts {
+            for ll in &p.exterior.lonlats {
+                bx.expand(ll.lon, ll.lat);
+            }
+        }
+        bx
+    }
+}
+
+impl WithType for PointGeometry {
+    fn get_type(&self) -> ElementType {
+        ElementType::PointGeometry
+    }
+}
+
+impl WithId for PointGeometry {
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl WithInfo for PointGeometry {
+    fn get_info<'a>(&'a self) -> &Option<Info> {
+        &self.info
+    }
+}
+
+impl WithTags for PointGeometry {
+    fn get_tags<'a>(&'a self) -> &'a [Tag] {
+        &self.tags
+    }
+}
+
+impl WithQuadtree for PointGeometry {
+    fn get_quadtree<'a>(&'a self) -> &'a Quadtree {
+        &self.quadtree
+    }
+}
+
+impl From<PointGeometry> for Element {
+    fn from(g: PointGeometry) -> Element {
+        Element::PointGeometry(g)
+    }
+}
+
+impl WithType for LinestringGeometry {
+    fn get_type(&self) -> ElementType {
+        ElementType::LinestringGeometry
+    }
+}
+
+impl WithId for LinestringGeometry {
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl WithInfo for LinestringGeometry {
+    fn get_info<'a>(&'a self) -> &Option<Info> {
+        &self.info
+    }
+}
+
+impl WithTags for LinestringGeometry {
+    fn get_tags<'a>(&'a self) -> &'a [Tag] {
+        &self.tags
+    }
+}
+
+impl WithQuadtree for LinestringGeometry {
+    fn get_quadtree<'a>(&'a self) -> &'a Quadtree {
+        &self.quadtree
+    }
+}
+
+impl From<LinestringGeometry> for Element {
+    fn from(g: LinestringGeometry) -> Element {
+        Element::LinestringGeometry(g)
+    }
+}
+
+impl WithType for SimplePolygonGeometry {
+    fn get_type(&self) -> ElementType {
+        ElementType::SimplePolygonGeometry
+    }
+}
+
+impl WithId for SimplePolygonGeometry {
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl WithInfo for SimplePolygonGeometry {
+    fn get_info<'a>(&'a self) -> &Option<Info> {
+        &self.info
+    }
+}
+
+impl WithTags for SimplePolygonGeometry {
+    fn get_tags<'a>(&'a self) -> &'a [Tag] {
+        &self.tags
+    }
+}
+
+impl WithQuadtree for SimplePolygonGeometry {
+    fn get_quadtree<'a>(&'a self) -> &'a Quadtree {
+        &self.quadtree
+    }
+}
+
+impl From<SimplePolygonGeometry> for Element {
+    fn from(g: SimplePolygonGeometry) -> Element {
+        Element::SimplePolygonGeometry(g)
+    }
+}
+
+impl WithType for ComplicatedPolygonGeometry {
+    fn get_type(&self) -> ElementType {
+        ElementType::ComplicatedPolygonGeometry
+    }
+}
+
+impl WithId for ComplicatedPolygonGeometry {
+    fn get_id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl WithInfo for ComplicatedPolygonGeometry {
+    fn get_info<'a>(&'a self) -> &Option<Info> {
+        &self.info
+    }
+}
+
+impl WithTags for ComplicatedPolygonGeometry {
+    fn get_tags<'a>(&'a self) -> &'a [Tag] {
+        &self.tags
+    }
+}
+
+impl WithQuadtree for ComplicatedPolygonGeometry {
+    fn get_quadtree<'a>(&'a self) -> &'a Quadtree {
+        &self.quadtree
+    }
+}
+
+impl From<ComplicatedPolygonGeometry> for Element {
+    fn from(g: ComplicatedPolygonGeometry) -> Element {
+        Element::ComplicatedPolygonGeometry(g)
+    }
+}
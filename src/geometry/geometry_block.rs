@@ -0,0 +1,58 @@
+use crate::elements::{Element, Quadtree};
+use crate::geometry::{
+    ComplicatedPolygonGeometry, LinestringGeometry, PointGeometry, SimplePolygonGeometry,
+};
+
+/// Geometry elements from one block of a quadtree sorted file.
+#[derive(Debug)]
+pub struct GeometryBlock {
+    pub index: i64,
+    pub location: u64,
+    pub quadtree: Quadtree,
+    pub end_date: i64,
+    pub points: Vec<PointGeometry>,
+    pub linestrings: Vec<LinestringGeometry>,
+    pub simple_polygons: Vec<SimplePolygonGeometry>,
+    pub complicated_polygons: Vec<ComplicatedPolygonGeometry>,
+}
+
+impl GeometryBlock {
+    pub fn new(index: i64, location: u64, quadtree: Quadtree, end_date: i64) -> GeometryBlock {
+        GeometryBlock {
+            index: index,
+            location: location,
+            quadtree: quadtree,
+            end_date: end_date,
+            points: Vec::new(),
+            linestrings: Vec::new(),
+            simple_polygons: Vec::new(),
+            complicated_polygons: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+            + self.linestrings.len()
+            + self.simple_polygons.len()
+            + self.complicated_polygons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl IntoIterator for GeometryBlock {
+    type Item = Element;
+    type IntoIter = Box<dyn Iterator<Item = Element>>;
+    fn into_iter(self: Self) -> Self::IntoIter {
+        Box::new(
+            self.points
+                .into_iter()
+                .map(Element::from)
+                .chain(self.linestrings.into_iter().map(Element::from))
+                .chain(self.simple_polygons.into_iter().map(Element::from))
+                .chain(self.complicated_polygons.into_iter().map(Element::from)),
+        )
+    }
+}
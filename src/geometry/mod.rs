@@ -0,0 +1,21 @@
+//! Geometries of features in quadtree sorted files. Way node refs are
+//! resolved to locations block by block, using the nodes of each tile and
+//! its parents.
+
+mod elements;
+mod geometry_block;
+mod multipolygon;
+mod process_geometry;
+
+pub use elements::{
+    ring_signed_area, ComplicatedPolygonGeometry, LinestringGeometry, LonLat, PointGeometry,
+    PolygonPart, Ring, SimplePolygonGeometry,
+};
+pub use geometry_block::GeometryBlock;
+pub use multipolygon::{
+    assemble_rings, is_multipolygon, make_polygon_parts, MultipolygonAssembler, WayPart,
+};
+pub use process_geometry::{
+    is_polygon, make_geometry_block, make_process_geometry, way_lonlats, ParentTileNodes,
+    UNDEFINED_COORDINATE,
+};
@@ -0,0 +1,289 @@
+use crate::elements::{ElementType, Info, Quadtree, Relation, Tag};
+use crate::geometry::{ComplicatedPolygonGeometry, LonLat, PolygonPart, Ring};
+use crate::utils::{Error, Result};
+
+use std::collections::HashMap;
+
+/// Returns true for relations with tag type=multipolygon or type=boundary.
+pub fn is_multipolygon(tags: &[Tag]) -> bool {
+    for t in tags {
+        if t.key.as_ref() == "type" {
+            return t.val.as_ref() == "multipolygon" || t.val.as_ref() == "boundary";
+        }
+    }
+    false
+}
+
+/// Way member of a multipolygon relation, with the location of each node.
+#[derive(Debug, Clone)]
+pub struct WayPart {
+    pub id: i64,
+    pub refs: Vec<i64>,
+    pub lonlats: Vec<LonLat>,
+}
+
+impl WayPart {
+    pub fn new(id: i64, refs: Vec<i64>, lonlats: Vec<LonLat>) -> WayPart {
+        WayPart {
+            id: id,
+            refs: refs,
+            lonlats: lonlats,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.refs.len() >= 4 && self.refs.first() == self.refs.last()
+    }
+}
+
+/// Joins `parts` end to end into closed rings. Returns the rings and the ids
+/// of any ways which couldn't be made into a closed ring.
+pub fn assemble_rings(parts: Vec<WayPart>) -> (Vec<Ring>, Vec<i64>) {
+    let mut rings = Vec::new();
+    let mut unclosed = Vec::new();
+    let mut open = Vec::new();
+
+    for p in parts {
+        if p.is_closed() {
+            rings.push(Ring {
+                way_ids: vec![p.id],
+                refs: p.refs,
+                lonlats: p.lonlats,
+            });
+        } else if p.refs.len() < 2 {
+            unclosed.push(p.id);
+        } else {
+            open.push(p);
+        }
+    }
+
+    while let Some(first) = open.pop() {
+        let mut ring = Ring {
+            way_ids: vec![first.id],
+            refs: first.refs,
+            lonlats: first.lonlats,
+        };
+        loop {
+            if ring.refs.len() >= 4 && ring.refs.first() == ring.refs.last() {
+                rings.push(ring);
+                break;
+            }
+            let end = *ring.refs.last().unwrap();
+            match open.iter().position(|p| p.refs[0] == end || *p.refs.last().unwrap() == end) {
+                None => {
+                    unclosed.extend(ring.way_ids);
+                    break;
+                }
+                Some(i) => {
+                    let mut p = open.swap_remove(i);
+                    if p.refs[0] != end {
+                        p.refs.reverse();
+                        p.lonlats.reverse();
+                    }
+                    ring.way_ids.push(p.id);
+                    ring.refs.extend_from_slice(&p.refs[1..]);
+                    ring.lonlats.extend_from_slice(&p.lonlats[1..]);
+                }
+            }
+        }
+    }
+    (rings, unclosed)
+}
+
+/// Makes a polygon from the rings of a multipolygon relation. Exterior
+/// rings are made anticlockwise and interior rings clockwise, and each
+/// interior ring is added to the exterior ring which contains it.
+pub fn make_polygon_parts(id: i64, outers: Vec<Ring>, inners: Vec<Ring>) -> Result<Vec<PolygonPart>> {
+    if outers.is_empty() {
+        return Err(Error::InvalidDataError(format!("relation {} has no outer rings", id)));
+    }
+
+    let mut parts = Vec::with_capacity(outers.len());
+    for mut r in outers {
+        if !r.is_anticlockwise() {
+            r.reverse();
+        }
+        parts.push(PolygonPart::new(r));
+    }
+
+    for mut r in inners {
+        if r.is_anticlockwise() {
+            r.reverse();
+        }
+        let ll = r.lonlats[0];
+        match parts.iter().position(|p| p.exterior.contains_point(&ll)) {
+            Some(i) => parts[i].interiors.push(r),
+            None => {
+                return Err(Error::InvalidDataError(format!(
+                    "relation {}: inner ring {:?} isn't inside an outer ring",
+                    id, r.way_ids
+                )));
+            }
+        }
+    }
+    Ok(parts)
+}
+
+struct PendingRelation {
+    id: i64,
+    info: Option<Info>,
+    tags: Vec<Tag>,
+    quadtree: Quadtree,
+    members: Vec<(i64, bool)>,
+}
+
+/// Assembles multipolygon relations from the blocks of a quadtree sorted
+/// file, passed in quadtree order. A relation is in the tile of, or a parent
+/// of the tile of, each of its members, so it can be assembled once every
+/// tile below its own has been seen, and only the member ways of relations
+/// in the current tile and its parents need to be kept.
+pub struct MultipolygonAssembler {
+    pending: Vec<(Quadtree, Vec<PendingRelation>)>,
+    wanted: HashMap<i64, usize>,
+    ways: HashMap<i64, WayPart>,
+}
+
+impl MultipolygonAssembler {
+    pub fn new() -> MultipolygonAssembler {
+        MultipolygonAssembler {
+            pending: Vec::new(),
+            wanted: HashMap::new(),
+            ways: HashMap::new(),
+        }
+    }
+
+    /// Adds the multipolygon relations from the block with quadtree `qt`.
+    /// Call after [`finish_tiles`](Self::finish_tiles) for the same tile,
+    /// and before adding the block's ways.
+    pub fn add_relations(&mut self, qt: Quadtree, relations: &[Relation]) {
+        let mut rels = Vec::new();
+        for r in relations {
+            if !is_multipolygon(&r.tags) {
+                continue;
+            }
+            let mut members = Vec::new();
+            for m in &r.members {
+                if m.mem_type != ElementType::Way {
+                    continue;
+                }
+                let is_inner = match m.role.as_str() {
+                    "outer" | "" => false,
+                    "inner" => true,
+                    _ => {
+                        continue;
+                    }
+                };
+                *self.wanted.entry(m.mem_ref).or_insert(0) += 1;
+                members.push((m.mem_ref, is_inner));
+            }
+            rels.push(PendingRelation {
+                id: r.id,
+                info: r.info.clone(),
+                tags: r.tags.clone(),
+                quadtree: r.quadtree.clone(),
+                members: members,
+            });
+        }
+        if !rels.is_empty() {
+            self.pending.push((qt, rels));
+        }
+    }
+
+    pub fn wants_way(&self, id: i64) -> bool {
+        self.wanted.contains_key(&id)
+    }
+
+    pub fn add_way(&mut self, way: WayPart) {
+        if self.wants_way(way.id) {
+            self.ways.insert(way.id, way);
+        }
+    }
+
+    fn take_way(&mut self, id: i64) -> Option<WayPart> {
+        let last = match self.wanted.get_mut(&id) {
+            Some(c) => {
+                *c -= 1;
+                *c == 0
+            }
+            None => false,
+        };
+        if last {
+            self.wanted.remove(&id);
+            self.ways.remove(&id)
+        } else {
+            self.ways.get(&id).cloned()
+        }
+    }
+
+    fn assemble(&mut self, rel: PendingRelation) -> Result<ComplicatedPolygonGeometry> {
+        let mut outers = Vec::new();
+        let mut inners = Vec::new();
+        let mut missing = Vec::new();
+        for (id, is_inner) in &rel.members {
+            match self.take_way(*id) {
+                Some(w) => {
+                    if *is_inner {
+                        inners.push(w);
+                    } else {
+                        outers.push(w);
+                    }
+                }
+                None => missing.push(*id),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(Error::MissingDataError(format!(
+                "relation {}: missing member ways {:?}",
+                rel.id, missing
+            )));
+        }
+
+        let (outer_rings, mut unclosed) = assemble_rings(outers);
+        let (inner_rings, unclosed_inner) = assemble_rings(inners);
+        unclosed.extend(unclosed_inner);
+        if !unclosed.is_empty() {
+            return Err(Error::InvalidDataError(format!(
+                "relation {}: unclosed rings from ways {:?}",
+                rel.id, unclosed
+            )));
+        }
+
+        let parts = make_polygon_parts(rel.id, outer_rings, inner_rings)?;
+        Ok(ComplicatedPolygonGeometry::new(rel.id, rel.info, rel.tags, parts, rel.quadtree))
+    }
+
+    /// Returns true if relations from the block with quadtree `qt` are still
+    /// waiting to be assembled. Call after [`add_relations`](Self::add_relations)
+    /// for that block.
+    pub fn is_pending(&self, qt: &Quadtree) -> bool {
+        match self.pending.last() {
+            Some((q, _)) => q == qt,
+            None => false,
+        }
+    }
+
+    /// Assembles the relations of every tile which isn't a parent of `qt`,
+    /// or of all remaining tiles if `qt` is None. Returns the polygons of
+    /// each tile, last tile first. Relations which can't be assembled are
+    /// returned as errors.
+    pub fn finish_tiles(&mut self, qt: Option<&Quadtree>) -> Vec<(Quadtree, Vec<Result<ComplicatedPolygonGeometry>>)> {
+        let mut res = Vec::new();
+        while let Some((q, _)) = self.pending.last() {
+            match qt {
+                Some(qt) => {
+                    if q.depth() < qt.depth() && q.is_parent(qt) {
+                        break;
+                    }
+                }
+                None => {}
+            }
+            let (q, rels) = self.pending.pop().unwrap();
+            let mut polys = Vec::with_capacity(rels.len());
+            for rel in rels {
+                polys.push(self.assemble(rel));
+            }
+            res.push((q, polys));
+        }
+        res
+    }
+}
@@ -0,0 +1,289 @@
+use channelled_callbacks::{CallFinish, Result as ccResult, Timings};
+
+use crate::elements::{PrimitiveBlock, Quadtree, Tag};
+use crate::geometry::{
+    GeometryBlock, LinestringGeometry, LonLat, MultipolygonAssembler, PointGeometry,
+    SimplePolygonGeometry, WayPart,
+};
+use crate::message;
+use crate::utils::{Error, ThreadTimer};
+
+use std::collections::HashMap;
+
+/// Keys which make a closed way a polygon rather than a linestring.
+const POLYGON_KEYS: &[&str] = &[
+    "building", "landuse", "natural", "amenity", "leisure", "aeroway", "military", "shop",
+    "tourism", "historic", "man_made", "place", "water",
+];
+
+/// Values of natural which are linear features even on closed ways.
+const NATURAL_LINE_VALUES: &[&str] = &["coastline", "cliff", "ridge", "arete", "tree_row"];
+
+/// Returns true if a closed way with `tags` is an area.
+pub fn is_polygon(tags: &[Tag]) -> bool {
+    for t in tags {
+        if t.key.as_ref() == "area" {
+            return t.val.as_ref() != "no";
+        }
+    }
+    for t in tags {
+        if t.key.as_ref() == "natural" && NATURAL_LINE_VALUES.contains(&t.val.as_ref()) {
+            continue;
+        }
+        if POLYGON_KEYS.contains(&t.key.as_ref()) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Node locations from the current tile and its parents. Blocks of a
+/// quadtree sorted file are in quadtree order, so each tile comes after its
+/// parents, and every node of a way is in the way's tile or one of its
+/// parents.
+pub struct ParentTileNodes {
+    tiles: Vec<(Quadtree, HashMap<i64, LonLat>)>,
+}
+
+impl ParentTileNodes {
+    pub fn new() -> ParentTileNodes {
+        ParentTileNodes { tiles: Vec::new() }
+    }
+
+    /// Drops the node locations of every tile which isn't a parent of `qt`.
+    pub fn set_tile(&mut self, qt: &Quadtree) {
+        while let Some((q, _)) = self.tiles.last() {
+            if q.depth() < qt.depth() && q.is_parent(qt) {
+                break;
+            }
+            self.tiles.pop();
+        }
+    }
+
+    pub fn add_tile(&mut self, qt: Quadtree, nodes: HashMap<i64, LonLat>) {
+        self.set_tile(&qt);
+        self.tiles.push((qt, nodes));
+    }
+
+    pub fn get(&self, id: i64) -> Option<LonLat> {
+        for (_, nodes) in self.tiles.iter().rev() {
+            match nodes.get(&id) {
+                Some(ll) => {
+                    return Some(*ll);
+                }
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+/// Coordinate value used by osmium for missing LocationsOnWays locations.
+pub const UNDEFINED_COORDINATE: i32 = i32::MAX;
+
+/// Returns the location of each node of a way, using the locations stored on
+/// the way (LocationsOnWays) where present and `get_location` otherwise.
+/// Returns None if any location is missing.
+pub fn way_lonlats<F>(refs: &[i64], lonlats: &[(i32, i32)], get_location: F) -> Option<Vec<LonLat>>
+where
+    F: Fn(i64) -> Option<LonLat>,
+{
+    let mut res = Vec::with_capacity(refs.len());
+    for (i, r) in refs.iter().enumerate() {
+        if lonlats.len() == refs.len() && lonlats[i].0 != UNDEFINED_COORDINATE {
+            res.push(LonLat::new(lonlats[i].0, lonlats[i].1));
+        } else {
+            res.push(get_location(*r)?);
+        }
+    }
+    Some(res)
+}
+
+/// Converts the merged block `pb` into geometries: tagged nodes become
+/// points, and tagged ways become linestrings or, if they are closed and
+/// [`is_polygon`], simple polygons. Multipolygon relations are added to
+/// `multipolygons`, to be assembled into complicated polygons by
+/// [`MultipolygonAssembler::finish_tiles`] once all their members have been
+/// seen. `nodes` and `multipolygons` must have been passed every previous
+/// block of the file, and `multipolygons.finish_tiles` called for this
+/// block's quadtree. Returns the geometries and the number of ways skipped
+/// because a node location was missing.
+pub fn make_geometry_block(
+    pb: PrimitiveBlock,
+    nodes: &mut ParentTileNodes,
+    multipolygons: &mut MultipolygonAssembler,
+) -> (GeometryBlock, usize) {
+    let mut res = GeometryBlock::new(pb.index, pb.location, pb.quadtree.clone(), pb.end_date);
+
+    multipolygons.add_relations(pb.quadtree.clone(), &pb.relations);
+
+    let mut tile_nodes = HashMap::with_capacity(pb.nodes.len());
+    for n in pb.nodes {
+        let ll = LonLat::new(n.lon, n.lat);
+        tile_nodes.insert(n.id, ll);
+        if !n.tags.is_empty() {
+            res.points.push(PointGeometry::new(n.id, n.info, n.tags, ll, n.quadtree));
+        }
+    }
+    nodes.add_tile(pb.quadtree.clone(), tile_nodes);
+
+    let mut num_missing = 0;
+    for w in pb.ways {
+        let is_member = multipolygons.wants_way(w.id);
+        if (w.tags.is_empty() && !is_member) || w.refs.len() < 2 {
+            continue;
+        }
+        let lonlats = match way_lonlats(&w.refs, &w.lonlats, |r| nodes.get(r)) {
+            Some(ll) => ll,
+            None => {
+                num_missing += 1;
+                continue;
+            }
+        };
+        if is_member {
+            multipolygons.add_way(WayPart::new(w.id, w.refs.clone(), lonlats.clone()));
+        }
+        if w.tags.is_empty() {
+            continue;
+        }
+
+        let is_closed = w.refs.len() >= 4 && w.refs.first() == w.refs.last();
+        if is_closed && is_polygon(&w.tags) {
+            res.simple_polygons.push(SimplePolygonGeometry::new(
+                w.id, w.info, w.tags, w.refs, lonlats, w.quadtree,
+            ));
+        } else {
+            res.linestrings.push(LinestringGeometry::new(
+                w.id, w.info, w.tags, w.refs, lonlats, w.quadtree,
+            ));
+        }
+    }
+    (res, num_missing)
+}
+
+/// Number of relations which couldn't be assembled to report individually.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Blocks are passed to `out` once they are complete. Blocks with
+/// multipolygon relations are held until every tile below them has been
+/// seen, so they are passed after those tiles' blocks.
+struct ProcessGeometry<T: ?Sized> {
+    out: Box<T>,
+    nodes: ParentTileNodes,
+    multipolygons: MultipolygonAssembler,
+    held: Vec<GeometryBlock>,
+    num_missing: usize,
+    num_errors: usize,
+    tm: f64,
+}
+
+impl<T> ProcessGeometry<T>
+where
+    T: ?Sized,
+{
+    pub fn new(out: Box<T>) -> ProcessGeometry<T> {
+        ProcessGeometry {
+            out: out,
+            nodes: ParentTileNodes::new(),
+            multipolygons: MultipolygonAssembler::new(),
+            held: Vec::new(),
+            num_missing: 0,
+            num_errors: 0,
+            tm: 0.0,
+        }
+    }
+
+    //adds the polygons of each finished tile to its held block, and
+    //returns those blocks
+    fn finish_tiles(&mut self, qt: Option<&Quadtree>) -> Vec<GeometryBlock> {
+        let mut res = Vec::new();
+        //blocks are held exactly when their tile has pending relations, so
+        //self.held matches the tiles returned
+        for (_, polys) in self.multipolygons.finish_tiles(qt) {
+            let mut gb = match self.held.pop() {
+                Some(gb) => gb,
+                None => {
+                    continue;
+                }
+            };
+            for p in polys {
+                match p {
+                    Ok(p) => gb.complicated_polygons.push(p),
+                    Err(e) => {
+                        if self.num_errors < MAX_REPORTED_ERRORS {
+                            message!("failed to assemble multipolygon: {:?}", e);
+                        }
+                        self.num_errors += 1;
+                    }
+                }
+            }
+            res.push(gb);
+        }
+        res
+    }
+}
+
+impl<T, U> CallFinish for ProcessGeometry<T>
+where
+    T: CallFinish<CallType = GeometryBlock, ReturnType = Timings<U>, ErrorType = Error> + ?Sized,
+    U: Sync + Send + 'static,
+{
+    type CallType = PrimitiveBlock;
+    type ReturnType = Timings<U>;
+    type ErrorType = Error;
+
+    //blocks must be passed in quadtree order, so this can't be split
+    //between threads
+    fn call(&mut self, pb: PrimitiveBlock) {
+        let tx = ThreadTimer::new();
+        let finished = self.finish_tiles(Some(&pb.quadtree));
+        let (gb, nm) = make_geometry_block(pb, &mut self.nodes, &mut self.multipolygons);
+        self.num_missing += nm;
+        let ready = if self.multipolygons.is_pending(&gb.quadtree) {
+            self.held.push(gb);
+            None
+        } else {
+            Some(gb)
+        };
+        self.tm += tx.since();
+        for f in finished {
+            self.out.call(f);
+        }
+        match ready {
+            Some(gb) => self.out.call(gb),
+            None => {}
+        }
+    }
+
+    fn finish(&mut self) -> ccResult<Self::ReturnType, Self::ErrorType> {
+        let tx = ThreadTimer::new();
+        //relations in the last tiles are only complete at the end of the file
+        let finished = self.finish_tiles(None);
+        self.tm += tx.since();
+        for f in finished {
+            self.out.call(f);
+        }
+
+        if self.num_missing > 0 {
+            message!("{} ways skipped with missing node locations", self.num_missing);
+        }
+        if self.num_errors > 0 {
+            message!("{} multipolygon relations couldn't be assembled", self.num_errors);
+        }
+        let mut tms = self.out.finish()?;
+        tms.add("process geometry", self.tm);
+        Ok(tms)
+    }
+}
+
+/// Returns a callback which converts merged blocks from a quadtree sorted
+/// file, in quadtree order, into [`GeometryBlock`]s passed to `out`.
+pub fn make_process_geometry<T, U>(
+    out: Box<T>,
+) -> Box<impl CallFinish<CallType = PrimitiveBlock, ReturnType = Timings<U>, ErrorType = Error>>
+where
+    T: CallFinish<CallType = GeometryBlock, ReturnType = Timings<U>, ErrorType = Error> + ?Sized,
+    U: Sync + Send + 'static,
+{
+    Box::new(ProcessGeometry::new(out))
+}
@@ -4,10 +4,12 @@ pub mod utils;
 
 pub mod calcqts;
 pub mod elements;
+pub mod geometry;
 pub mod mergechanges;
 pub mod pbfformat;
 pub mod sortblocks;
 pub mod update;
+pub mod verify;
 
 
 
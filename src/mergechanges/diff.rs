@@ -0,0 +1,546 @@
+use channelled_callbacks::CallFinish;
+use crate::elements::{
+    coordinate_as_float, compare_element_iters_call, Changetype, Element, ElementCompare, ElementType, Info,
+    Member, PrimitiveBlock, Quadtree, Tag, WithQuadtree,
+};
+use crate::mergechanges::run_mergechanges_sort;
+use crate::pbfformat::{
+    iter_elements_flat, pack_file_block, read_file_block_with_pos, read_filelist, BadBlocks,
+    CompressionType, HeaderBlock,
+};
+use crate::sortblocks::{QuadtreeTree, WriteFileInternalLocs};
+use crate::utils::{parse_timestamp, timestamp_string, LogTimes};
+use crate::utils::{Error, Result};
+use crate::message;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+fn is_directory(input: &str) -> bool {
+    input.ends_with("/") || std::path::Path::new(input).is_dir()
+}
+
+//removes the sorted snapshots written by snapshot_iter, including when
+//run_diff returns early with an error
+struct TempFiles(Vec<String>);
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for f in &self.0 {
+            if std::path::Path::new(f).exists() {
+                let _ = std::fs::remove_file(f);
+            }
+        }
+    }
+}
+
+//returns the elements of input in id order. A pbf file is read directly. An
+//osmquadtree directory is merged up to timestamp and sorted into tempfn
+//using temporary files, so that memory use stays bounded.
+fn snapshot_iter(
+    input: &str,
+    timestamp: Option<&str>,
+    tempfn: &str,
+    temps: &mut TempFiles,
+    numchan: usize,
+    ram_gb: usize,
+) -> Result<Box<dyn Iterator<Item = Element>>> {
+    if !is_directory(input) {
+        if timestamp.is_some() {
+            return Err(Error::UserSelectionError(format!(
+                "can't specify timestamp with single file {}",
+                input
+            )));
+        }
+        return iter_elements_flat(input, numchan);
+    }
+
+    temps.0.push(String::from(tempfn));
+    run_mergechanges_sort(
+        input, tempfn, None, None, None, false, timestamp, false, CompressionType::Zlib, numchan, ram_gb, false,
+        false, None, None, &BadBlocks::abort(),
+    )?;
+    iter_elements_flat(tempfn, numchan)
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            '\n' => res.push_str("&#10;"),
+            '\r' => res.push_str("&#13;"),
+            '\t' => res.push_str("&#9;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+fn info_attributes(info: &Option<Info>) -> String {
+    match info {
+        None => String::new(),
+        Some(info) => format!(
+            " version=\"{}\" timestamp=\"{}Z\" changeset=\"{}\" uid=\"{}\" user=\"{}\"",
+            info.version,
+            timestamp_string(info.timestamp),
+            info.changeset,
+            info.user_id,
+            xml_escape(&info.user)
+        ),
+    }
+}
+
+fn tags_xml(tags: &[Tag]) -> String {
+    let mut res = String::new();
+    for t in tags {
+        res.push_str(&format!(
+            "    <tag k=\"{}\" v=\"{}\"/>\n",
+            xml_escape(&t.key),
+            xml_escape(&t.val)
+        ));
+    }
+    res
+}
+
+fn member_xml(m: &Member) -> Result<String> {
+    let mem_type = match m.mem_type {
+        ElementType::Node => "node",
+        ElementType::Way => "way",
+        ElementType::Relation => "relation",
+        t => {
+            return Err(Error::InvalidDataError(format!("unexpected member type {:?}", t)));
+        }
+    };
+    Ok(format!(
+        "    <member type=\"{}\" ref=\"{}\" role=\"{}\"/>\n",
+        mem_type,
+        m.mem_ref,
+        xml_escape(&m.role)
+    ))
+}
+
+fn element_xml(ele: &Element, with_children: bool) -> Result<String> {
+    let (head, name, children) = match ele {
+        Element::Node(n) => {
+            let head = format!(
+                "<node id=\"{}\"{} lat=\"{:.7}\" lon=\"{:.7}\"",
+                n.id,
+                info_attributes(&n.info),
+                coordinate_as_float(n.lat),
+                coordinate_as_float(n.lon)
+            );
+            (head, "node", tags_xml(&n.tags))
+        }
+        Element::Way(w) => {
+            let mut children = String::new();
+            for r in &w.refs {
+                children.push_str(&format!("    <nd ref=\"{}\"/>\n", r));
+            }
+            children.push_str(&tags_xml(&w.tags));
+            (format!("<way id=\"{}\"{}", w.id, info_attributes(&w.info)), "way", children)
+        }
+        Element::Relation(r) => {
+            let mut children = String::new();
+            for m in &r.members {
+                children.push_str(&member_xml(m)?);
+            }
+            children.push_str(&tags_xml(&r.tags));
+            (format!("<relation id=\"{}\"{}", r.id, info_attributes(&r.info)), "relation", children)
+        }
+        e => {
+            return Err(Error::InvalidDataError(format!("can't write {:?} as osmChange", e)));
+        }
+    };
+
+    if !with_children || children.is_empty() {
+        Ok(format!("  {}/>\n", head))
+    } else {
+        Ok(format!("  {}>\n{}  </{}>\n", head, children, name))
+    }
+}
+
+fn action_name(ct: Changetype) -> &'static str {
+    match ct {
+        Changetype::Create => "create",
+        Changetype::Delete => "delete",
+        _ => "modify",
+    }
+}
+
+/// Writes an osmChange xml document. Consecutive changes with the same action
+/// are grouped into one create, modify or delete block.
+struct WriteOsc<W: Write> {
+    out: W,
+    current: Option<Changetype>,
+}
+
+impl<W: Write> WriteOsc<W> {
+    fn new(mut out: W) -> Result<WriteOsc<W>> {
+        out.write_all(b"<?xml version='1.0' encoding='UTF-8'?>\n")?;
+        out.write_all(b"<osmChange version=\"0.6\" generator=\"osmquadtree\">\n")?;
+        Ok(WriteOsc {
+            out: out,
+            current: None,
+        })
+    }
+
+    fn write(&mut self, ct: Changetype, ele: &Element) -> Result<()> {
+        if self.current != Some(ct) {
+            if let Some(c) = self.current {
+                self.out.write_all(format!("</{}>\n", action_name(c)).as_bytes())?;
+            }
+            self.out.write_all(format!("<{}>\n", action_name(ct)).as_bytes())?;
+            self.current = Some(ct);
+        }
+        let x = element_xml(ele, ct != Changetype::Delete)?;
+        self.out.write_all(x.as_bytes())?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W> {
+        if let Some(c) = self.current {
+            self.out.write_all(format!("</{}>\n", action_name(c)).as_bytes())?;
+        }
+        self.out.write_all(b"</osmChange>\n")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn read_tile_tree(input: &str) -> Result<QuadtreeTree> {
+    let fname = if is_directory(input) {
+        let filelist = read_filelist(input)?;
+        if filelist.is_empty() {
+            return Err(Error::InvalidDataError(format!("no files in {}", input)));
+        }
+        format!("{}{}", input, filelist[0].filename)
+    } else {
+        String::from(input)
+    };
+    let mut fobj = File::open(&fname)?;
+    let (x, fb) = read_file_block_with_pos(&mut fobj, 0)?;
+    if fb.block_type != "OSMHeader" {
+        return Err(Error::PbfDataError("first block not an OSMHeader".to_string()));
+    }
+    let head = HeaderBlock::read(x, &fb.try_data()?, &fname)?;
+
+    let mut tree = QuadtreeTree::new();
+    for ii in &head.index {
+        tree.add(&ii.quadtree, 1);
+    }
+    Ok(tree)
+}
+
+/// Collects changes into blocks, one per tile of a quadtree tree, in the same
+/// form as the .pbfc files written by update.
+struct TileChanges {
+    tree: QuadtreeTree,
+    blocks: BTreeMap<Quadtree, PrimitiveBlock>,
+    start_date: i64,
+    end_date: i64,
+}
+
+impl TileChanges {
+    fn new(tree: QuadtreeTree, start_date: i64, end_date: i64) -> TileChanges {
+        TileChanges {
+            tree: tree,
+            blocks: BTreeMap::new(),
+            start_date: start_date,
+            end_date: end_date,
+        }
+    }
+
+    fn find_tile(&self, ele: &Element) -> Quadtree {
+        let q = ele.get_quadtree();
+        if q.as_int() < 0 {
+            return Quadtree::new(0);
+        }
+        let (_, t) = self.tree.find(q);
+        t.qt
+    }
+
+    fn add(&mut self, tile: Quadtree, ele: Element, ct: Changetype, keep_quadtree: bool) -> Result<()> {
+        let (st, et) = (self.start_date, self.end_date);
+        let pb = self.blocks.entry(tile).or_insert_with(|| {
+            let mut pb = PrimitiveBlock::new(0, 0);
+            pb.quadtree = tile;
+            pb.start_date = st;
+            pb.end_date = et;
+            pb
+        });
+        match ele {
+            Element::Node(mut n) => {
+                n.changetype = ct;
+                if !keep_quadtree {
+                    n.quadtree = Quadtree::new(0);
+                }
+                pb.nodes.push(n);
+            }
+            Element::Way(mut w) => {
+                w.changetype = ct;
+                if !keep_quadtree {
+                    w.quadtree = Quadtree::new(0);
+                }
+                pb.ways.push(w);
+            }
+            Element::Relation(mut r) => {
+                r.changetype = ct;
+                if !keep_quadtree {
+                    r.quadtree = Quadtree::new(0);
+                }
+                pb.relations.push(r);
+            }
+            e => {
+                return Err(Error::InvalidDataError(format!("can't write {:?} as a change", e)));
+            }
+        }
+        Ok(())
+    }
+
+    //an element which moves to a different tile is removed from the old tile
+    fn add_changed(&mut self, left: Element, right: Element, ct: Changetype) -> Result<()> {
+        let left_tile = self.find_tile(&left);
+        let right_tile = self.find_tile(&right);
+        if left_tile != right_tile {
+            self.add(left_tile, left, Changetype::Remove, false)?;
+        } else if ct == Changetype::Unchanged {
+            return Ok(());
+        }
+        self.add(right_tile, right, ct, true)
+    }
+
+    fn write(self, outfn: &str) -> Result<usize> {
+        let mut wf = WriteFileInternalLocs::new(outfn, true);
+        let num_tiles = self.blocks.len();
+        for (k, mut v) in self.blocks {
+            v.sort();
+            let pp = v.pack(true, true)?;
+            let qq = pack_file_block("OSMData", &pp, &CompressionType::Zlib)?;
+            wf.call((k, qq));
+        }
+        wf.finish()?;
+        Ok(num_tiles)
+    }
+}
+
+fn write_osc_changes<W: Write, F: FnMut(Changetype)>(
+    mut out: WriteOsc<W>,
+    left_iter: Box<dyn Iterator<Item = Element>>,
+    right_iter: Box<dyn Iterator<Item = Element>>,
+    count: &mut F,
+) -> Result<W> {
+    compare_element_iters_call(left_iter, right_iter, |c| match c {
+        ElementCompare::OnlyLeft(l) => {
+            count(Changetype::Delete);
+            out.write(Changetype::Delete, &l)
+        }
+        ElementCompare::OnlyRight(r) => {
+            count(Changetype::Create);
+            out.write(Changetype::Create, &r)
+        }
+        ElementCompare::DifferentInfo(_, r)
+        | ElementCompare::DifferentTags(_, r)
+        | ElementCompare::DifferentData(_, r) => {
+            count(Changetype::Modify);
+            out.write(Changetype::Modify, &r)
+        }
+        _ => Ok(()),
+    })?;
+    out.finish()
+}
+
+/// Compares two snapshots, each either a pbf file or an osmquadtree directory
+/// merged up to a timestamp, and writes the changes from left to right to
+/// outfn. Elements only in left are deleted, elements only in right are
+/// created and elements with different info, tags or data are modified.
+/// Pbf files must be in id order, otherwise an error is returned: sorted
+/// osmquadtree files should be passed as a directory. Directories are first
+/// written to temporary id-sorted files, using about `ram_gb` GB of ram.
+///
+/// Outputs ending in .osc or .osc.gz are written as osmChange xml. Outputs
+/// ending in .pbfc are split into the tiles of the right snapshot, as
+/// written by update: elements whose quadtree moves them to another tile are
+/// also removed from their old tile.
+pub fn run_diff(
+    left: &str,
+    left_timestamp: Option<&str>,
+    right: &str,
+    right_timestamp: Option<&str>,
+    outfn: &str,
+    numchan: usize,
+    ram_gb: usize,
+) -> Result<()> {
+    let mut tx = LogTimes::new();
+    let left_ts = match left_timestamp {
+        None => None,
+        Some(ts) => Some(parse_timestamp(ts)?),
+    };
+    let right_ts = match right_timestamp {
+        None => None,
+        Some(ts) => Some(parse_timestamp(ts)?),
+    };
+
+    let is_pbfc = outfn.ends_with(".pbfc");
+    if !is_pbfc && !outfn.ends_with(".osc") && !outfn.ends_with(".osc.gz") {
+        return Err(Error::UserSelectionError(format!(
+            "don't know how to write {}: use .osc, .osc.gz or .pbfc",
+            outfn
+        )));
+    }
+
+    let mut temps = TempFiles(Vec::new());
+    let left_iter = snapshot_iter(left, left_timestamp, &format!("{}-left.pbf", outfn), &mut temps, numchan, ram_gb)?;
+    tx.add("read left");
+    let right_iter = snapshot_iter(right, right_timestamp, &format!("{}-right.pbf", outfn), &mut temps, numchan, ram_gb)?;
+    tx.add("read right");
+
+    let mut counts = BTreeMap::new();
+    let mut count = |ct: Changetype| {
+        *counts.entry(action_name(ct)).or_insert(0) += 1;
+    };
+
+    if is_pbfc {
+        let tree = read_tile_tree(right)?;
+        let mut tiles = TileChanges::new(tree, left_ts.unwrap_or(0), right_ts.unwrap_or(0));
+
+        compare_element_iters_call(left_iter, right_iter, |c| match c {
+            ElementCompare::OnlyLeft(l) => {
+                count(Changetype::Delete);
+                let tile = tiles.find_tile(&l);
+                tiles.add(tile, l, Changetype::Delete, false)
+            }
+            ElementCompare::OnlyRight(r) => {
+                count(Changetype::Create);
+                let tile = tiles.find_tile(&r);
+                tiles.add(tile, r, Changetype::Create, true)
+            }
+            ElementCompare::DifferentInfo(l, r)
+            | ElementCompare::DifferentTags(l, r)
+            | ElementCompare::DifferentData(l, r) => {
+                count(Changetype::Modify);
+                tiles.add_changed(l, r, Changetype::Modify)
+            }
+            ElementCompare::DifferentQuadtree(l, r) => tiles.add_changed(l, r, Changetype::Unchanged),
+            _ => Ok(()),
+        })?;
+        tx.add("compare");
+        let num_tiles = tiles.write(outfn)?;
+        tx.add("write pbfc");
+        message!("wrote {} tiles", num_tiles);
+    } else {
+        let out = BufWriter::new(File::create(outfn)?);
+        if outfn.ends_with(".gz") {
+            let gz = flate2::write::GzEncoder::new(out, flate2::Compression::default());
+            let wo = write_osc_changes(WriteOsc::new(gz)?, left_iter, right_iter, &mut count)?;
+            wo.finish()?.flush()?;
+        } else {
+            write_osc_changes(WriteOsc::new(out)?, left_iter, right_iter, &mut count)?;
+        }
+        tx.add("compare and write osc");
+    }
+
+    message!("{:?}", counts);
+    message!("{}", tx);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elements::{Node, Way};
+    use crate::update::read_xml_change;
+
+    fn node(id: i64, version: i64, lon: i32, quadtree: Quadtree) -> Element {
+        let mut n = Node::new(id, Changetype::Normal);
+        let mut info = Info::new();
+        info.version = version;
+        info.timestamp = 1704067200 + version;
+        n.info = Some(info);
+        n.lon = lon;
+        n.lat = 515000000;
+        n.quadtree = quadtree;
+        Element::Node(n)
+    }
+
+    fn way(id: i64, refs: Vec<i64>) -> Element {
+        let mut w = Way::new(id, Changetype::Normal);
+        let mut info = Info::new();
+        info.version = 1;
+        info.timestamp = 1704067200;
+        w.info = Some(info);
+        w.refs = refs;
+        Element::Way(w)
+    }
+
+    fn iter(eles: Vec<Element>) -> Box<dyn Iterator<Item = Element>> {
+        Box::new(eles.into_iter())
+    }
+
+    #[test]
+    fn osc_round_trip() {
+        let q = Quadtree::new(0);
+        let left = vec![node(1, 1, 10, q), node(2, 1, 20, q), way(5, vec![1, 2])];
+        let right = vec![node(1, 2, 11, q), node(3, 1, 30, q), way(5, vec![1, 2])];
+
+        let mut counts = BTreeMap::new();
+        let mut count = |ct: Changetype| {
+            *counts.entry(action_name(ct)).or_insert(0) += 1;
+        };
+        let out = write_osc_changes(WriteOsc::new(Vec::new()).unwrap(), iter(left), iter(right), &mut count).unwrap();
+        assert_eq!(counts.len(), 3);
+
+        let cb = read_xml_change(&mut out.as_slice()).unwrap();
+        assert_eq!(cb.nodes.len(), 3);
+        assert_eq!(cb.nodes[&1].changetype, Changetype::Modify);
+        assert_eq!(cb.nodes[&1].lon, 11);
+        assert_eq!(cb.nodes[&2].changetype, Changetype::Delete);
+        assert_eq!(cb.nodes[&3].changetype, Changetype::Create);
+        assert!(cb.ways.is_empty());
+    }
+
+    #[test]
+    fn unsorted_input_is_error() {
+        let q = Quadtree::new(0);
+        let left = vec![node(2, 1, 20, q), node(1, 1, 10, q)];
+        let right = vec![node(1, 1, 10, q), node(2, 1, 20, q)];
+        let mut count = |_: Changetype| {};
+        match write_osc_changes(WriteOsc::new(Vec::new()).unwrap(), iter(left), iter(right), &mut count) {
+            Err(Error::InvalidDataError(_)) => {}
+            r => panic!("expected InvalidDataError, got {:?}", r.err()),
+        }
+    }
+
+    #[test]
+    fn moved_element_removed_from_old_tile() {
+        let tiles = Quadtree::new(0).children();
+        let mut tree = QuadtreeTree::new();
+        tree.add(&tiles[0], 1);
+        tree.add(&tiles[1], 1);
+        let mut tc = TileChanges::new(tree, 0, 1);
+
+        let left = node(1, 1, 10, tiles[0].children()[2]);
+        let right = node(1, 2, 11, tiles[1].children()[3]);
+        tc.add_changed(left, right, Changetype::Modify).unwrap();
+
+        //a quadtree change within the same tile isn't a change
+        let left = node(2, 1, 10, tiles[0].children()[0]);
+        let right = node(2, 1, 10, tiles[0].children()[1]);
+        tc.add_changed(left, right, Changetype::Unchanged).unwrap();
+
+        assert_eq!(tc.blocks.len(), 2);
+        let old = &tc.blocks[&tiles[0]];
+        assert_eq!(old.nodes.len(), 1);
+        assert_eq!(old.nodes[0].changetype, Changetype::Remove);
+        assert_eq!(old.nodes[0].quadtree, Quadtree::new(0));
+        let new = &tc.blocks[&tiles[1]];
+        assert_eq!(new.nodes.len(), 1);
+        assert_eq!(new.nodes[0].changetype, Changetype::Modify);
+        assert_eq!(new.nodes[0].quadtree, tiles[1].children()[3]);
+    }
+}
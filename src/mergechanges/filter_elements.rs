@@ -1,9 +1,10 @@
 use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, MergeTimings, ReplaceNoneWithTimings, Result as ccResult};
 use crate::elements::{
     Bbox, ElementType, IdSet, IdSetAll, IdSetSet, IdSetBool, MinimalBlock, MinimalNode, MinimalRelation, MinimalWay,
+    Quadtree, QuadtreeCovering, TileOverlap,
 };
 use crate::pbfformat::{
-    make_read_minimal_blocks_combine_call_all, read_all_blocks_parallel_with_progbar, FileBlock,ParallelFileLocs
+    make_read_minimal_blocks_combine_call_all, read_all_blocks_parallel_with_progbar, BadBlocks, FileBlock,ParallelFileLocs
 };
 use crate::utils::{as_int, ThreadTimer, Error, Result};
 use simple_protocolbuffers::{DeltaPackedInt, PackedInt};
@@ -27,14 +28,46 @@ pub struct Poly {
     pub vertsx: Vec<f64>,
     pub vertsy: Vec<f64>,
     pub name: String,
+    /// Set for polygons crossing the antimeridian, whose vertsx are then
+    /// stored from 0 to 360 rather than -180 to 180.
+    pub wraps: bool,
+}
+
+//an edge spanning more than 180 degrees of longitude is taken to cross the
+//antimeridian
+fn ring_crosses_antimeridian(vertsx: &[f64]) -> bool {
+    for i in 0..vertsx.len() {
+        let j = if i == 0 { vertsx.len() - 1 } else { i - 1 };
+        if (vertsx[i] - vertsx[j]).abs() > 180.0 {
+            return true;
+        }
+    }
+    false
+}
+
+fn unwrap_lon(x: f64) -> f64 {
+    if x > 180.0 {
+        x - 360.0
+    } else {
+        x
+    }
 }
 
 impl Poly {
-    pub fn new(vertsx: Vec<f64>, vertsy: Vec<f64>, name: String) -> Poly {
+    pub fn new(mut vertsx: Vec<f64>, vertsy: Vec<f64>, name: String) -> Poly {
+        let wraps = ring_crosses_antimeridian(&vertsx);
+        if wraps {
+            for x in vertsx.iter_mut() {
+                if *x < 0.0 {
+                    *x += 360.0;
+                }
+            }
+        }
         Poly {
             vertsx: vertsx,
             vertsy: vertsy,
-            name: name
+            name: name,
+            wraps: wraps,
         }
     }
 
@@ -72,11 +105,25 @@ impl Poly {
         
     }
 
+    /// Returns a bbox crossing the antimeridian if the polygon does.
     pub fn bounds(&self) -> Bbox {
+        if !self.wraps {
+            let mut bx = Bbox::empty();
+            for (a, b) in self.vertsx.iter().zip(self.vertsy.iter()) {
+                bx.expand(as_int(*a), as_int(*b));
+            }
+            return bx;
+        }
+        let mut minx = f64::MAX;
+        let mut maxx = f64::MIN;
         let mut bx = Bbox::empty();
         for (a, b) in self.vertsx.iter().zip(self.vertsy.iter()) {
-            bx.expand(as_int(*a), as_int(*b));
+            minx = f64::min(minx, *a);
+            maxx = f64::max(maxx, *a);
+            bx.expand(0, as_int(*b));
         }
+        bx.minlon = as_int(unwrap_lon(minx));
+        bx.maxlon = as_int(unwrap_lon(maxx));
         bx
     }
     pub fn check_box(&self, bx: &Bbox) -> bool {
@@ -109,8 +156,11 @@ impl Poly {
           return c;
         }
         */
-        let testx = (ln as f64) * 0.0000001;
+        let mut testx = (ln as f64) * 0.0000001;
         let testy = (lt as f64) * 0.0000001;
+        if self.wraps && testx < 0.0 {
+            testx += 360.0;
+        }
 
         let mut c = false;
         for i in 0..self.vertsx.len() {
@@ -127,6 +177,83 @@ impl Poly {
         }
         c
     }
+
+    fn crosses_box(&self, bx: &Bbox) -> bool {
+        let mut minx = (bx.minlon as f64) * 0.0000001;
+        let miny = (bx.minlat as f64) * 0.0000001;
+        let mut maxx = (bx.maxlon as f64) * 0.0000001;
+        let maxy = (bx.maxlat as f64) * 0.0000001;
+        if self.wraps && maxx <= 0.0 {
+            minx += 360.0;
+            maxx += 360.0;
+        }
+        for i in 0..self.vertsx.len() {
+            let j = if i == 0 { self.vertsx.len() - 1 } else { i - 1 };
+            if segment_crosses_box(
+                self.vertsx[j], self.vertsy[j], self.vertsx[i], self.vertsy[i],
+                minx, miny, maxx, maxy,
+            ) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// If no edge of the polygon crosses `tile`, the tile is either entirely
+    /// inside or entirely outside the polygon.
+    pub fn tile_overlap(&self, tile: &Bbox) -> TileOverlap {
+        if !self.bounds().overlaps(tile) {
+            TileOverlap::Outside
+        } else if self.crosses_box(tile) {
+            TileOverlap::Partial
+        } else if self.contains_point(tile.minlon, tile.minlat) {
+            TileOverlap::Inside
+        } else {
+            TileOverlap::Outside
+        }
+    }
+
+    /// Returns the minimal set of tiles, no deeper than `maxlevel`, which
+    /// cover the polygon.
+    pub fn covering(&self, maxlevel: usize) -> Vec<Quadtree> {
+        QuadtreeCovering::new(maxlevel, 0.0, |t| self.tile_overlap(t)).tiles()
+    }
+}
+
+//Liang-Barsky line clipping
+fn segment_crosses_box(
+    ax: f64, ay: f64, bx: f64, by: f64,
+    minx: f64, miny: f64, maxx: f64, maxy: f64,
+) -> bool {
+    let dx = bx - ax;
+    let dy = by - ay;
+    let mut t0 = 0.0;
+    let mut t1 = 1.0;
+    for (p, q) in [(-dx, ax - minx), (dx, maxx - ax), (-dy, ay - miny), (dy, maxy - ay)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let r = q / p;
+            if p < 0.0 {
+                if r > t1 {
+                    return false;
+                }
+                if r > t0 {
+                    t0 = r;
+                }
+            } else {
+                if r < t0 {
+                    return false;
+                }
+                if r < t1 {
+                    t1 = r;
+                }
+            }
+        }
+    }
+    true
 }
 
 
@@ -179,28 +306,35 @@ impl Display for IdSetEither {
     
     
 impl IdSetEither {    
-    fn add_node(&mut self, i: i64) {
+    pub fn new(bool_idset: bool) -> IdSetEither {
+        if bool_idset {
+            IdSetEither::Bool(IdSetBool::new())
+        } else {
+            IdSetEither::Set(IdSetSet::new())
+        }
+    }
+    pub fn add_node(&mut self, i: i64) {
         match self {
             IdSetEither::Set(ref mut s) =>  {s.nodes.insert(i);},
             IdSetEither::Bool(ref mut b) => {b.nodes.insert(i);},
             IdSetEither::All(ref _a) => {},
         }
     }
-    fn add_exnode(&mut self, i: i64) {
+    pub fn add_exnode(&mut self, i: i64) {
         match self {
             IdSetEither::Set(ref mut s) =>  {s.exnodes.insert(i);},
             IdSetEither::Bool(ref mut b) => {b.exnodes.insert(i);},
             IdSetEither::All(ref _a) => {},
         }
     }
-    fn add_way(&mut self, i: i64) {
+    pub fn add_way(&mut self, i: i64) {
         match self {
             IdSetEither::Set(ref mut s) =>  {s.ways.insert(i);},
             IdSetEither::Bool(ref mut b) => {b.ways.insert(i);},
             IdSetEither::All(ref _a) => {}
         }
     }
-    fn add_relation(&mut self, i: i64) {
+    pub fn add_relation(&mut self, i: i64) {
         match self {
             IdSetEither::Set(ref mut s) =>  {s.relations.insert(i);},
             IdSetEither::Bool(ref mut b) => {b.relations.insert(i);},
@@ -231,15 +365,10 @@ fn has_node<T: IdSet>(idset: &T, w: &MinimalWay) -> bool {
 
 impl FilterObjs {
     pub fn new(bbox: &Bbox, poly: &Option<Poly>, bool_idset: bool) -> FilterObjs {
-        let ids = if bool_idset {
-            IdSetEither::Bool(IdSetBool::new())
-        } else {
-            IdSetEither::Set(IdSetSet::new())
-        };
         FilterObjs {
             bbox: bbox.clone(),
             poly: poly.clone(),
-            idset: ids,
+            idset: IdSetEither::new(bool_idset),
             tm: 0.0,
             pending_rels: Vec::new(),
         }
@@ -307,7 +436,7 @@ impl FilterObjs {
             self.add_way(w);
         }
     }
-    fn add_way(&mut self, w: &MinimalWay) {
+    pub fn add_way(&mut self, w: &MinimalWay) {
         //self.get_idset().ways.insert(w.id);
         self.idset.add_way(w.id);
 
@@ -405,6 +534,7 @@ pub fn prep_bbox_filter(
     bbox: &Bbox,
     poly: &Option<Poly>,
     numchan: usize,
+    bad_blocks: &BadBlocks,
 ) -> Result<Arc<dyn IdSet>> {
     /*let mut pb = ProgBarWrap::new(100);
     pb.set_range(100);
@@ -414,7 +544,7 @@ pub fn prep_bbox_filter(
 
     let conv: Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType=Error>> =
         if numchan == 0 {
-            make_read_minimal_blocks_combine_call_all(fb)
+            make_read_minimal_blocks_combine_call_all(fb, bad_blocks)
         } else {
             let fbb = CallbackSync::new(fb, numchan);
             let mut convs: Vec<
@@ -423,7 +553,7 @@ pub fn prep_bbox_filter(
             for f in fbb {
                 let f2 = Box::new(ReplaceNoneWithTimings::new(f));
                 convs.push(Box::new(Callback::new(
-                    make_read_minimal_blocks_combine_call_all(f2),
+                    make_read_minimal_blocks_combine_call_all(f2, bad_blocks),
                 )));
             }
             Box::new(CallbackMerge::new(convs, Box::new(MergeTimings::new())))
@@ -432,11 +562,13 @@ pub fn prep_bbox_filter(
     //let (mut tm,_) = read_all_blocks_parallel_prog(&mut pfilelocs.0, &pfilelocs.1, conv, &pb);
     let mut tm = read_all_blocks_parallel_with_progbar(
         &mut pfilelocs.0,
+        &pfilelocs.3,
         &pfilelocs.1,
         conv,
         "prep_bbox_filter",
         pfilelocs.2,
-    );
+        bad_blocks,
+    )?;
     //pb.finish();
     Ok(tm.others.pop().unwrap().1)
 }
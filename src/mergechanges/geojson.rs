@@ -0,0 +1,241 @@
+use channelled_callbacks::{CallFinish, Error as ccError, Result as ccResult};
+use crate::elements::{coordinate_as_float, IdSet, IdSetAll, PrimitiveBlock, Tag};
+use crate::geometry::{is_polygon, ring_signed_area, way_lonlats, LonLat};
+use crate::mergechanges::filter_elements::{prep_bbox_filter, read_filter};
+use crate::mergechanges::{collect_blocks_filtered, get_file_locs_and_header};
+use crate::mergechanges::tag_filter::{prep_tag_filter, read_tag_filter};
+use crate::pbfformat::BadBlocks;
+use crate::sortblocks::Timings;
+use crate::utils::{LogTimes, ThreadTimer};
+use crate::utils::{Error, Result};
+use crate::message;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+
+fn coordinates_json(lonlats: &[LonLat]) -> String {
+    let mut res = String::from("[");
+    for (i, ll) in lonlats.iter().enumerate() {
+        if i > 0 {
+            res.push(',');
+        }
+        res.push_str(&format!(
+            "[{:.7},{:.7}]",
+            coordinate_as_float(ll.lon),
+            coordinate_as_float(ll.lat)
+        ));
+    }
+    res.push(']');
+    res
+}
+
+fn properties_json(tags: &[Tag]) -> Result<String> {
+    let mut res = String::from("{");
+    for (i, t) in tags.iter().enumerate() {
+        if i > 0 {
+            res.push(',');
+        }
+        res.push_str(&serde_json::to_string(&t.key)?);
+        res.push(':');
+        res.push_str(&serde_json::to_string(&t.val)?);
+    }
+    res.push('}');
+    Ok(res)
+}
+
+fn feature_json(id: &str, tags: &[Tag], geometry: &str) -> Result<String> {
+    Ok(format!(
+        "{{\"type\":\"Feature\",\"id\":\"{}\",\"properties\":{},\"geometry\":{}}}\n",
+        id,
+        properties_json(tags)?,
+        geometry
+    ))
+}
+
+struct WriteGeoJson {
+    out: BufWriter<File>,
+    locations: HashMap<i64, LonLat>,
+    num_features: usize,
+    num_missing: usize,
+    error: Option<Error>,
+    tm: f64,
+}
+
+impl WriteGeoJson {
+    pub fn new(outfn: &str) -> Result<WriteGeoJson> {
+        Ok(WriteGeoJson {
+            out: BufWriter::new(File::create(outfn)?),
+            locations: HashMap::new(),
+            num_features: 0,
+            num_missing: 0,
+            error: None,
+            tm: 0.0,
+        })
+    }
+
+    fn write_block(&mut self, pb: PrimitiveBlock) -> Result<()> {
+        for n in pb.nodes {
+            let ll = LonLat::new(n.lon, n.lat);
+            self.locations.insert(n.id, ll);
+            if n.tags.is_empty() {
+                continue;
+            }
+            let geometry = format!(
+                "{{\"type\":\"Point\",\"coordinates\":[{:.7},{:.7}]}}",
+                coordinate_as_float(ll.lon),
+                coordinate_as_float(ll.lat)
+            );
+            self.out.write_all(feature_json(&format!("n{}", n.id), &n.tags, &geometry)?.as_bytes())?;
+            self.num_features += 1;
+        }
+
+        for w in pb.ways {
+            if w.tags.is_empty() || w.refs.len() < 2 {
+                continue;
+            }
+            let mut lonlats = match way_lonlats(&w.refs, &w.lonlats, |r| self.locations.get(&r).copied()) {
+                Some(ll) => ll,
+                None => {
+                    self.num_missing += 1;
+                    continue;
+                }
+            };
+
+            let is_closed = w.refs.len() >= 4 && w.refs.first() == w.refs.last();
+            let geometry = if is_closed && is_polygon(&w.tags) {
+                //exterior rings are anticlockwise (RFC 7946)
+                if ring_signed_area(&lonlats) < 0.0 {
+                    lonlats.reverse();
+                }
+                format!("{{\"type\":\"Polygon\",\"coordinates\":[{}]}}", coordinates_json(&lonlats))
+            } else {
+                format!("{{\"type\":\"LineString\",\"coordinates\":{}}}", coordinates_json(&lonlats))
+            };
+            self.out.write_all(feature_json(&format!("w{}", w.id), &w.tags, &geometry)?.as_bytes())?;
+            self.num_features += 1;
+        }
+        Ok(())
+    }
+}
+
+impl CallFinish for WriteGeoJson {
+    type CallType = PrimitiveBlock;
+    type ReturnType = Timings;
+    type ErrorType = Error;
+
+    //blocks are passed in id order, so all nodes arrive before any ways
+    fn call(&mut self, pb: PrimitiveBlock) {
+        if self.error.is_some() {
+            return;
+        }
+        let tx = ThreadTimer::new();
+        match self.write_block(pb) {
+            Ok(()) => {}
+            Err(e) => {
+                self.error = Some(e);
+            }
+        }
+        self.tm += tx.since();
+    }
+
+    fn finish(&mut self) -> ccResult<Timings, Error> {
+        if self.error.is_none() {
+            match self.out.flush() {
+                Ok(()) => {}
+                Err(e) => {
+                    self.error = Some(Error::from(e));
+                }
+            }
+        }
+        match self.error.take() {
+            Some(e) => Err(ccError::OtherError(e)),
+            None => {
+                message!("wrote {} features", self.num_features);
+                if self.num_missing > 0 {
+                    message!("{} ways skipped with missing node locations", self.num_missing);
+                }
+                let mut tms = Timings::new();
+                tms.add("WriteGeoJson", self.tm);
+                Ok(tms)
+            }
+        }
+    }
+}
+
+/// Returns a callback which writes tagged nodes as points, and tagged ways as
+/// linestrings or polygons, to `outfn` as GeoJSON-seq: one feature per line,
+/// with tags as properties. Relations are not written. Blocks must be passed
+/// in id order, with all nodes before any ways.
+pub fn make_write_geojson(
+    outfn: &str,
+) -> Result<Box<impl CallFinish<CallType = PrimitiveBlock, ReturnType = Timings, ErrorType = Error>>> {
+    Ok(Box::new(WriteGeoJson::new(outfn)?))
+}
+
+pub fn run_mergechanges_geojson(
+    inprfx: &str,
+    outfn: &str,
+    filter: Option<&str>,
+    tag_filter: Option<&str>,
+    filterobjs: bool,
+    timestamp: Option<&str>,
+    numchan: usize,
+    ram_gb: usize,
+    history_timestamp: Option<&str>,
+    bad_blocks: &BadBlocks,
+) -> Result<()> {
+    let mut tx = LogTimes::new();
+    let (bbox, poly) = read_filter(filter)?;
+    let tag_filter = read_tag_filter(tag_filter)?;
+    tx.add("read filter");
+    let (mut pfilelocs, history, _) =
+        get_file_locs_and_header(inprfx, Some(bbox.clone()), timestamp, history_timestamp, None)?;
+    tx.add("get_file_locs");
+
+    if pfilelocs.2 > (ram_gb as u64) * 32 * 1024 * 1024 {
+        return Err(Error::UserSelectionError(format!(
+            "extract too big to merge in memory ({:0.1}mb > {:0.1}mb)",
+            (pfilelocs.2 as f64) / 1024.0 / 1024.0,
+            (ram_gb as f64) * 32.0
+        )));
+    }
+
+    let ids: Arc<dyn IdSet> = match (filterobjs, filter) {
+        (true, Some(_)) => {
+            let ids = prep_bbox_filter(&mut pfilelocs, &bbox, &poly, numchan, bad_blocks)?;
+            tx.add("prep_bbox_filter");
+            message!("have: {}", ids);
+            Arc::from(ids)
+        }
+        _ => Arc::new(IdSetAll()),
+    };
+
+    let ids = match &tag_filter {
+        Some(tf) => {
+            let ids = prep_tag_filter(&mut pfilelocs, tf, ids, numchan, bad_blocks)?;
+            tx.add("prep_tag_filter");
+            message!("have: {}", ids);
+            ids
+        }
+        None => ids,
+    };
+
+    let pb = collect_blocks_filtered(&mut pfilelocs, ids, history, numchan, bad_blocks)?;
+    tx.add("collect_blocks_filtered");
+    message!(
+        "have {} nodes, {} ways, {} relations",
+        pb.nodes.len(),
+        pb.ways.len(),
+        pb.relations.len()
+    );
+
+    let mut wf = make_write_geojson(outfn)?;
+    wf.call(pb);
+    let tm = wf.finish()?;
+    tx.add("write geojson");
+    message!("{}", tm);
+    message!("{}", tx);
+    Ok(())
+}
@@ -1,12 +1,15 @@
-use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, MergeTimings, ReplaceNoneWithTimings, Result as ccResult};
+use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, MergeTimings, ReplaceNoneWithTimings, Error as ccError, Result as ccResult};
 use crate::elements::{Bbox, Block, IdSet, IdSetAll, PrimitiveBlock};
+use crate::geometry::UNDEFINED_COORDINATE;
 use crate::mergechanges::filter_elements::{prep_bbox_filter, read_filter};
+use crate::mergechanges::tag_filter::{prep_tag_filter, read_tag_filter};
 use crate::pbfformat::make_read_primitive_blocks_combine_call_all_idset;
-use crate::pbfformat::HeaderType;
-use crate::pbfformat::{read_all_blocks_parallel_prog, FileBlock, CompressionType};
+use crate::pbfformat::{HeaderType, HeaderInfo};
+use crate::pbfformat::{read_all_blocks_parallel_prog, BadBlocks, FileBlock, CompressionType, History};
 use crate::sortblocks::{make_packprimblock_zeroindex, WriteFile};
-use crate::pbfformat::{get_file_locs, ParallelFileLocs};
-use crate::utils::{parse_timestamp, LogTimes, ThreadTimer};
+use crate::pbfformat::ParallelFileLocs;
+use crate::mergechanges::get_file_locs_and_header;
+use crate::utils::{LogTimes, ThreadTimer};
 use crate::{message,progress_percent};
 use crate::utils::{Result,Error};
 use std::sync::Arc;
@@ -59,14 +62,16 @@ impl CallFinish for CollectObjs {
 pub fn collect_blocks_filtered(
     pfilelocs: &mut ParallelFileLocs,
     ids: Arc<dyn IdSet>,
+    history: History,
     numchan: usize,
+    bad_blocks: &BadBlocks,
 ) -> Result<PrimitiveBlock> {
     let pb = progress_percent!("merge blocks");
 
     let conv: Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType = Error>> =
         if numchan == 0 {
             let co = Box::new(CollectObjs::new());
-            make_read_primitive_blocks_combine_call_all_idset(co, ids.clone(), true)
+            make_read_primitive_blocks_combine_call_all_idset(co, ids.clone(), true, history, bad_blocks)
         } else {
             let mut convs: Vec<
                 Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType = Error>>,
@@ -74,13 +79,13 @@ pub fn collect_blocks_filtered(
             for _ in 0..numchan {
                 let co = Box::new(CollectObjs::new());
                 convs.push(Box::new(Callback::new(
-                    make_read_primitive_blocks_combine_call_all_idset(co, ids.clone(), true),
+                    make_read_primitive_blocks_combine_call_all_idset(co, ids.clone(), true, history, bad_blocks),
                 )));
             }
             Box::new(CallbackMerge::new(convs, Box::new(MergeTimings::new())))
         };
 
-    let (tm, _) = read_all_blocks_parallel_prog(&mut pfilelocs.0, &pfilelocs.1, conv, pb);
+    let (tm, _) = read_all_blocks_parallel_prog(&mut pfilelocs.0, &pfilelocs.3, &pfilelocs.1, conv, pb, bad_blocks)?;
     //pb.finish();
 
     let mut res = PrimitiveBlock::new(0, 0);
@@ -166,20 +171,132 @@ where
 
 
 
+/// Number of node locations [`AddLocationsOnWays`] may hold for each GB of
+/// the ram_gb setting. Each location takes 16 bytes, so this uses at most
+/// half the memory allowed.
+pub const LOCATIONS_PER_GB: usize = 32 * 1024 * 1024;
+
+/// Fills in [`Way::lonlats`](crate::elements::Way) for the LocationsOnWays
+/// feature. Blocks are passed in id order, so all nodes arrive before any
+/// ways: node locations are kept in a vector sorted by id, and an error is
+/// returned if there are more than max_locations nodes.
+struct AddLocationsOnWays<T: ?Sized> {
+    out: Box<T>,
+    locations: Option<Vec<(i64, i32, i32)>>,
+    max_locations: usize,
+    is_sorted: bool,
+    num_missing: usize,
+    error: Option<Error>,
+    tm: f64,
+}
+
+impl<T> AddLocationsOnWays<T>
+where
+    T: CallFinish<CallType = PrimitiveBlock, ReturnType = crate::sortblocks::Timings, ErrorType=Error> + ?Sized,
+{
+    pub fn new(out: Box<T>, max_locations: Option<usize>) -> AddLocationsOnWays<T> {
+        AddLocationsOnWays {
+            out: out,
+            locations: match max_locations { Some(_) => Some(Vec::new()), None => None },
+            max_locations: max_locations.unwrap_or(0),
+            is_sorted: true,
+            num_missing: 0,
+            error: None,
+            tm: 0.0,
+        }
+    }
+}
+
+impl<T> CallFinish for AddLocationsOnWays<T>
+where
+    T: CallFinish<CallType = PrimitiveBlock, ReturnType = crate::sortblocks::Timings, ErrorType = Error> + ?Sized,
+{
+    type CallType = PrimitiveBlock;
+    type ReturnType = crate::sortblocks::Timings;
+    type ErrorType = Error;
+
+    fn call(&mut self, mut pb: PrimitiveBlock) {
+        if self.error.is_some() {
+            return;
+        }
+        let tx = ThreadTimer::new();
+        match self.locations.as_mut() {
+            None => {}
+            Some(locations) => {
+                if locations.len() + pb.nodes.len() > self.max_locations {
+                    self.error = Some(Error::UserSelectionError(format!(
+                        "too many nodes to add locations on ways: more than {}, increase ram_gb",
+                        self.max_locations
+                    )));
+                    return;
+                }
+                for n in &pb.nodes {
+                    match locations.last() {
+                        Some((i, _, _)) if *i >= n.id => { self.is_sorted = false; },
+                        _ => {}
+                    }
+                    locations.push((n.id, n.lon, n.lat));
+                }
+                if !pb.ways.is_empty() && !self.is_sorted {
+                    locations.sort_by_key(|(i, _, _)| *i);
+                    self.is_sorted = true;
+                }
+                for w in pb.ways.iter_mut() {
+                    let mut lonlats = Vec::with_capacity(w.refs.len());
+                    for r in &w.refs {
+                        match locations.binary_search_by_key(r, |(i, _, _)| *i) {
+                            Ok(j) => lonlats.push((locations[j].1, locations[j].2)),
+                            Err(_) => {
+                                self.num_missing += 1;
+                                lonlats.push((UNDEFINED_COORDINATE, UNDEFINED_COORDINATE));
+                            }
+                        }
+                    }
+                    w.lonlats = lonlats;
+                }
+            }
+        }
+        self.tm += tx.since();
+        self.out.call(pb);
+    }
+
+    fn finish(&mut self) -> ccResult<Self::ReturnType, Self::ErrorType> {
+        let mut tms = self.out.finish()?;
+        match self.error.take() {
+            Some(e) => Err(ccError::OtherError(e)),
+            None => {
+                if self.num_missing > 0 {
+                    message!("{} way node locations missing", self.num_missing);
+                }
+                tms.add("AddLocationsOnWays", self.tm);
+                Ok(tms)
+            }
+        }
+    }
+}
+
+/// Returns a callback writing blocks to outfn. If locations_on_ways is
+/// given, way node locations are added for up to that many nodes.
 pub fn make_write_file(
     outfn: &str,
     bbox: &Bbox,
     block_size: usize,
     compression_type: CompressionType,
+    header_info: &HeaderInfo,
+    locations_on_ways: Option<usize>,
     numchan: usize,
 ) -> Box<impl CallFinish<CallType = PrimitiveBlock, ReturnType = crate::sortblocks::Timings, ErrorType=Error>> {
-    let wf = Box::new(WriteFile::with_compression_type(
-        outfn, HeaderType::NoLocs, Some(bbox), compression_type));
+    let mut header_info = header_info.clone();
+    if locations_on_ways.is_some() {
+        header_info.optional_features.push(String::from("LocationsOnWays"));
+    }
+    let wf = Box::new(WriteFile::with_header_info(
+        outfn, HeaderType::NoLocs, Some(bbox), compression_type, &header_info));
 
     let pack: Box<
         dyn CallFinish<CallType = PrimitiveBlock, ReturnType = crate::sortblocks::Timings, ErrorType = Error>,
     > = if numchan == 0 {
-        make_packprimblock_zeroindex(wf, false, compression_type)
+        make_packprimblock_zeroindex(wf, false, locations_on_ways.is_some(), compression_type)
     } else {
         let wff = CallbackSync::new(wf, 4);
         let mut packs: Vec<
@@ -188,13 +305,13 @@ pub fn make_write_file(
         for w in wff {
             let w2 = Box::new(ReplaceNoneWithTimings::new(w));
             packs.push(Box::new(Callback::new(make_packprimblock_zeroindex(
-                w2, false, compression_type.clone()
+                w2, false, locations_on_ways.is_some(), compression_type.clone()
             ))));
         }
 
         Box::new(CallbackMerge::new(packs, Box::new(MergeTimings::new())))
     };
-    Box::new(GroupBlocks::new(pack, block_size))
+    Box::new(AddLocationsOnWays::new(Box::new(GroupBlocks::new(pack, block_size)), locations_on_ways))
 }
 
 
@@ -203,21 +320,23 @@ pub fn run_mergechanges_sort_inmem(
     inprfx: &str,
     outfn: &str,
     filter: Option<&str>,
+    tag_filter: Option<&str>,
     filterobjs: bool,
     timestamp: Option<&str>,
     numchan: usize,
     ram_gb: usize,
-    compression_type: CompressionType
+    compression_type: CompressionType,
+    locations_on_ways: bool,
+    history_timestamp: Option<&str>,
+    writing_program: Option<&str>,
+    bad_blocks: &BadBlocks,
 ) -> Result<()> {
     let mut tx = LogTimes::new();
     let (bbox, poly) = read_filter(filter)?;
+    let tag_filter = read_tag_filter(tag_filter)?;
     tx.add("read filter");
-    let timestamp = match timestamp {
-        None => None,
-        Some(ts) => Some(parse_timestamp(ts)?),
-    };
-
-    let mut pfilelocs = get_file_locs(inprfx, Some(bbox.clone()), timestamp)?;
+    let (mut pfilelocs, history, header_info) =
+        get_file_locs_and_header(inprfx, Some(bbox.clone()), timestamp, history_timestamp, writing_program)?;
     tx.add("get_file_locs");
     
     if pfilelocs.2 > (ram_gb as u64)*32*1024*1024 {
@@ -233,7 +352,7 @@ pub fn run_mergechanges_sort_inmem(
         if filterobjs {
             match filter {
                 Some(_) => {
-                    let ids = prep_bbox_filter(&mut pfilelocs, &bbox, &poly, numchan)?;
+                    let ids = prep_bbox_filter(&mut pfilelocs, &bbox, &poly, numchan, bad_blocks)?;
                     tx.add("prep_bbox_filter");
                     message!("have: {}", ids);
                     Arc::from(ids)
@@ -246,20 +365,35 @@ pub fn run_mergechanges_sort_inmem(
             
             Arc::new(IdSetAll())
         };
+
+    let ids = match &tag_filter {
+        Some(tf) => {
+            let ids = prep_tag_filter(&mut pfilelocs, tf, ids, numchan, bad_blocks)?;
+            tx.add("prep_tag_filter");
+            message!("have: {}", ids);
+            ids
+        }
+        None => ids,
+    };
     
-    call_mergechanges_sort_inmem(&mut pfilelocs, outfn, ids, &bbox, compression_type, tx, numchan)
+    let locations_on_ways = if locations_on_ways { Some(ram_gb * LOCATIONS_PER_GB) } else { None };
+    call_mergechanges_sort_inmem(&mut pfilelocs, outfn, ids, history, &bbox, compression_type, &header_info, locations_on_ways, tx, numchan, bad_blocks)
 }
 
 pub fn call_mergechanges_sort_inmem(
     pfilelocs: &mut ParallelFileLocs,
     outfn: &str,
     ids: Arc<dyn IdSet>,
+    history: History,
     bbox: &Bbox,
     compression_type: CompressionType,
+    header_info: &HeaderInfo,
+    locations_on_ways: Option<usize>,
     mut tx: LogTimes,
-    numchan: usize) -> Result<()> {
+    numchan: usize,
+    bad_blocks: &BadBlocks) -> Result<()> {
 
-    let pb = collect_blocks_filtered(pfilelocs, ids.clone(), numchan)?;
+    let pb = collect_blocks_filtered(pfilelocs, ids.clone(), history, numchan, bad_blocks)?;
     tx.add("collect_blocks_filtered");
     message!(
         "have {} nodes, {} ways, {} relations",
@@ -268,7 +402,7 @@ pub fn call_mergechanges_sort_inmem(
         pb.relations.len()
     );
 
-    let mut gb = make_write_file(outfn, &bbox, 8000, compression_type, numchan);
+    let mut gb = make_write_file(outfn, &bbox, 8000, compression_type, header_info, locations_on_ways, numchan);
     gb.call(pb);
     let tm = gb.finish()?;
     tx.add("write");
@@ -1,10 +1,52 @@
+mod diff;
 mod filter_elements;
+mod geojson;
 mod inmem;
+mod tag_filter;
 mod writetemp;
 
-pub use crate::mergechanges::inmem::{make_write_file, run_mergechanges_sort_inmem, call_mergechanges_sort_inmem, collect_blocks_filtered};
+use crate::elements::Bbox;
+use crate::pbfformat::{get_file_locs, HeaderInfo, History, ParallelFileLocs};
+use crate::update::get_replication_header_info;
+use crate::utils::{parse_timestamp, Result};
+
+pub use crate::mergechanges::inmem::{make_write_file, run_mergechanges_sort_inmem, LOCATIONS_PER_GB, call_mergechanges_sort_inmem, collect_blocks_filtered};
 pub use crate::mergechanges::writetemp::{
     run_mergechanges, run_mergechanges_sort, run_mergechanges_sort_from_existing, call_mergechanges_sort, call_mergechanges
 };
 
+pub use crate::mergechanges::diff::run_diff;
+pub use crate::mergechanges::geojson::{make_write_geojson, run_mergechanges_geojson};
 pub use crate::mergechanges::filter_elements::{prep_bbox_filter, Poly, read_filter};
+pub use crate::mergechanges::tag_filter::{prep_tag_filter, read_tag_filter, TagFilter};
+
+/// Opens the files of `inprfx` up to `timestamp` with [`get_file_locs`], and
+/// returns them with the [`History`] for reading them and the header for the
+/// merged output.
+pub fn get_file_locs_and_header(
+    inprfx: &str,
+    filter: Option<Bbox>,
+    timestamp: Option<&str>,
+    history_timestamp: Option<&str>,
+    writing_program: Option<&str>,
+) -> Result<(ParallelFileLocs, History, HeaderInfo)> {
+    let timestamp = match timestamp {
+        None => None,
+        Some(ts) => Some(parse_timestamp(ts)?),
+    };
+    let history_timestamp = match history_timestamp {
+        None => None,
+        Some(ts) => Some(parse_timestamp(ts)?),
+    };
+
+    let pfilelocs = get_file_locs(inprfx, filter, timestamp)?;
+    let history = History::from_file(&pfilelocs.3[0], history_timestamp)?;
+
+    let mut header_info = get_replication_header_info(inprfx, timestamp)?;
+    header_info.history = history == History::All;
+    match writing_program {
+        Some(w) => { header_info.writingprogram = String::from(w); },
+        None => {}
+    }
+    Ok((pfilelocs, history, header_info))
+}
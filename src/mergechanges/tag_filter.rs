@@ -0,0 +1,323 @@
+use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, MergeTimings, ReplaceNoneWithTimings, Result as ccResult};
+use crate::elements::{ElementType, IdSet, Node, PrimitiveBlock, Relation, Tag, Way};
+use crate::mergechanges::filter_elements::IdSetEither;
+use crate::pbfformat::{
+    make_read_primitive_blocks_combine_call_all, read_all_blocks_parallel_with_progbar, BadBlocks, FileBlock, History, ParallelFileLocs
+};
+use crate::utils::{ThreadTimer, Error, Result};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+type Timings = channelled_callbacks::Timings<Arc<dyn IdSet>>;
+
+#[derive(Debug, Clone)]
+struct TagFilterRule {
+    nodes: bool,
+    ways: bool,
+    relations: bool,
+    negate: bool,
+    key: String,
+    vals: Vec<String>,
+}
+
+impl TagFilterRule {
+    fn parse(rule: &str) -> Result<TagFilterRule> {
+        let (types, rest) = match rule.find('/') {
+            Some(p) if p > 0 && rule[..p].chars().all(|c| c == 'n' || c == 'w' || c == 'r') => {
+                (&rule[..p], &rule[p + 1..])
+            }
+            _ => ("nwr", rule),
+        };
+        let (negate, rest) = match rest.strip_prefix('!') {
+            Some(r) => (true, r),
+            None => (false, rest),
+        };
+        let (key, vals) = match rest.find('=') {
+            Some(p) => (
+                &rest[..p],
+                rest[p + 1..].split(',').map(|v| v.trim().to_string()).collect::<Vec<String>>(),
+            ),
+            None => (rest, Vec::new()),
+        };
+        let key = key.trim();
+        if key.is_empty() || vals.iter().any(|v| v.is_empty()) {
+            return Err(Error::UserSelectionError(format!("invalid tag filter rule {:?}", rule)));
+        }
+
+        Ok(TagFilterRule {
+            nodes: types.contains('n'),
+            ways: types.contains('w'),
+            relations: types.contains('r'),
+            negate: negate,
+            key: key.to_string(),
+            vals: vals,
+        })
+    }
+
+    fn applies_to(&self, t: ElementType) -> bool {
+        match t {
+            ElementType::Node => self.nodes,
+            ElementType::Way => self.ways,
+            ElementType::Relation => self.relations,
+            _ => false,
+        }
+    }
+
+    fn matches(&self, tags: &[Tag]) -> bool {
+        for t in tags {
+            if t.key.as_ref() == self.key {
+                return self.vals.is_empty() || self.vals.iter().any(|v| v.as_str() == t.val.as_ref());
+            }
+        }
+        false
+    }
+}
+
+impl fmt::Display for TagFilterRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.nodes {
+            write!(f, "n")?;
+        }
+        if self.ways {
+            write!(f, "w")?;
+        }
+        if self.relations {
+            write!(f, "r")?;
+        }
+        write!(f, "/{}{}", if self.negate { "!" } else { "" }, self.key)?;
+        if !self.vals.is_empty() {
+            write!(f, "={}", self.vals.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Selects elements by their tags. A filter is a list of rules separated by
+/// `;`, each `[TYPES/][!]KEY[=VAL1,VAL2,...]`, where TYPES is some of `n`,
+/// `w` and `r` (all three if omitted), e.g. `w/highway=primary,secondary`,
+/// `n/amenity`, `r/type=route` or `!building`. An element is selected if it
+/// matches any rule without `!` for its type (or there are no such rules),
+/// and doesn't match any rule with `!`.
+#[derive(Debug, Clone)]
+pub struct TagFilter {
+    rules: Vec<TagFilterRule>,
+}
+
+impl TagFilter {
+    pub fn from_str(filter: &str) -> Result<TagFilter> {
+        let mut rules = Vec::new();
+        for r in filter.split(';') {
+            let r = r.trim();
+            if !r.is_empty() {
+                rules.push(TagFilterRule::parse(r)?);
+            }
+        }
+        if rules.is_empty() {
+            return Err(Error::UserSelectionError(format!("empty tag filter {:?}", filter)));
+        }
+        Ok(TagFilter { rules: rules })
+    }
+
+    pub fn matches(&self, t: ElementType, tags: &[Tag]) -> bool {
+        let mut has_positive = false;
+        let mut positive = false;
+        for r in &self.rules {
+            if r.negate {
+                if r.applies_to(t) && r.matches(tags) {
+                    return false;
+                }
+            } else {
+                has_positive = true;
+                if !positive && r.applies_to(t) && r.matches(tags) {
+                    positive = true;
+                }
+            }
+        }
+        positive || !has_positive
+    }
+}
+
+impl fmt::Display for TagFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, r) in self.rules.iter().enumerate() {
+            if i > 0 {
+                write!(f, ";")?;
+            }
+            write!(f, "{}", r)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn read_tag_filter(tag_filter: Option<&str>) -> Result<Option<TagFilter>> {
+    match tag_filter {
+        None => Ok(None),
+        Some(tf) => Ok(Some(TagFilter::from_str(tf)?)),
+    }
+}
+
+struct TagFilterObjs {
+    filter: TagFilter,
+    base: Arc<dyn IdSet>,
+    idset: IdSetEither,
+    wanted_ways: HashSet<i64>,
+    wanted_relations: HashSet<i64>,
+    tm: f64,
+}
+
+impl TagFilterObjs {
+    pub fn new(filter: &TagFilter, base: Arc<dyn IdSet>, bool_idset: bool) -> TagFilterObjs {
+        TagFilterObjs {
+            filter: filter.clone(),
+            base: base,
+            idset: IdSetEither::new(bool_idset),
+            wanted_ways: HashSet::new(),
+            wanted_relations: HashSet::new(),
+            tm: 0.0,
+        }
+    }
+
+    fn check_node(&mut self, n: &Node) {
+        if self.base.contains(ElementType::Node, n.id) && self.filter.matches(ElementType::Node, &n.tags) {
+            self.idset.add_node(n.id);
+        }
+    }
+
+    fn check_way(&mut self, w: &Way) {
+        if self.wanted_ways.remove(&w.id)
+            || (self.base.contains(ElementType::Way, w.id) && self.filter.matches(ElementType::Way, &w.tags))
+        {
+            self.idset.add_way(w.id);
+            for n in &w.refs {
+                self.idset.add_node(*n);
+            }
+        }
+    }
+
+    fn add_relation(&mut self, r: &Relation) {
+        self.idset.add_relation(r.id);
+        for m in &r.members {
+            match m.mem_type {
+                ElementType::Node => {
+                    self.idset.add_node(m.mem_ref);
+                }
+                ElementType::Way => {
+                    if !self.idset.contains(ElementType::Way, m.mem_ref) {
+                        self.idset.add_way(m.mem_ref);
+                        self.wanted_ways.insert(m.mem_ref);
+                    }
+                }
+                ElementType::Relation => {
+                    if !self.idset.contains(ElementType::Relation, m.mem_ref) {
+                        self.wanted_relations.insert(m.mem_ref);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    //blocks are passed in quadtree order, and a relation's members are in
+    //the relation's tile or its children, so each member arrives after the
+    //relation which references it
+    fn check_block(&mut self, pb: PrimitiveBlock) {
+        for n in &pb.nodes {
+            self.check_node(n);
+        }
+
+        let mut pending = Vec::new();
+        for r in &pb.relations {
+            if self.wanted_relations.remove(&r.id)
+                || (self.base.contains(ElementType::Relation, r.id)
+                    && self.filter.matches(ElementType::Relation, &r.tags))
+            {
+                self.add_relation(r);
+            } else {
+                pending.push(r);
+            }
+        }
+        //child relations in the same block may come before their parent
+        loop {
+            let mut found = Vec::new();
+            pending.retain(|r| {
+                if self.wanted_relations.contains(&r.id) {
+                    found.push(*r);
+                    false
+                } else {
+                    true
+                }
+            });
+            if found.is_empty() {
+                break;
+            }
+            for r in found {
+                self.wanted_relations.remove(&r.id);
+                self.add_relation(r);
+            }
+        }
+
+        for w in &pb.ways {
+            self.check_way(w);
+        }
+    }
+}
+
+impl CallFinish for TagFilterObjs {
+    type CallType = PrimitiveBlock;
+    type ReturnType = Timings;
+    type ErrorType = Error;
+    fn call(&mut self, pb: PrimitiveBlock) {
+        let tx = ThreadTimer::new();
+        self.check_block(pb);
+        self.tm += tx.since();
+    }
+
+    fn finish(&mut self) -> ccResult<Timings, Error> {
+        let mut tm = Timings::new();
+        tm.add("TagFilterObjs::call", self.tm);
+
+        let aa: Arc<dyn IdSet> = Arc::from(std::mem::replace(&mut self.idset, IdSetEither::new(false)));
+        tm.add_other("idset", aa);
+        Ok(tm)
+    }
+}
+
+/// Finds the elements of `pfilelocs` in `base` selected by `filter`, along
+/// with the nodes of selected ways and the members of selected relations.
+pub fn prep_tag_filter(
+    pfilelocs: &mut ParallelFileLocs,
+    filter: &TagFilter,
+    base: Arc<dyn IdSet>,
+    numchan: usize,
+    bad_blocks: &BadBlocks,
+) -> Result<Arc<dyn IdSet>> {
+    let fb = Box::new(TagFilterObjs::new(filter, base, pfilelocs.2 > 512 * 1024 * 1024));
+
+    let conv: Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType = Error>> =
+        if numchan == 0 {
+            make_read_primitive_blocks_combine_call_all(fb, History::None, bad_blocks)
+        } else {
+            let fbb = CallbackSync::new(fb, numchan);
+            let mut convs: Vec<
+                Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType = Error>>,
+            > = Vec::new();
+            for f in fbb {
+                let f2 = Box::new(ReplaceNoneWithTimings::new(f));
+                convs.push(Box::new(Callback::new(make_read_primitive_blocks_combine_call_all(f2, History::None, bad_blocks))));
+            }
+            Box::new(CallbackMerge::new(convs, Box::new(MergeTimings::new())))
+        };
+
+    let mut tm = read_all_blocks_parallel_with_progbar(
+        &mut pfilelocs.0,
+        &pfilelocs.3,
+        &pfilelocs.1,
+        conv,
+        "prep_tag_filter",
+        pfilelocs.2,
+        bad_blocks,
+    )?;
+    Ok(tm.others.pop().unwrap().1)
+}
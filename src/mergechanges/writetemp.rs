@@ -3,19 +3,19 @@ use crate::elements::{
     Bbox, Block, IdSet, IdSetAll, Node, PrimitiveBlock, Quadtree, Relation, Way, WithId,
 };
 use crate::mergechanges::filter_elements::prep_bbox_filter;
-use crate::mergechanges::{make_write_file, read_filter};
+use crate::mergechanges::{get_file_locs_and_header, make_write_file, prep_tag_filter, read_filter, read_tag_filter, LOCATIONS_PER_GB};
 use crate::pbfformat::make_read_primitive_blocks_combine_call_all_idset;
-use crate::pbfformat::HeaderType;
-use crate::pbfformat::{read_all_blocks_parallel_with_progbar, FileBlock, CompressionType};
+use crate::pbfformat::{HeaderType, HeaderInfo};
+use crate::pbfformat::{read_all_blocks_parallel_with_progbar, BadBlocks, FileBlock, CompressionType, History};
 use crate::sortblocks::{make_packprimblock_many, make_packprimblock_qtindex};
 use crate::sortblocks::{
     read_temp_data, read_tempfile_locs, read_tempfilesplit_locs, write_tempfile_locs,
     write_tempfilesplit_locs, WriteTempData, WriteTempFile, WriteTempFileSplit, WriteTempNull
 };
 use crate::sortblocks::{OtherData, TempData, Timings, WriteFile};
-use crate::pbfformat::{get_file_locs, ParallelFileLocs};
+use crate::pbfformat::ParallelFileLocs;
 use crate::utils::{
-    parse_timestamp, LogTimes, ThreadTimer,
+    LogTimes, ThreadTimer,
 };
 use crate::message;
 
@@ -253,11 +253,13 @@ where
 pub fn write_temp_blocks(
     pfilelocs: &mut ParallelFileLocs,
     ids: Arc<dyn IdSet>,
+    history: History,
     tempfn: &str,
     write_at: usize,
     splitat: (i64, i64, i64),
     fsplit: i64,
     numchan: usize,
+    bad_blocks: &BadBlocks,
 ) -> Result<TempData> {
     let wt: Box<dyn CallFinish< CallType = Vec<(i64, Vec<u8>)>, ReturnType = Timings, ErrorType=Error>> = 
         if tempfn == "NONE" {
@@ -288,7 +290,7 @@ pub fn write_temp_blocks(
             let pc = make_packprimblock_many(wt, true, CompressionType::Zlib);
             let cc = Box::new(CollectTemp::new(pc, 0, splitat, write_at));
             /*let pp =*/
-            make_read_primitive_blocks_combine_call_all_idset(cc, ids.clone(), true)
+            make_read_primitive_blocks_combine_call_all_idset(cc, ids.clone(), true, history, bad_blocks)
 
         //read_all_blocks_parallel_prog(&mut pfilelocs.0, &pfilelocs.1, pp, &prog)
         } else {
@@ -301,7 +303,7 @@ pub fn write_temp_blocks(
                 let pc = make_packprimblock_many(wt2, true, CompressionType::Zlib);
                 let cc = Box::new(CollectTemp::new(pc, 0, splitat, write_at / numchan));
                 pcs.push(Box::new(Callback::new(
-                    make_read_primitive_blocks_combine_call_all_idset(cc, ids.clone(), true),
+                    make_read_primitive_blocks_combine_call_all_idset(cc, ids.clone(), true, history, bad_blocks),
                 )));
             }
             Box::new(CallbackMerge::new(pcs, Box::new(MergeTimings::new())))
@@ -313,11 +315,13 @@ pub fn write_temp_blocks(
     let msg = format!("write_temp_blocks to {}, numchan={}", tempfn, numchan);
     let mut res = read_all_blocks_parallel_with_progbar(
         &mut pfilelocs.0,
+        &pfilelocs.3,
         &pfilelocs.1,
         pp,
         &msg,
         pfilelocs.2,
-    );
+        bad_blocks,
+    )?;
 
     for (_, b) in std::mem::take(&mut res.others) {
         match b {
@@ -346,6 +350,7 @@ pub fn run_mergechanges_sort(
     outfn: &str,
     tempfn: Option<&str>,
     filter: Option<&str>,
+    tag_filter: Option<&str>,
     filter_objs: bool,
     timestamp: Option<&str>,
     keep_temps: bool,
@@ -353,25 +358,26 @@ pub fn run_mergechanges_sort(
     numchan: usize,
     ram_gb: usize,
     single_temp_file: bool,
+    locations_on_ways: bool,
+    history_timestamp: Option<&str>,
+    writing_program: Option<&str>,
+    bad_blocks: &BadBlocks,
 ) -> Result<()> {
     let mut tx = LogTimes::new();
     let (bbox, poly) = read_filter(filter)?;
+    let tag_filter = read_tag_filter(tag_filter)?;
 
     message!("bbox={}, poly={:?}", bbox, poly);
 
     tx.add("read filter");
-    let timestamp = match timestamp {
-        None => None,
-        Some(ts) => Some(parse_timestamp(ts)?),
-    };
-
-    let mut pfilelocs = get_file_locs(inprfx, Some(bbox.clone()), timestamp)?;
+    let (mut pfilelocs, history, header_info) =
+        get_file_locs_and_header(inprfx, Some(bbox.clone()), timestamp, history_timestamp, writing_program)?;
     tx.add("get_file_locs");
 
     let ids: Arc<dyn IdSet> = match filter {
         Some(_) => {
             if filter_objs {
-                let ids = prep_bbox_filter(&mut pfilelocs, &bbox, &poly, numchan)?;
+                let ids = prep_bbox_filter(&mut pfilelocs, &bbox, &poly, numchan, bad_blocks)?;
                 tx.add("prep_bbox_filter");
                 message!("have: {}", ids);
                 Arc::from(ids)
@@ -382,6 +388,16 @@ pub fn run_mergechanges_sort(
         None => Arc::new(IdSetAll()),
     };
 
+    let ids = match &tag_filter {
+        Some(tf) => {
+            let ids = prep_tag_filter(&mut pfilelocs, tf, ids, numchan, bad_blocks)?;
+            tx.add("prep_tag_filter");
+            message!("have: {}", ids);
+            ids
+        }
+        None => ids,
+    };
+
     let tempfn = match tempfn {
         Some(t) => t.to_string(),
         None => {
@@ -399,7 +415,8 @@ pub fn run_mergechanges_sort(
         0
     };
 
-    call_mergechanges_sort(&mut pfilelocs, outfn, &tempfn, limit, fsplit, ids, &bbox, keep_temps, compression_type, tx, numchan, ram_gb)
+    let locations_on_ways = if locations_on_ways { Some(ram_gb * LOCATIONS_PER_GB) } else { None };
+    call_mergechanges_sort(&mut pfilelocs, outfn, &tempfn, limit, fsplit, ids, history, &bbox, keep_temps, compression_type, &header_info, locations_on_ways, tx, numchan, ram_gb, bad_blocks)
 }
 
 pub fn call_mergechanges_sort(
@@ -409,23 +426,29 @@ pub fn call_mergechanges_sort(
     limit: usize,
     fsplit: i64,
     ids: Arc<dyn IdSet>,
+    history: History,
     bbox: &Bbox,
     keep_temps: bool,
     compression_type: CompressionType,
+    header_info: &HeaderInfo,
+    locations_on_ways: Option<usize>,
     mut tx: LogTimes,
     numchan: usize,
     _ram_gb: usize,
+    bad_blocks: &BadBlocks,
 ) -> Result<()> {
 
 
     let temps = write_temp_blocks(
         pfilelocs,
         ids.clone(),
+        history,
         tempfn,
         limit,
         (1i64 << 21, 1i64 << 18, 1i64 << 17),
         fsplit,
         numchan,
+        bad_blocks,
     )?;
     tx.add("write_temp_blocks");
     match &temps {
@@ -469,7 +492,7 @@ pub fn call_mergechanges_sort(
         }
     }
 
-    let wf = make_write_file(outfn, bbox, 8000, compression_type, numchan);
+    let wf = make_write_file(outfn, bbox, 8000, compression_type, header_info, locations_on_ways, numchan);
 
     let res = if numchan == 0 {
         read_temp_data(
@@ -522,7 +545,7 @@ pub fn run_mergechanges_sort_from_existing(
 
     tx.add("load filelocs");
 
-    let wf = make_write_file(outfn, &bbox, 8000, compression_type, numchan);
+    let wf = make_write_file(outfn, &bbox, 8000, compression_type, &HeaderInfo::new(), None, numchan);
 
     let res = if numchan == 0 {
         read_temp_data(
@@ -562,56 +585,70 @@ pub fn run_mergechanges(
     inprfx: &str,
     outfn: &str,
     filter: Option<&str>,
+    tag_filter: Option<&str>,
     filter_objs: bool,
     timestamp: Option<&str>,
     compression_type: CompressionType,
     numchan: usize,
+    history_timestamp: Option<&str>,
+    writing_program: Option<&str>,
+    bad_blocks: &BadBlocks,
 ) -> Result<()> {
     let mut tx = LogTimes::new();
     let (bbox, poly) = read_filter(filter)?;
+    let tag_filter = read_tag_filter(tag_filter)?;
 
     message!("bbox={}, poly={:?}", bbox, poly);
 
     tx.add("read filter");
-    let timestamp = match timestamp {
-        None => None,
-        Some(ts) => Some(parse_timestamp(ts)?),
-    };
-
-    let mut pfilelocs = get_file_locs(inprfx, Some(bbox.clone()), timestamp)?;
+    let (mut pfilelocs, history, header_info) =
+        get_file_locs_and_header(inprfx, Some(bbox.clone()), timestamp, history_timestamp, writing_program)?;
     tx.add("get_file_locs");
 
     let ids: Arc<dyn IdSet> = match (filter_objs, filter) {
         (true, Some(_)) => {
-            let ids = prep_bbox_filter(&mut pfilelocs, &bbox, &poly, numchan)?;
+            let ids = prep_bbox_filter(&mut pfilelocs, &bbox, &poly, numchan, bad_blocks)?;
             tx.add("prep_bbox_filter");
             message!("have: {}", ids);
             Arc::from(ids)
         }
         _ => Arc::new(IdSetAll()),
     };
+
+    let ids = match &tag_filter {
+        Some(tf) => {
+            let ids = prep_tag_filter(&mut pfilelocs, tf, ids, numchan, bad_blocks)?;
+            tx.add("prep_tag_filter");
+            message!("have: {}", ids);
+            ids
+        }
+        None => ids,
+    };
     
-    call_mergechanges(&mut pfilelocs, outfn, ids, &bbox, compression_type, tx, numchan)
+    call_mergechanges(&mut pfilelocs, outfn, ids, history, &bbox, compression_type, &header_info, tx, numchan, bad_blocks)
 }
 
 pub fn call_mergechanges(
     pfilelocs: &mut ParallelFileLocs,
     outfn: &str,
     ids: Arc<dyn IdSet>,
+    history: History,
     bbox: &Bbox,
     compression_type: CompressionType,
+    header_info: &HeaderInfo,
     mut tx: LogTimes,
-    numchan: usize) -> Result<()> {
+    numchan: usize,
+    bad_blocks: &BadBlocks) -> Result<()> {
     
     
-    let wf = Box::new(WriteFile::with_compression_type(
-            &outfn, HeaderType::ExternalLocs, Some(bbox), compression_type
+    let wf = Box::new(WriteFile::with_header_info(
+            &outfn, HeaderType::ExternalLocs, Some(bbox), compression_type, header_info
         ));
 
     let pp: Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType=Error>> =
         if numchan == 0 {
             let pc = make_packprimblock_qtindex(wf, true, compression_type);
-            make_read_primitive_blocks_combine_call_all_idset(pc, ids.clone(), true)
+            make_read_primitive_blocks_combine_call_all_idset(pc, ids.clone(), true, history, bad_blocks)
         } else {
             let wfs = CallbackSync::new(wf, numchan);
             let mut pps: Vec<
@@ -621,7 +658,7 @@ pub fn call_mergechanges(
                 let w2 = Box::new(ReplaceNoneWithTimings::new(w));
                 let pc = make_packprimblock_qtindex(w2, true, compression_type);
                 pps.push(Box::new(Callback::new(
-                    make_read_primitive_blocks_combine_call_all_idset(pc, ids.clone(), true),
+                    make_read_primitive_blocks_combine_call_all_idset(pc, ids.clone(), true, history, bad_blocks),
                 )))
             }
             Box::new(CallbackMerge::new(pps, Box::new(MergeTimings::new())))
@@ -630,11 +667,13 @@ pub fn call_mergechanges(
     let msg = format!("write merged blocks, numchan={}", numchan);
     let tm = read_all_blocks_parallel_with_progbar(
         &mut pfilelocs.0,
+        &pfilelocs.3,
         &pfilelocs.1,
         pp,
         &msg,
         pfilelocs.2,
-    );
+        bad_blocks,
+    )?;
 
     /*let mut prog = ProgBarWrap::new(100);
     prog.set_range(100);
@@ -1,113 +1,206 @@
-use channelled_callbacks::{CallFinish,CallAll, Timings, Result as ccResult};
-use crate::elements::{apply_change_minimal, combine_block_minimal, MinimalBlock};
+use channelled_callbacks::{CallFinish, Timings, Result as ccResult, Error as ccError};
+use crate::elements::{apply_change_minimal, combine_block_minimal, BlockView, MinimalBlock};
 use crate::elements::{apply_change_primitive, combine_block_primitive, IdSet, PrimitiveBlock};
-use crate::pbfformat::FileBlock;
+use crate::elements::{apply_change_primitive_history, combine_block_primitive_history, history_at_primitive};
+use crate::pbfformat::{BadBlocks, FileBlock, HeaderInfo};
 use crate::utils::{ThreadTimer,Error,Result};
 
-use std::marker::PhantomData;
 use std::sync::Arc;
 
+fn block_error(fb: &FileBlock, err: Error) -> Error {
+    Error::PbfDataError(format!("failed to read {}: {}", fb.location(), err))
+}
+
+fn read_primitive_block(idx: i64, pos: u64, fb: &FileBlock, ischange: bool, ids: Option<&dyn IdSet>) -> Result<PrimitiveBlock> {
+    let data = fb.try_data()?;
+    match PrimitiveBlock::read_check_ids(idx, pos, &data, ischange, false, ids) {
+        Ok(pb) => Ok(pb),
+        Err(e) => Err(block_error(fb, e)),
+    }
+}
+
+fn read_minimal_block(idx: i64, pos: u64, fb: &FileBlock, ischange: bool) -> Result<MinimalBlock> {
+    let data = fb.try_data()?;
+    match MinimalBlock::read(idx, pos, &data, ischange) {
+        Ok(mb) => Ok(mb),
+        Err(e) => Err(block_error(fb, e)),
+    }
+}
+
+/// How the blocks of full-history files (the HistoricalInformation feature)
+/// are combined with their changes by [`read_primitive_blocks_combine`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum History {
+    /// Not a full-history file: changes replace each object.
+    None,
+    /// Keep every version, adding the versions from changes.
+    All,
+    /// As `All`, then keep the state at this timestamp: the last version of
+    /// each object at or before it, unless that version is deleted.
+    At(i64),
+}
+
+impl History {
+    /// Returns the History for reading `fname`: [`History::None`] unless its
+    /// header has the HistoricalInformation feature. Returns an error if
+    /// `timestamp` is given for a file without full history.
+    pub fn from_file(fname: &str, timestamp: Option<i64>) -> Result<History> {
+        let is_history = HeaderInfo::from_file(fname)?.history;
+        match (is_history, timestamp) {
+            (false, None) => Ok(History::None),
+            (false, Some(_)) => Err(Error::UserSelectionError(format!(
+                "{} isn't a full-history file",
+                fname
+            ))),
+            (true, None) => Ok(History::All),
+            (true, Some(ts)) => Ok(History::At(ts)),
+        }
+    }
+}
+
+fn combine_primitive(left: PrimitiveBlock, right: PrimitiveBlock, history: History) -> PrimitiveBlock {
+    match history {
+        History::None => combine_block_primitive(left, right),
+        _ => combine_block_primitive_history(left, right),
+    }
+}
+
+fn apply_change(left: PrimitiveBlock, right: PrimitiveBlock, history: History) -> PrimitiveBlock {
+    match history {
+        History::None => apply_change_primitive(left, right),
+        _ => apply_change_primitive_history(left, right),
+    }
+}
+
 pub fn read_primitive_blocks_combine(
     idx: i64,
     mut blocks: Vec<FileBlock>,
     ids: Option<&dyn IdSet>,
+    history: History,
 ) -> Result<PrimitiveBlock> {
     if blocks.is_empty() {
         return Ok(PrimitiveBlock::new(idx, 0));
     }
 
-    if blocks.len() == 1 {
+    let res = if blocks.len() == 1 {
         let fb = blocks.pop().unwrap();
-        return PrimitiveBlock::read_check_ids(idx, fb.pos, &fb.data(), false, false, ids);
-    }
+        read_primitive_block(idx, fb.pos, &fb, false, ids)?
+    } else {
+        let pos = blocks[0].pos;
 
-    let pos = blocks[0].pos;
+        let fb = blocks.pop().unwrap();
+        let mut curr = read_primitive_block(idx, pos, &fb, true, ids)?;
 
-    let fb = blocks.pop().unwrap();
-    let mut curr = PrimitiveBlock::read_check_ids(idx, pos, &fb.data(), true, false, ids)?;
+        while blocks.len() > 1 {
+            let fb = blocks.pop().unwrap();
+            let nb = read_primitive_block(idx, pos, &fb, true, ids)?;
+            curr = combine_primitive(nb, curr, history);
+        }
 
-    while blocks.len() > 1 {
         let fb = blocks.pop().unwrap();
-        let nb = PrimitiveBlock::read_check_ids(idx, pos, &fb.data(), true, false, ids)?;
-        curr = combine_block_primitive(nb, curr);
-    }
+        let main = read_primitive_block(idx, pos, &fb, false, ids)?;
 
-    let fb = blocks.pop().unwrap();
-    let main = PrimitiveBlock::read_check_ids(idx, pos, &fb.data(), false, false, ids)?;
+        apply_change(main, curr, history)
+    };
 
-    Ok(apply_change_primitive(main, curr))
+    match history {
+        History::At(ts) => Ok(history_at_primitive(res, ts)),
+        _ => Ok(res),
+    }
 }
 
-fn wrap_read_primitive_blocks_combine(idx_blocks: (usize, Vec<FileBlock>)) -> PrimitiveBlock {
-    read_primitive_blocks_combine(idx_blocks.0 as i64, idx_blocks.1, None)
-        .expect("failed to read data")
-}
+type ConvertFn<X, Y> = Box<dyn Fn(X) -> Result<Y> + Sync + Send + 'static>;
+type EmptyFn<Y> = Box<dyn Fn() -> Y + Sync + Send + 'static>;
 
-pub fn make_read_primitive_blocks_combine_call_all<
-    V: Sync + Send + 'static,
-    O: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings<V>, ErrorType=Error>,
->(
+struct ConvertBlocks<O, X, Y> {
     out: Box<O>,
-) -> Box<impl CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings<V>, ErrorType=Error>> {
-    Box::new(CallAll::new(
-        out,
-        "read_primitive_blocks_combine",
-        Box::new(wrap_read_primitive_blocks_combine),
-    ))
-}
-
-struct Rpbccai<O, V> {
-    out: Box<O>, 
-    ids: Arc<dyn IdSet>,
-    filter_relations: bool,
-    x: PhantomData<V>,
+    name: &'static str,
+    convert: ConvertFn<X, Y>,
+    empty: EmptyFn<Y>,
+    bad_blocks: BadBlocks,
+    error: Option<Error>,
     tm: f64,
 }
-impl<O, V> Rpbccai<O, V> {
-    pub fn new(out: Box<O>, ids: Arc<dyn IdSet>, filter_relations: bool) -> Rpbccai<O, V> {
-        Rpbccai {
+
+impl<O, X, Y> ConvertBlocks<O, X, Y> {
+    pub fn new(out: Box<O>, name: &'static str, convert: ConvertFn<X, Y>, empty: EmptyFn<Y>, bad_blocks: &BadBlocks) -> ConvertBlocks<O, X, Y> {
+        ConvertBlocks {
             out: out,
-            ids: ids,
-            filter_relations: filter_relations,
-            x: PhantomData,
+            name: name,
+            convert: convert,
+            empty: empty,
+            bad_blocks: bad_blocks.clone(),
+            error: None,
             tm: 0.0,
         }
     }
 }
 
-impl<O, V> CallFinish for Rpbccai<O, V>
+impl<O, X, Y, V> CallFinish for ConvertBlocks<O, X, Y>
 where
-    O: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings<V>, ErrorType=Error>,
+    O: CallFinish<CallType = Y, ReturnType = Timings<V>, ErrorType = Error>,
+    X: Sync + Send + 'static,
+    Y: Sync + Send + 'static,
     V: Sync + Send + 'static,
 {
-    type CallType = (usize, Vec<FileBlock>);
+    type CallType = X;
     type ReturnType = Timings<V>;
     type ErrorType = Error;
 
-    fn call(&mut self, idx_blocks: (usize, Vec<FileBlock>)) {
+    fn call(&mut self, x: X) {
         let tx = ThreadTimer::new();
-        let mut b = read_primitive_blocks_combine(
-            idx_blocks.0 as i64,
-            idx_blocks.1,
-            Some(self.ids.as_ref()),
-        )
-        .expect("?");
-        if self.filter_relations {
-            for r in b.relations.iter_mut() {
-                r.filter_relations(self.ids.as_ref());
+
+        //once a block has failed to read, pass empty blocks downstream so
+        //that any synchronised callbacks keep in step
+        let y = if self.error.is_some() {
+            (self.empty)()
+        } else {
+            match (self.convert)(x) {
+                Ok(y) => y,
+                Err(e) => {
+                    if let Err(e) = self.bad_blocks.check(e) {
+                        self.error = Some(e);
+                    }
+                    (self.empty)()
+                }
             }
-        }
-        
+        };
         self.tm += tx.since();
-        self.out.call(b);
+        self.out.call(y);
     }
+
     fn finish(&mut self) -> ccResult<Timings<V>, Error> {
-        let mut tm = self.out.finish()?;
-        tm.add("read_primitive_blocks_combine", self.tm);
-        Ok(tm)
+        let r = self.out.finish();
+        match self.error.take() {
+            Some(e) => Err(ccError::OtherError(e)),
+            None => {
+                let mut tm = r?;
+                tm.add(self.name, self.tm);
+                Ok(tm)
+            }
+        }
     }
 }
 
+pub fn make_read_primitive_blocks_combine_call_all<
+    V: Sync + Send + 'static,
+    O: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings<V>, ErrorType=Error>,
+>(
+    out: Box<O>,
+    history: History,
+    bad_blocks: &BadBlocks,
+) -> Box<impl CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings<V>, ErrorType=Error>> {
+    Box::new(ConvertBlocks::new(
+        out,
+        "read_primitive_blocks_combine",
+        Box::new(move |(idx, blocks): (usize, Vec<FileBlock>)| {
+            read_primitive_blocks_combine(idx as i64, blocks, None, history)
+        }),
+        Box::new(|| PrimitiveBlock::new(0, 0)),
+        bad_blocks,
+    ))
+}
+
 pub fn make_read_primitive_blocks_combine_call_all_idset<
     V: Sync + Send + 'static,
     O: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings<V>, ErrorType = Error>,
@@ -115,9 +208,27 @@ pub fn make_read_primitive_blocks_combine_call_all_idset<
     out: Box<O>,
     idset: Arc<dyn IdSet>,
     filter_relations: bool,
+    history: History,
+    bad_blocks: &BadBlocks,
 ) -> Box<impl CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings<V>, ErrorType=Error>> {
-    //Box::new(CallAll::new(out, "read_primitive_blocks_combine_idset", Box::new(move |pp| { wrap_read_primitive_blocks_combine(pp, Some(idset)) })))
-    Box::new(Rpbccai::new(out, idset, filter_relations))
+
+    let convert = move |(idx, blocks): (usize, Vec<FileBlock>)| -> Result<PrimitiveBlock> {
+        let mut b = read_primitive_blocks_combine(idx as i64, blocks, Some(idset.as_ref()), history)?;
+        if filter_relations {
+            for r in b.relations.iter_mut() {
+                r.filter_relations(idset.as_ref());
+            }
+        }
+        Ok(b)
+    };
+
+    Box::new(ConvertBlocks::new(
+        out,
+        "read_primitive_blocks_combine",
+        Box::new(convert),
+        Box::new(|| PrimitiveBlock::new(0, 0)),
+        bad_blocks,
+    ))
 }
 
 pub fn read_minimal_blocks_combine(idx: i64, mut blocks: Vec<FileBlock>) -> Result<MinimalBlock> {
@@ -127,40 +238,41 @@ pub fn read_minimal_blocks_combine(idx: i64, mut blocks: Vec<FileBlock>) -> Resu
 
     if blocks.len() == 1 {
         let fb = blocks.pop().unwrap();
-        return MinimalBlock::read(idx, fb.pos, &fb.data(), false);
+        return read_minimal_block(idx, fb.pos, &fb, false);
     }
 
     let pos = blocks[0].pos;
 
     let fb = blocks.pop().unwrap();
-    let mut curr = MinimalBlock::read(idx, pos, &fb.data(), true)?;
+    let mut curr = read_minimal_block(idx, pos, &fb, true)?;
 
     while blocks.len() > 1 {
         let fb = blocks.pop().unwrap();
-        let nb = MinimalBlock::read(idx, pos, &fb.data(), true)?;
+        let nb = read_minimal_block(idx, pos, &fb, true)?;
         curr = combine_block_minimal(nb, curr);
     }
 
     let fb = blocks.pop().unwrap();
-    let main = MinimalBlock::read(idx, pos, &fb.data(), false)?;
+    let main = read_minimal_block(idx, pos, &fb, false)?;
 
     Ok(apply_change_minimal(main, curr))
 }
 
-fn wrap_read_minimal_blocks_combine(idx_blocks: (usize, Vec<FileBlock>)) -> MinimalBlock {
-    read_minimal_blocks_combine(idx_blocks.0 as i64, idx_blocks.1).expect("failed to read data")
-}
-
 pub fn make_read_minimal_blocks_combine_call_all<
     V: Sync + Send + 'static,
     O: CallFinish<CallType = MinimalBlock, ReturnType = Timings<V>, ErrorType=Error>,
 >(
     out: Box<O>,
+    bad_blocks: &BadBlocks,
 ) -> Box<impl CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings<V>, ErrorType=Error>> {
-    Box::new(CallAll::new(
+    Box::new(ConvertBlocks::new(
         out,
         "read_minimal_blocks_combine",
-        Box::new(wrap_read_minimal_blocks_combine),
+        Box::new(|(idx, blocks): (usize, Vec<FileBlock>)| {
+            read_minimal_blocks_combine(idx as i64, blocks)
+        }),
+        Box::new(|| MinimalBlock::new()),
+        bad_blocks,
     ))
 }
 
@@ -170,20 +282,22 @@ pub fn make_convert_minimal_block<
 >(
     ischange: bool,
     out: Box<T>,
+    bad_blocks: &BadBlocks,
 ) -> Box<impl CallFinish<CallType = (usize, FileBlock), ReturnType = Timings<U>, ErrorType=Error>> {
-    let convert_minimal = move |(i, fb): (usize, FileBlock)| -> MinimalBlock {
+    let convert_minimal = move |(i, fb): (usize, FileBlock)| -> Result<MinimalBlock> {
         if fb.block_type == "OSMData" {
-            MinimalBlock::read(i as i64, fb.pos, &fb.data(), ischange)
-                .expect("failed to read minimalblock")
+            read_minimal_block(i as i64, fb.pos, &fb, ischange)
         } else {
-            MinimalBlock::new()
+            Ok(MinimalBlock::new())
         }
     };
 
-    Box::new(CallAll::new(
+    Box::new(ConvertBlocks::new(
         out,
         "convert minimal",
         Box::new(convert_minimal),
+        Box::new(|| MinimalBlock::new()),
+        bad_blocks,
     ))
 }
 
@@ -196,28 +310,65 @@ pub fn make_convert_minimal_block_parts<
     readways: bool,
     readrelations: bool,
     out: Box<T>,
+    bad_blocks: &BadBlocks,
 ) -> Box<impl CallFinish<CallType = (usize, FileBlock), ReturnType = Timings<U>, ErrorType=Error>> {
-    let convert_minimal = move |(i, fb): (usize, FileBlock)| -> MinimalBlock {
+    let convert_minimal = move |(i, fb): (usize, FileBlock)| -> Result<MinimalBlock> {
         if fb.block_type == "OSMData" {
-            MinimalBlock::read_parts(
+            let data = fb.try_data()?;
+            match MinimalBlock::read_parts(
                 i as i64,
                 fb.pos,
-                &fb.data(),
+                &data,
                 ischange,
                 readnodes,
                 readways,
                 readrelations,
-            )
-            .expect("failed to read minimalblock")
+            ) {
+                Ok(mb) => Ok(mb),
+                Err(e) => Err(block_error(&fb, e)),
+            }
         } else {
-            MinimalBlock::new()
+            Ok(MinimalBlock::new())
         }
     };
 
-    Box::new(CallAll::new(
+    Box::new(ConvertBlocks::new(
         out,
         "convert minimal",
         Box::new(convert_minimal),
+        Box::new(|| MinimalBlock::new()),
+        bad_blocks,
+    ))
+}
+
+/// Like [`make_convert_primitive_block`], but passes [`BlockView`]s which
+/// only decode tags, refs and members when they are used.
+pub fn make_convert_block_view<
+    T: CallFinish<CallType = BlockView, ReturnType = Timings<U>, ErrorType=Error>,
+    U: Sync + Send + 'static,
+>(
+    ischange: bool,
+    out: Box<T>,
+    bad_blocks: &BadBlocks,
+) -> Box<impl CallFinish<CallType = (usize, FileBlock), ReturnType = Timings<U>, ErrorType=Error>> {
+    let convert_view = move |(i, fb): (usize, FileBlock)| -> Result<BlockView> {
+        if fb.block_type == "OSMData" {
+            let data = fb.try_data()?;
+            match BlockView::read(i as i64, fb.pos, data, ischange) {
+                Ok(bv) => Ok(bv),
+                Err(e) => Err(block_error(&fb, e)),
+            }
+        } else {
+            Ok(BlockView::new(0, 0))
+        }
+    };
+
+    Box::new(ConvertBlocks::new(
+        out,
+        "convert block view",
+        Box::new(convert_view),
+        Box::new(|| BlockView::new(0, 0)),
+        bad_blocks,
     ))
 }
 
@@ -227,18 +378,25 @@ pub fn make_convert_primitive_block<
 >(
     ischange: bool,
     out: Box<T>,
+    bad_blocks: &BadBlocks,
 ) -> Box<impl CallFinish<CallType = (usize, FileBlock), ReturnType = Timings<U>, ErrorType=Error>> {
-    let convert_minimal = move |(i, fb): (usize, FileBlock)| -> PrimitiveBlock {
+    let convert_primitive = move |(i, fb): (usize, FileBlock)| -> Result<PrimitiveBlock> {
         if fb.block_type == "OSMData" {
-            PrimitiveBlock::read(i as i64, fb.pos, &fb.data(), ischange, false).expect("?")
+            let data = fb.try_data()?;
+            match PrimitiveBlock::read(i as i64, fb.pos, &data, ischange, false) {
+                Ok(pb) => Ok(pb),
+                Err(e) => Err(block_error(&fb, e)),
+            }
         } else {
-            PrimitiveBlock::new(0, 0)
+            Ok(PrimitiveBlock::new(0, 0))
         }
     };
 
-    Box::new(CallAll::new(
+    Box::new(ConvertBlocks::new(
         out,
         "convert primitive",
-        Box::new(convert_minimal),
+        Box::new(convert_primitive),
+        Box::new(|| PrimitiveBlock::new(0, 0)),
+        bad_blocks,
     ))
 }
@@ -1,23 +1,34 @@
 use serde::{Deserialize, Serialize};
 use serde_json;
+use flate2::Crc;
 
-use crate::elements::{Bbox, Quadtree};
+use crate::elements::{Bbox, Quadtree, QuadtreeCovering};
 use crate::pbfformat::HeaderBlock;
-use crate::pbfformat::{file_position, read_file_block};
+use crate::pbfformat::{file_position, read_file_block, try_file_length};
 use crate::utils::parse_timestamp;
 use crate::message;
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::BufReader;
-use crate::utils::{Error, Result, at_end_of_file};
+use crate::utils::{Error, Result, at_end_of_file, write_file_atomic};
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Version of the filelist entries written by this crate. Entries from older
+/// filelists, without a version, have version 0.
+pub const FILELIST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct FilelistEntry {
     pub filename: String,
     pub end_date: String,
     pub num_tiles: usize,
     pub state: i64,
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_crc: Option<u32>,
 }
 
 impl FilelistEntry {
@@ -27,26 +38,94 @@ impl FilelistEntry {
             end_date,
             num_tiles,
             state,
+            version: FILELIST_VERSION,
+            length: None,
+            header_crc: None,
+        }
+    }
+
+    /// Creates an entry for the file `filename` in the directory `prfx`,
+    /// recording its length and a checksum of its header so that
+    /// `get_file_locs` can detect if the file is later changed.
+    pub fn new_checked(prfx: &str, filename: String, end_date: String, num_tiles: usize, state: i64) -> Result<FilelistEntry> {
+        let (length, header_crc) = file_checks(&format!("{}{}", prfx, filename))?;
+        let mut fle = FilelistEntry::new(filename, end_date, num_tiles, state);
+        fle.length = Some(length);
+        fle.header_crc = Some(header_crc);
+        Ok(fle)
+    }
+
+    fn check(&self, fname: &str, header_data: &[u8]) -> Result<()> {
+        if self.version > FILELIST_VERSION {
+            return Err(Error::InvalidDataError(format!(
+                "filelist entry for {} has version {}: only version {} is supported",
+                fname, self.version, FILELIST_VERSION
+            )));
+        }
+        if let Some(length) = self.length {
+            let fl = try_file_length(fname)?;
+            if fl != length {
+                return Err(Error::InvalidDataError(format!(
+                    "{} is {} bytes, filelist expects {}: file truncated or replaced?",
+                    fname, fl, length
+                )));
+            }
+        }
+        if let Some(header_crc) = self.header_crc {
+            let crc = header_checksum(header_data);
+            if crc != header_crc {
+                return Err(Error::InvalidDataError(format!(
+                    "{} header checksum {:08x}, filelist expects {:08x}: file replaced?",
+                    fname, crc, header_crc
+                )));
+            }
         }
+        Ok(())
     }
 }
 
-pub fn read_filelist(prfx: &str) -> Vec<FilelistEntry> {
-    let ff = File::open(format!("{}filelist.json", prfx)).expect("failed to open filelist file");
+fn header_checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// Returns the length of `fname` and a crc32 checksum of its header block,
+/// which includes the index of tile locations.
+pub fn file_checks(fname: &str) -> Result<(u64, u32)> {
+    let mut fbuf = BufReader::new(File::open(fname)?);
+    let fb = read_file_block(&mut fbuf)?;
+    Ok((try_file_length(fname)?, header_checksum(&fb.try_data()?)))
+}
+
+pub fn read_filelist(prfx: &str) -> Result<Vec<FilelistEntry>> {
+    let fname = format!("{}filelist.json", prfx);
+    let ff = match File::open(&fname) {
+        Ok(ff) => ff,
+        Err(e) => {
+            return Err(Error::FilelistError(format!("failed to open {}: {}", fname, e)));
+        }
+    };
     let mut ffb = BufReader::new(ff);
-    serde_json::from_reader(&mut ffb).expect("failed to read filelist")
+    match serde_json::from_reader(&mut ffb) {
+        Ok(filelist) => Ok(filelist),
+        Err(e) => Err(Error::FilelistError(format!("failed to read {}: {}", fname, e))),
+    }
 }
 
-pub fn write_filelist(prfx: &str, filelist: &Vec<FilelistEntry>) {
-    let flfile =
-        File::create(format!("{}filelist.json", prfx)).expect("failed to create filelist file");
-    serde_json::to_writer(&flfile, &filelist).expect("failed to write filelist json");
+pub fn write_filelist(prfx: &str, filelist: &Vec<FilelistEntry>) -> Result<()> {
+    let data = serde_json::to_vec(&filelist)?;
+    write_file_atomic(&format!("{}filelist.json", prfx), &data)?;
+    Ok(())
 }
 
+/// Open files, the locations of each tile's blocks as (file index, position),
+/// the total length of these blocks, and the file names.
 pub type ParallelFileLocs = (
     Vec<BufReader<File>>,
     Vec<(Quadtree, Vec<(usize, u64)>)>,
     u64,
+    Vec<String>,
 );
 
 /*pub fn get_file_locs_single(infn: &str, filter: Option<Bbox>) -> Result<ParallelFileLocs> {
@@ -68,21 +147,17 @@ pub fn get_file_locs_single_max_depth(infn: &str, filter: Option<Bbox>, max_dept
 
     let fb = read_file_block(&mut fbuf)?;
     let filepos = file_position(&mut fbuf)?;
-    let head = HeaderBlock::read(filepos, &fb.data(), infn)?;
+    let head = HeaderBlock::read(filepos, &fb.try_data()?, infn)?;
     if head.index.is_empty() {
         return Err(Error::PbfDataError(format!("file {} has no locations in header", infn)));
     }
 
     let mut total_len = 0;
+    let covering = make_filter_covering(&filter);
 
     for entry in head.index {
         if check_entry_depth(&max_depth, &entry.quadtree.depth()) {
-            if filter.as_ref().is_none()
-                || filter
-                    .as_ref()
-                    .unwrap()
-                    .overlaps(&entry.quadtree.as_bbox(0.05))
-            {
+            if check_entry_filter(&filter, &covering, &entry.quadtree) {
                 locs.insert(
                     entry.quadtree.clone(),
                     (locs.len(), vec![(0, entry.location)]),
@@ -95,7 +170,7 @@ pub fn get_file_locs_single_max_depth(infn: &str, filter: Option<Bbox>, max_dept
     for (a, (_b, c)) in locs {
         locsv.push((a, c));
     }
-    Ok((vec![fbuf], locsv, total_len))
+    Ok((vec![fbuf], locsv, total_len, vec![infn.to_string()]))
 }
 
 pub fn get_file_locs(
@@ -107,6 +182,24 @@ pub fn get_file_locs(
     get_file_locs_max_depth(prfx, filter, timestamp, None)
 }
 
+//header entries below a partly covered tile at this level are tested
+//against the filter directly
+const FILTER_COVERING_LEVEL: usize = 10;
+
+fn make_filter_covering(filter: &Option<Bbox>) -> Option<QuadtreeCovering> {
+    filter.as_ref().map(|f| QuadtreeCovering::from_bbox(f, FILTER_COVERING_LEVEL, 0.05))
+}
+
+fn check_entry_filter(filter: &Option<Bbox>, covering: &Option<QuadtreeCovering>, qt: &Quadtree) -> bool {
+    match (filter, covering) {
+        (Some(f), Some(c)) => match c.check(qt) {
+            Some(r) => r,
+            None => f.overlaps(&qt.as_bbox(0.05)),
+        },
+        _ => true,
+    }
+}
+
 fn check_entry_depth(max_depth: &Option<usize>, test_depth: &usize) -> bool {
     match max_depth {
         None => true,
@@ -131,9 +224,10 @@ pub fn get_file_locs_max_depth(prfx: &str, filter: Option<Bbox>, timestamp: Opti
         return get_file_locs_single_max_depth(prfx, filter, max_depth);
     }
 
-    let filelist = read_filelist(&prfx);
+    let filelist = read_filelist(&prfx)?;
 
     let mut fbufs = Vec::new();
+    let mut fnames = Vec::new();
     let mut locs = BTreeMap::new();
 
     let cap = match filter {
@@ -142,6 +236,7 @@ pub fn get_file_locs_max_depth(prfx: &str, filter: Option<Bbox>, timestamp: Opti
     };
     let mut all_locs = 0;
     let mut total_len = 0;
+    let covering = make_filter_covering(&filter);
     for (i, fle) in filelist.iter().enumerate() {
         let fle_ts = parse_timestamp(&fle.end_date)?;
         if !timestamp.is_none() && fle_ts > timestamp.unwrap() {
@@ -154,7 +249,9 @@ pub fn get_file_locs_max_depth(prfx: &str, filter: Option<Bbox>, timestamp: Opti
 
         let fb = read_file_block(&mut fbuf)?;
         let filepos = file_position(&mut fbuf)?;
-        let head = HeaderBlock::read(filepos, &fb.data(), &fle_fn)?;
+        let data = fb.try_data()?;
+        fle.check(&fle_fn, &data)?;
+        let head = HeaderBlock::read(filepos, &data, &fle_fn)?;
 
         if head.index.is_empty() {
             if at_end_of_file(&mut fbuf)? {
@@ -173,12 +270,7 @@ pub fn get_file_locs_max_depth(prfx: &str, filter: Option<Bbox>, timestamp: Opti
             if i == 0 {
                 if check_entry_depth(&max_depth, &entry.quadtree.depth()) {
                 
-                    if filter.as_ref().is_none()
-                        || filter
-                            .as_ref()
-                            .unwrap()
-                            .overlaps(&entry.quadtree.as_bbox(0.05))
-                    {
+                    if check_entry_filter(&filter, &covering, &entry.quadtree) {
                         locs.insert(entry.quadtree.clone(), (locs.len(), Vec::new()));
                         locs.get_mut(&entry.quadtree)
                             .unwrap()
@@ -199,6 +291,7 @@ pub fn get_file_locs_max_depth(prfx: &str, filter: Option<Bbox>, timestamp: Opti
         }
 
         fbufs.push(fbuf);
+        fnames.push(fle_fn);
     }
 
     let mut locsv = Vec::new();
@@ -215,5 +308,33 @@ pub fn get_file_locs_max_depth(prfx: &str, filter: Option<Bbox>, timestamp: Opti
         (total_len as f64) / 1024.0 / 1024.0
     );
 
-    Ok((fbufs, locsv, total_len))
+    Ok((fbufs, locsv, total_len, fnames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_outside_shallow_tile() {
+        //the filter misses the tile itself, and the buffers of its children,
+        //but is inside its 5% buffer
+        let qt = Quadtree::from_xyz(2, 1, 2);
+        let tile = qt.as_bbox(0.0);
+        let buffered = qt.as_bbox(0.05);
+        let midlat = tile.minlat / 2 + tile.maxlat / 2;
+        let filter = Some(Bbox::new(
+            tile.maxlon + (buffered.maxlon - tile.maxlon) / 4 * 3,
+            midlat,
+            tile.maxlon + (buffered.maxlon - tile.maxlon) / 8 * 7,
+            midlat + (tile.maxlat - midlat) / 4,
+        ));
+        assert!(!filter.as_ref().unwrap().overlaps(&tile));
+
+        let covering = make_filter_covering(&filter);
+        assert!(check_entry_filter(&filter, &covering, &qt));
+
+        let far = Quadtree::from_xyz(0, 1, 2);
+        assert!(!check_entry_filter(&filter, &covering, &far));
+    }
 }
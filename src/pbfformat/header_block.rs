@@ -1,4 +1,5 @@
 use crate::elements::{Bbox, Quadtree};
+use crate::pbfformat::read_file_block::{file_position, is_stdin, read_file_block};
 
 use simple_protocolbuffers as spb;
 
@@ -59,6 +60,34 @@ pub struct HeaderBlock {
     pub osmosis_replication_base_url: Option<String>,
     
     pub index: Vec<IndexItem>,
+    
+    /// Fields not otherwise read, as (tag, encoded field) pairs. These are
+    /// kept by [`HeaderInfo::from_header_block`] so that they are written
+    /// unchanged when the header is rewritten.
+    pub unknown_fields: Vec<(u64, Vec<u8>)>,
+}
+
+/// Required feature of full-history files, which may contain several
+/// versions of each element, including deleted versions with
+/// `Info::visible` set to false.
+pub const HISTORICAL_INFORMATION: &str = "HistoricalInformation";
+
+/// Required features which this crate can read. Any other required feature
+/// means the file can't be read correctly.
+pub const SUPPORTED_FEATURES: &[&str] = &["OsmSchema-V0.6", "DenseNodes", HISTORICAL_INFORMATION];
+
+fn pack_unknown_field(x: &spb::PbfTag) -> (u64, Vec<u8>) {
+    let mut res = Vec::new();
+    match x {
+        spb::PbfTag::Value(t, v) => {
+            spb::pack_value(&mut res, *t, *v);
+            (*t, res)
+        }
+        spb::PbfTag::Data(t, d) => {
+            spb::pack_data(&mut res, *t, d);
+            (*t, res)
+        }
+    }
 }
 
 fn read_header_bbox(data: &[u8]) -> Result<Vec<i64>> {
@@ -71,7 +100,7 @@ fn read_header_bbox(data: &[u8]) -> Result<Vec<i64>> {
             spb::PbfTag::Value(2, minlat) => bbox[2] = spb::un_zig_zag(minlat) / 1000, //right
             spb::PbfTag::Value(3, maxlon) => bbox[3] = spb::un_zig_zag(maxlon) / 1000, //top
             spb::PbfTag::Value(4, maxlat) => bbox[1] = spb::un_zig_zag(maxlat) / 1000, //bottom
-            _ => return Err(Error::new(ErrorKind::Other, "unexpected item")),
+            _ => {} //ignore any extra fields
         }
     }
 
@@ -90,6 +119,7 @@ impl HeaderBlock {
             osmosis_replication_sequence_number: None,
             osmosis_replication_base_url: None,
             index: Vec::new(),
+            unknown_fields: Vec::new(),
         }
     }
 
@@ -140,12 +170,37 @@ impl HeaderBlock {
                     
                 }
                 
-                _ => { return Err(Error::new(ErrorKind::Other, format!("?? {:?}", x))); },
+                _ => {
+                    res.unknown_fields.push(pack_unknown_field(&x));
+                },
             }
         }
+        res.check_required_features(fname)?;
         Ok(res)
     }
 
+    pub fn is_history(&self) -> bool {
+        self.required_features.iter().any(|f| f == HISTORICAL_INFORMATION)
+    }
+
+    /// Returns an error if the header lists any required feature not in
+    /// [`SUPPORTED_FEATURES`].
+    pub fn check_required_features(&self, fname: &str) -> Result<()> {
+        let unsupported: Vec<&str> = self.required_features
+            .iter()
+            .filter(|f| !SUPPORTED_FEATURES.contains(&f.as_str()))
+            .map(|f| f.as_str())
+            .collect();
+
+        if !unsupported.is_empty() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("{} has unsupported required features: {}", fname, unsupported.join(", ")),
+            ));
+        }
+        Ok(())
+    }
+
     fn read_file_locs(&mut self, filelocs_fn: &str) -> Result<()> {
         let fl = File::open(filelocs_fn)?;
         let mut flb = BufReader::new(fl);
@@ -185,7 +240,106 @@ fn pack_bbox(bbox: &Bbox) -> Vec<u8> {
     res
 }
 
-pub fn make_header_block(withlocs: bool, bbox: Option<&Bbox>) -> Vec<u8> {
+/// Optional fields written to the header block of each output file. The
+/// osmosis replication fields allow a merged file to be passed to tools such
+/// as osmium or osm2pgsql, which then apply updates from `base_url` starting
+/// at `sequence_number`.
+#[derive(Debug, Clone)]
+pub struct HeaderInfo {
+    pub optional_features: Vec<String>,
+    /// Adds the HistoricalInformation required feature.
+    pub history: bool,
+    pub writingprogram: String,
+    pub source: Option<String>,
+    pub osmosis_replication_timestamp: Option<i64>,
+    pub osmosis_replication_sequence_number: Option<i64>,
+    pub osmosis_replication_base_url: Option<String>,
+    pub unknown_fields: Vec<(u64, Vec<u8>)>,
+}
+
+impl HeaderInfo {
+    pub fn new() -> HeaderInfo {
+        HeaderInfo {
+            optional_features: Vec::new(),
+            history: false,
+            writingprogram: String::from(DEFAULT_WRITING_PROGRAM),
+            source: None,
+            osmosis_replication_timestamp: None,
+            osmosis_replication_sequence_number: None,
+            osmosis_replication_base_url: None,
+            unknown_fields: Vec::new(),
+        }
+    }
+
+    /// Returns a HeaderInfo keeping the history flag, source, replication
+    /// and unknown fields of an existing header. The writingprogram is
+    /// replaced.
+    pub fn from_header_block(head: &HeaderBlock) -> HeaderInfo {
+        let mut res = HeaderInfo::new();
+        res.history = head.is_history();
+        res.source = head.source.clone();
+        res.osmosis_replication_timestamp = head.osmosis_replication_timestamp;
+        res.osmosis_replication_sequence_number = head.osmosis_replication_sequence_number;
+        res.osmosis_replication_base_url = head.osmosis_replication_base_url.clone();
+        res.unknown_fields = head.unknown_fields.clone();
+        res
+    }
+
+    /// Reads the header of `fname` with [`HeaderInfo::from_header_block`],
+    /// or returns [`HeaderInfo::new`] if reading from stdin.
+    pub fn from_file(fname: &str) -> Result<HeaderInfo> {
+        if is_stdin(fname) {
+            return Ok(HeaderInfo::new());
+        }
+        let mut fbuf = BufReader::new(File::open(fname)?);
+        let fb = read_file_block(&mut fbuf)?;
+        let head = HeaderBlock::read(file_position(&mut fbuf)?, &fb.data(), fname)?;
+        Ok(HeaderInfo::from_header_block(&head))
+    }
+
+    pub fn with_replication(timestamp: i64, sequence_number: i64, base_url: Option<&str>) -> HeaderInfo {
+        let mut res = HeaderInfo::new();
+        res.osmosis_replication_timestamp = Some(timestamp);
+        res.osmosis_replication_sequence_number = Some(sequence_number);
+        res.osmosis_replication_base_url = base_url.map(|s| s.to_string());
+        res
+    }
+
+    fn pack(&self, res: &mut Vec<u8>) {
+        if self.history {
+            spb::pack_data(res, 4, HISTORICAL_INFORMATION.as_bytes());
+        }
+        for f in &self.optional_features {
+            spb::pack_data(res, 5, f.as_bytes());
+        }
+        spb::pack_data(res, 16, self.writingprogram.as_bytes());
+        match &self.source {
+            Some(s) => spb::pack_data(res, 17, s.as_bytes()),
+            None => {}
+        }
+        match self.osmosis_replication_timestamp {
+            Some(t) => spb::pack_value(res, 32, t as u64),
+            None => {}
+        }
+        match self.osmosis_replication_sequence_number {
+            Some(s) => spb::pack_value(res, 33, s as u64),
+            None => {}
+        }
+        match &self.osmosis_replication_base_url {
+            Some(u) => spb::pack_data(res, 34, u.as_bytes()),
+            None => {}
+        }
+        for (_, f) in &self.unknown_fields {
+            res.extend_from_slice(f);
+        }
+    }
+}
+
+/// Default writingprogram value for [`HeaderInfo::new`]. Callers may set
+/// [`HeaderInfo::writingprogram`] to use another value.
+pub const DEFAULT_WRITING_PROGRAM: &str = "osmquadtree-rust";
+
+pub fn make_header_block(withlocs: bool, bbox: Option<&Bbox>, info: &HeaderInfo) -> Vec<u8> {
     let mut res = Vec::new();
 
     match bbox {
@@ -198,7 +352,7 @@ pub fn make_header_block(withlocs: bool, bbox: Option<&Bbox>) -> Vec<u8> {
     }
     spb::pack_data(&mut res, 4, b"OsmSchema-V0.6");
     spb::pack_data(&mut res, 4, b"DenseNodes");
-    spb::pack_data(&mut res, 16, b"osmquadtree-cpp"); //b"osmquadtree-rust"
+    info.pack(&mut res);
     if withlocs {
         spb::pack_data(&mut res, 23, b"-filelocs.json");
     }
@@ -215,13 +369,13 @@ fn pack_index_item(q: &Quadtree, _ischange: bool, l: u64) -> Vec<u8> {
     res
 }
 
-pub fn make_header_block_stored_locs(ischange: bool, locs: Vec<(Quadtree, u64)>) -> Vec<u8> {
+pub fn make_header_block_stored_locs(ischange: bool, locs: Vec<(Quadtree, u64)>, info: &HeaderInfo) -> Vec<u8> {
     let mut res = Vec::new();
 
     spb::pack_data(&mut res, 1, &pack_bbox_planet());
     spb::pack_data(&mut res, 4, b"OsmSchema-V0.6");
     spb::pack_data(&mut res, 4, b"DenseNodes");
-    spb::pack_data(&mut res, 16, b"osmquadtree-cpp"); //b"osmquadtree-rust"
+    info.pack(&mut res);
     for (a, b) in &locs {
         spb::pack_data(&mut res, 22, &pack_index_item(a, ischange, *b));
     }
@@ -0,0 +1,192 @@
+use crate::elements::{merge_changes_primitive, Bbox, PrimitiveBlock, Quadtree};
+use crate::pbfformat::{get_file_locs_max_depth, read_file_block_with_pos};
+use crate::utils::{Error, Result};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// Least recently used cache of decoded tiles.
+struct TileCache {
+    capacity: usize,
+    counter: u64,
+    tiles: BTreeMap<Quadtree, (u64, Arc<PrimitiveBlock>)>,
+    order: BTreeMap<u64, Quadtree>,
+}
+
+impl TileCache {
+    fn new(capacity: usize) -> TileCache {
+        TileCache {
+            capacity: capacity,
+            counter: 0,
+            tiles: BTreeMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, qt: &Quadtree) -> Option<Arc<PrimitiveBlock>> {
+        match self.tiles.get_mut(qt) {
+            None => None,
+            Some((c, bl)) => {
+                self.order.remove(c);
+                self.counter += 1;
+                *c = self.counter;
+                self.order.insert(self.counter, qt.clone());
+                Some(bl.clone())
+            }
+        }
+    }
+
+    fn insert(&mut self, qt: Quadtree, bl: Arc<PrimitiveBlock>) {
+        if self.capacity == 0 {
+            return;
+        }
+        match self.tiles.remove(&qt) {
+            Some((c, _)) => {
+                self.order.remove(&c);
+            }
+            None => {}
+        }
+        while self.tiles.len() >= self.capacity {
+            let (c, q) = match self.order.iter().next() {
+                Some((c, q)) => (*c, q.clone()),
+                None => break,
+            };
+            self.order.remove(&c);
+            self.tiles.remove(&q);
+        }
+        self.counter += 1;
+        self.order.insert(self.counter, qt.clone());
+        self.tiles.insert(qt, (self.counter, bl));
+    }
+
+    fn len(&self) -> usize {
+        self.tiles.len()
+    }
+}
+
+/// Random access to the tiles of a sorted osmquadtree file, or a filelist
+/// directory of a file and its updates. Tiles are found using the header
+/// index of each file, and any updates are applied to the returned blocks.
+/// Up to `cache_size` decoded tiles are kept.
+pub struct IndexedReader {
+    fbufs: Vec<BufReader<File>>,
+    fposes: Vec<u64>,
+    locs: BTreeMap<Quadtree, Vec<(usize, u64)>>,
+    cache: TileCache,
+}
+
+impl IndexedReader {
+    pub fn open(prfx: &str, timestamp: Option<i64>, cache_size: usize) -> Result<IndexedReader> {
+        IndexedReader::open_max_depth(prfx, timestamp, None, cache_size)
+    }
+
+    pub fn open_max_depth(
+        prfx: &str,
+        timestamp: Option<i64>,
+        max_depth: Option<usize>,
+        cache_size: usize,
+    ) -> Result<IndexedReader> {
+        let (fbufs, locsv, _, _) = get_file_locs_max_depth(prfx, None, timestamp, max_depth)?;
+
+        let fposes = vec![u64::MAX; fbufs.len()];
+        let locs = locsv.into_iter().collect::<BTreeMap<Quadtree, Vec<(usize, u64)>>>();
+
+        Ok(IndexedReader {
+            fbufs: fbufs,
+            fposes: fposes,
+            locs: locs,
+            cache: TileCache::new(cache_size),
+        })
+    }
+
+    /// Returns all tiles in the index, in quadtree order.
+    pub fn tiles(&self) -> Vec<Quadtree> {
+        self.locs.keys().cloned().collect()
+    }
+
+    pub fn num_cached(&self) -> usize {
+        self.cache.len()
+    }
+
+    fn read_block(&mut self, i: usize, pos: u64) -> Result<PrimitiveBlock> {
+        if self.fposes[i] != pos {
+            self.fbufs[i].seek(SeekFrom::Start(pos))?;
+        }
+        let (p, fb) = match read_file_block_with_pos(&mut self.fbufs[i], pos) {
+            Ok(r) => r,
+            Err(e) => {
+                //position unknown after a failed read
+                self.fposes[i] = u64::MAX;
+                return Err(Error::PbfDataError(format!("failed to read block {} @ {}: {}", i, pos, e)));
+            }
+        };
+        self.fposes[i] = p;
+
+        let data = fb.try_data()?;
+        match PrimitiveBlock::read(0, pos, &data, i > 0, false) {
+            Ok(pb) => Ok(pb),
+            Err(e) => Err(Error::PbfDataError(format!("failed to read block {} @ {}: {}", i, pos, e))),
+        }
+    }
+
+    /// Returns the tile `qt` with all updates applied, or None if `qt` is
+    /// not in the index.
+    pub fn get_tile(&mut self, qt: &Quadtree) -> Result<Option<Arc<PrimitiveBlock>>> {
+        match self.cache.get(qt) {
+            Some(bl) => {
+                return Ok(Some(bl));
+            }
+            None => {}
+        }
+
+        let ll = match self.locs.get(qt) {
+            Some(ll) => ll.clone(),
+            None => {
+                return Ok(None);
+            }
+        };
+
+        let mut orig = None;
+        let mut changes = Vec::new();
+        for (i, pos) in ll {
+            let pb = self.read_block(i, pos)?;
+            if i == 0 {
+                orig = Some(pb);
+            } else {
+                changes.push(pb);
+            }
+        }
+
+        let bl = match orig {
+            Some(orig) => merge_changes_primitive(orig, changes),
+            None => {
+                return Err(Error::MissingDataError(format!("no main block for tile {}", qt.as_string())));
+            }
+        };
+
+        let bl = Arc::new(bl);
+        self.cache.insert(qt.clone(), bl.clone());
+        Ok(Some(bl))
+    }
+
+    /// Returns every tile which overlaps `bbox`, in quadtree order.
+    pub fn get_bbox(&mut self, bbox: &Bbox) -> Result<Vec<Arc<PrimitiveBlock>>> {
+        let qts = self
+            .locs
+            .keys()
+            .filter(|q| bbox.overlaps(&q.as_bbox(0.05)))
+            .cloned()
+            .collect::<Vec<Quadtree>>();
+
+        let mut res = Vec::with_capacity(qts.len());
+        for q in qts {
+            match self.get_tile(&q)? {
+                Some(bl) => res.push(bl),
+                None => {}
+            }
+        }
+        Ok(res)
+    }
+}
@@ -1,5 +1,5 @@
 use crate::pbfformat::read_file_block::ReadFileBlocksOwn;
-use crate::pbfformat::{FileBlock,make_convert_primitive_block,/*file_length,*/read_all_blocks_with_progbar};
+use crate::pbfformat::{BadBlocks,FileBlock,make_convert_primitive_block,/*file_length,*/read_all_blocks_with_progbar};
 use crate::elements::{Element,PrimitiveBlock};
 use channelled_callbacks::{
         Callback,   CallbackMerge,  CallbackSync,
@@ -105,16 +105,17 @@ impl Iterator for ConvertPrimitiveBlocksLumps {
     
 fn prep_read_all_primitive(fname: String, numchan: usize, cb: Box<dyn CallFinish<CallType=PrimitiveBlock, ReturnType=Timings<()>, ErrorType=Error>>) -> Result<Timings<()>> {
     
+    let bad_blocks = BadBlocks::abort();
     let cbs = CallbackSync::new(cb, numchan);
     let mut convs: Vec<Box<dyn CallFinish<CallType=(usize,FileBlock),ReturnType=Timings<()>, ErrorType=Error>>> = Vec::new();
     for c in cbs {
         let c2 = Box::new(ReplaceNoneWithTimings::new(c));
-        convs.push(Box::new(Callback::new(make_convert_primitive_block(false, c2))))
+        convs.push(Box::new(Callback::new(make_convert_primitive_block(false, c2, &bad_blocks))))
     }
     
     let conv = Box::new(CallbackMerge::new(convs,Box::new(MergeTimings::new())));
     
-    let (tm,_) = read_all_blocks_with_progbar(&fname, conv, &format!("read from {}",&fname) );
+    let (tm,_) = read_all_blocks_with_progbar(&fname, conv, &format!("read from {}",&fname), &bad_blocks)?;
     Ok(tm)
 }
     
@@ -4,24 +4,30 @@ mod read_file_block;
 mod writefile;
 mod iterelementsflat;
 mod filelist;
+mod indexedreader;
 
 pub use crate::pbfformat::convertblocks::{
-    make_convert_minimal_block, make_convert_minimal_block_parts, make_convert_primitive_block,
+    make_convert_block_view, make_convert_minimal_block, make_convert_minimal_block_parts,
+    make_convert_primitive_block,
     make_read_minimal_blocks_combine_call_all, make_read_primitive_blocks_combine_call_all,
     make_read_primitive_blocks_combine_call_all_idset,
-    read_primitive_blocks_combine, read_minimal_blocks_combine
+    read_primitive_blocks_combine, read_minimal_blocks_combine, History
 };
 
 pub use crate::pbfformat::header_block::{
-    make_header_block, make_header_block_stored_locs, HeaderBlock, HeaderType,
+    make_header_block, make_header_block_stored_locs, HeaderBlock, HeaderType, HeaderInfo,
+    DEFAULT_WRITING_PROGRAM, HISTORICAL_INFORMATION,
+    SUPPORTED_FEATURES,
 };
 
 pub use crate::pbfformat::read_file_block::{
-    file_length, file_position, pack_file_block, read_all_blocks, read_all_blocks_locs_prog,
+    file_length, try_file_length, file_position, pack_file_block, read_all_blocks, read_all_blocks_locs_prog,
     read_all_blocks_parallel_prog, read_all_blocks_parallel_with_progbar, read_all_blocks_prog,
     read_all_blocks_prog_fpos, read_all_blocks_with_progbar, read_all_blocks_with_progbar_stop,
     read_file_block, read_file_block_with_pos, unpack_file_block, FileBlock,
-    ReadFileBlocks, read_all_blocks_vec_with_progbar, CompressionType
+    ReadFileBlocks, read_all_blocks_vec_with_progbar, CompressionType,
+    BadBlockPolicy, BadBlocks,
+    StreamFileBlocks, STDIN_FILENAME, is_stdin, open_input
 };
 
 
@@ -29,4 +35,6 @@ pub use crate::pbfformat::read_file_block::{
 pub use crate::pbfformat::writefile::{FileLocs, WriteFile};
 
 pub use crate::pbfformat::iterelementsflat::iter_elements_flat;
-pub use filelist::{get_file_locs, read_filelist, write_filelist, FilelistEntry, ParallelFileLocs, get_file_locs_max_depth};
+pub use filelist::{get_file_locs, read_filelist, write_filelist, FilelistEntry, ParallelFileLocs, get_file_locs_max_depth,
+    file_checks, FILELIST_VERSION};
+pub use indexedreader::IndexedReader;
@@ -11,15 +11,20 @@ use simple_protocolbuffers as spb;
 
 use crate::utils::Timer;
 use crate::logging::{ProgressPercent,ProgressBytes};
-use crate::{progress_bytes};
+use crate::{message,progress_bytes};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 //use indicatif::{ProgressBar, ProgressStyle};
 
 //extern crate lzma_rs;
 //extern crate lz4_flex;
 extern crate lzma;
+extern crate zstd;
 use crate::brotli_compression::{compress_brotli, decompress_brotli};
 
 const LZMA_PRESET: u32 = 3;
+const ZSTD_LEVEL: i32 = 3;
 
 #[derive(Debug, Clone, Copy)]
 pub enum CompressionType {
@@ -30,9 +35,71 @@ pub enum CompressionType {
     ZlibLevel(u32),
     BrotliLevel(u32),
     LzmaLevel(u32),
-    //Lz4
+    Zstd,
+    ZstdLevel(u32),
+    Lz4,
+}
+
+
+/// What to do with blocks which can't be read or decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadBlockPolicy {
+    Abort,
+    SkipAndReport,
 }
 
+/// Applies a [`BadBlockPolicy`] to one read. Clones share their state, so
+/// the same `BadBlocks` should be passed to a reader and to the callbacks
+/// converting its blocks: with `Abort` the reader then stops at the first
+/// bad block, and with `SkipAndReport` it counts the blocks skipped.
+#[derive(Debug, Clone)]
+pub struct BadBlocks {
+    policy: BadBlockPolicy,
+    failed: Arc<AtomicBool>,
+    num_skipped: Arc<AtomicUsize>,
+}
+
+impl BadBlocks {
+    pub fn new(policy: BadBlockPolicy) -> BadBlocks {
+        BadBlocks {
+            policy: policy,
+            failed: Arc::new(AtomicBool::new(false)),
+            num_skipped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn abort() -> BadBlocks {
+        BadBlocks::new(BadBlockPolicy::Abort)
+    }
+
+    pub fn policy(&self) -> BadBlockPolicy {
+        self.policy
+    }
+
+    /// True once a bad block has been found with the `Abort` policy.
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    pub fn num_skipped(&self) -> usize {
+        self.num_skipped.load(Ordering::SeqCst)
+    }
+
+    //returns err if reading should stop, otherwise reports the block as skipped
+    pub(crate) fn check(&self, err: crate::Error) -> crate::Result<()> {
+        match self.policy {
+            BadBlockPolicy::SkipAndReport => {
+                self.num_skipped.fetch_add(1, Ordering::SeqCst);
+                message!("skipping bad block: {}", err);
+                Ok(())
+            }
+            BadBlockPolicy::Abort => {
+                self.failed.store(true, Ordering::SeqCst);
+                Err(err)
+            }
+        }
+    }
+}
 
 pub fn read_file_data<R: Read>(file: &mut R, nbytes: u64) -> io::Result<Vec<u8>> {
     let mut res = vec![0u8; nbytes as usize];
@@ -48,6 +115,8 @@ pub struct FileBlock {
     pub data_raw: Vec<u8>,
     pub data_len: u64,
     pub compression_type: CompressionType,
+    /// The file the block was read from, if known.
+    pub fname: String,
 }
 impl FileBlock {
     pub fn new() -> FileBlock {
@@ -58,42 +127,88 @@ impl FileBlock {
             data_raw: Vec::new(),
             data_len: 0,
             compression_type: CompressionType::Uncompressed,
+            fname: String::new(),
+        }
+    }
+
+    /// Describes where the block was read from, for error messages.
+    pub fn location(&self) -> String {
+        if self.fname.is_empty() {
+            format!("block @ {}", self.pos)
+        } else {
+            format!("{} @ {}", self.fname, self.pos)
         }
     }
 
     pub fn data(&self) -> Vec<u8> {
+        match self.try_data() {
+            Ok(d) => d,
+            Err(e) => panic!("{}", e),
+        }
+    }
 
-        match self.compression_type {
-            CompressionType::Uncompressed => self.data_raw.clone(),
+    pub fn try_data(&self) -> crate::Result<Vec<u8>> {
+        let res = match self.compression_type {
+            CompressionType::Uncompressed => Ok(self.data_raw.clone()),
             CompressionType::Zlib | CompressionType::ZlibLevel(_) => self.read_data_zlib(),
             CompressionType::Brotli | CompressionType::BrotliLevel(_)=> self.read_data_brotli(),
             CompressionType::Lzma | CompressionType::LzmaLevel(_)=> self.read_data_lzma(),
-            //CompressionType::Lz4 => self.read_data_lz4(),
+            CompressionType::Zstd | CompressionType::ZstdLevel(_) => self.read_data_zstd(),
+            CompressionType::Lz4 => self.read_data_lz4(),
+        };
+        
+        match res {
+            Ok(d) => {
+                match self.compression_type {
+                    CompressionType::Uncompressed => {},
+                    _ => {
+                        if self.data_len > 0 && d.len() as u64 != self.data_len {
+                            return Err(crate::Error::PbfDataError(format!(
+                                "{:?} blob in {} has length {}, expected {}",
+                                self.compression_type, self.location(), d.len(), self.data_len)));
+                        }
+                    }
+                }
+                Ok(d)
+            },
+            Err(e) => Err(crate::Error::PbfDataError(format!(
+                "failed to decompress {:?} blob in {}: {}", self.compression_type, self.location(), e))),
         }
     }
     
-    fn read_data_zlib(&self) -> Vec<u8> {
+    fn read_data_zlib(&self) -> io::Result<Vec<u8>> {
         let mut comp = Vec::new();
         let mut xx = ZlibDecoder::new(&self.data_raw[..]);
         
-        xx.read_to_end(&mut comp).expect(&format!("failed to decompress data at pos {}", self.pos));
-        comp
+        xx.read_to_end(&mut comp)?;
+        Ok(comp)
     }
 
-    fn read_data_brotli(&self) -> Vec<u8> {
+    fn read_data_brotli(&self) -> io::Result<Vec<u8>> {
 
-        decompress_brotli(&self.data_raw[..]).expect(&format!("failed to decompress data at pos {}", self.pos))
+        decompress_brotli(&self.data_raw[..])
     }
 
-    fn read_data_lzma(&self) -> Vec<u8> {
+    fn read_data_lzma(&self) -> io::Result<Vec<u8>> {
         //let mut bf = std::io::BufReader::new(&self.data_raw[..]);
-        lzma::decompress(&self.data_raw[..]).unwrap()
+        match lzma::decompress(&self.data_raw[..]) {
+            Ok(d) => Ok(d),
+            Err(e) => Err(Error::new(ErrorKind::Other, format!("{:?}", e))),
+        }
         
     }
-    /*
-    fn read_data_lz4(&self) -> Vec<u8> {
-        lz4_flex::decompress_size_prepended(&self.data_raw[..]).unwrap()//, self.data_len as usize).unwrap()
-    }*/
+
+    fn read_data_zstd(&self) -> io::Result<Vec<u8>> {
+        zstd::decode_all(&self.data_raw[..])
+    }
+    
+    fn read_data_lz4(&self) -> io::Result<Vec<u8>> {
+        //lz4_data is a raw lz4 block: the uncompressed size is stored in raw_size
+        match lz4_flex::decompress(&self.data_raw[..], self.data_len as usize) {
+            Ok(d) => Ok(d),
+            Err(e) => Err(Error::new(ErrorKind::Other, format!("{:?}", e))),
+        }
+    }
         
 }
 
@@ -141,7 +256,7 @@ pub fn read_file_block_with_pos<F: Read>(
     }
     fb.len = 4 + l + ln;
 
-    let c = read_file_data(file, ln).unwrap();
+    let c = read_file_data(file, ln)?;
     pos += ln;
 
     for tg in spb::IterTags::new(&c) {
@@ -156,10 +271,14 @@ pub fn read_file_block_with_pos<F: Read>(
                 fb.compression_type = CompressionType::Lzma;
                 fb.data_raw = d.to_vec();
             },
-            /*spb::PbfTag::Data(6, d) => {
+            spb::PbfTag::Data(6, d) => {
                 fb.compression_type = CompressionType::Lz4;
                 fb.data_raw = d.to_vec();
-            },*/
+            },
+            spb::PbfTag::Data(7, d) => {
+                fb.compression_type = CompressionType::Zstd;
+                fb.data_raw = d.to_vec();
+            },
             spb::PbfTag::Data(8, d) => {
                 fb.compression_type = CompressionType::Brotli;
                 fb.data_raw = d.to_vec();
@@ -240,14 +359,27 @@ pub fn pack_file_block(blockname: &str, data: &[u8], compression_type: &Compress
             spb::pack_value(&mut body, 2, data.len() as u64);
             spb::pack_data(&mut body, 4, &comp[..]);
         },
-        /*
+        CompressionType::Zstd => {
+            let comp = zstd::encode_all(data, ZSTD_LEVEL)?;
+            
+            body.reserve(comp.len()+25);
+            spb::pack_value(&mut body, 2, data.len() as u64);
+            spb::pack_data(&mut body, 7, &comp[..]);
+        },
+        CompressionType::ZstdLevel(level) => {
+            let comp = zstd::encode_all(data, *level as i32)?;
+            
+            body.reserve(comp.len()+25);
+            spb::pack_value(&mut body, 2, data.len() as u64);
+            spb::pack_data(&mut body, 7, &comp[..]);
+        },
         CompressionType::Lz4 => {
-            let comp = lz4_flex::compress_prepend_size(&data[..]);
+            let comp = lz4_flex::compress(&data[..]);
             
             body.reserve(comp.len()+25);
             spb::pack_value(&mut body, 2, data.len() as u64);
             spb::pack_data(&mut body, 6, &comp[..]);
-        },*/
+        },
     }
 
     let mut head = Vec::with_capacity(25);
@@ -369,24 +501,220 @@ impl Iterator for ReadFileBlocksOwn {
     }
 }
 
-pub fn read_all_blocks<T, U>(fname: &str, mut pp: Box<T>) -> (U, f64)
+/// Input filename meaning read from stdin.
+pub const STDIN_FILENAME: &str = "-";
+
+pub fn is_stdin(fname: &str) -> bool {
+    fname == STDIN_FILENAME
+}
+
+/// Opens `fname` for sequential reading, or stdin if `fname` is "-". The
+/// returned reader is not buffered.
+pub fn open_input(fname: &str) -> io::Result<Box<dyn Read>> {
+    if is_stdin(fname) {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(File::open(fname)?))
+    }
+}
+
+/// Reads blocks from any `Read`, such as stdin or a decompressing reader,
+/// finding the position of each block by counting bytes. Unlike
+/// `ReadFileBlocks`, input which ends partway through a block is returned
+/// as an error rather than treated as the end of the file.
+pub struct StreamFileBlocks<R: Read> {
+    reader: R,
+    p: u64,
+    finished: bool,
+}
+
+impl<R> StreamFileBlocks<R>
+where
+    R: Read,
+{
+    pub fn new(reader: R) -> StreamFileBlocks<R> {
+        StreamFileBlocks {
+            reader: reader,
+            p: 0,
+            finished: false,
+        }
+    }
+
+    /// Number of bytes read so far, which is the position of the next block.
+    pub fn position(&self) -> u64 {
+        self.p
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_head(&mut self) -> io::Result<Option<[u8; 4]>> {
+        let mut head = [0u8; 4];
+        let mut nread = 0;
+        while nread < 4 {
+            match self.reader.read(&mut head[nread..]) {
+                Ok(0) => break,
+                Ok(n) => {
+                    nread += n;
+                }
+                Err(e) => {
+                    if e.kind() != ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        if nread == 0 {
+            Ok(None)
+        } else if nread < 4 {
+            Err(Error::new(ErrorKind::UnexpectedEof, format!("input truncated @ {}", self.p)))
+        } else {
+            Ok(Some(head))
+        }
+    }
+}
+
+impl<R> Iterator for StreamFileBlocks<R>
+where
+    R: Read,
+{
+    type Item = io::Result<FileBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let head = match self.read_head() {
+            Ok(Some(head)) => head,
+            Ok(None) => {
+                //at end of input
+                self.finished = true;
+                return None;
+            }
+            Err(e) => {
+                self.finished = true;
+                return Some(Err(e));
+            }
+        };
+
+        let mut rd = (&head[..]).chain(&mut self.reader);
+        match read_file_block_with_pos(&mut rd, self.p) {
+            Ok((p, fb)) => {
+                self.p = p;
+                Some(Ok(fb))
+            }
+            Err(e) => {
+                self.finished = true;
+                if e.kind() == ErrorKind::UnexpectedEof {
+                    Some(Err(Error::new(ErrorKind::UnexpectedEof, format!("input truncated in block @ {}", self.p))))
+                } else {
+                    Some(Err(e))
+                }
+            }
+        }
+    }
+}
+
+fn read_error(fname: Option<&str>, pos: u64, err: io::Error) -> crate::Error {
+    match fname {
+        Some(fname) => crate::Error::PbfDataError(format!("failed to read {} @ {}: {}", fname, pos, err)),
+        None => crate::Error::PbfDataError(format!("failed to read block @ {}: {}", pos, err)),
+    }
+}
+
+fn finish_error<E: Into<crate::Error>>(err: channelled_callbacks::Error<E>) -> crate::Error {
+    match err {
+        channelled_callbacks::Error::OtherError(e) => e.into(),
+        channelled_callbacks::Error::ChannelledCallbackError(s) => crate::Error::ChannelledCallbackError(s),
+    }
+}
+
+fn finish_all<T, U>(pp: &mut Box<T>, res: crate::Result<()>) -> crate::Result<U>
+where
+    T: CallFinish<ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
+    U: Send + Sync + 'static,
+{
+    //always finish pp so that any callback threads are closed
+    let r = pp.finish();
+    res?;
+    match r {
+        Ok(r) => Ok(r),
+        Err(e) => Err(finish_error(e)),
+    }
+}
+
+fn read_blocks_sequential<R: Read>(
+    fobj: &mut R,
+    fname: Option<&str>,
+    stop_at: u64,
+    bad_blocks: &BadBlocks,
+    mut call: impl FnMut(usize, FileBlock),
+) -> crate::Result<()> {
+    //blocks can't be skipped here: after a bad block header we don't know
+    //where the next block starts
+    let mut blocks = StreamFileBlocks::new(fobj);
+    let mut i = 0;
+    while blocks.position() <= stop_at && !bad_blocks.failed() {
+        let pos = blocks.position();
+        match blocks.next() {
+            None => {
+                return Ok(());
+            }
+            Some(Ok(mut fb)) => {
+                if let Some(fname) = fname {
+                    fb.fname = fname.to_string();
+                }
+                call(i, fb);
+                i += 1;
+            }
+            Some(Err(err)) => {
+                return Err(read_error(fname, pos, err));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_block_at<F: Read + Seek>(fobj: &mut F, curr_pos: u64, pos: u64) -> io::Result<(u64, FileBlock)> {
+    if curr_pos != pos {
+        fobj.seek(SeekFrom::Start(pos))?;
+    }
+    read_file_block_with_pos(fobj, pos)
+}
+
+pub fn read_all_blocks<T, U>(fname: &str, mut pp: Box<T>, bad_blocks: &BadBlocks) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, FileBlock), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
 {
     let tx=Timer::new();
-    let f = File::open(fname).expect("fail");
+    let f = open_input(fname)?;
     let mut fbuf = BufReader::new(f);
-    for (i, fb) in ReadFileBlocks::new(&mut fbuf).enumerate() {
+    let res = read_blocks_sequential(&mut fbuf, Some(fname), u64::MAX, bad_blocks, |i, fb| {
         pp.call((i, fb));
+    });
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, tx.since()))
+}
+
+/// Returns the length of `fname` in bytes, or 0 for stdin where the length
+/// isn't known in advance.
+pub fn try_file_length(fname: &str) -> crate::Result<u64> {
+    if is_stdin(fname) {
+        return Ok(0);
+    }
+    match std::fs::metadata(fname) {
+        Ok(m) => Ok(m.len()),
+        Err(e) => Err(crate::Error::MissingDataError(format!("failed to open {}: {}", fname, e))),
     }
-    (pp.finish().expect("finish failed"), tx.since())
 }
 
+/// As [`try_file_length`], but panics if `fname` can't be opened.
 pub fn file_length(fname: &str) -> u64 {
-    std::fs::metadata(fname)
-        .expect(&format!("failed to open {}", fname))
-        .len()
+    try_file_length(fname).expect(&format!("failed to open {}", fname))
 }
 
 pub fn read_all_blocks_prog<R: Read, T, U>(
@@ -395,48 +723,52 @@ pub fn read_all_blocks_prog<R: Read, T, U>(
     mut pp: Box<T>,
     pb: &Box<dyn ProgressPercent>,
     start_percent: f64,
-    end_percent: f64
-) -> (U, f64)
+    end_percent: f64,
+    bad_blocks: &BadBlocks,
+) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, FileBlock), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
 {
     let ct = Timer::new();
 
     let pf = (end_percent-start_percent) / (flen as f64);
 
-    for (i, fb) in ReadFileBlocks::new_at_start(fobj).enumerate() {
+    let res = read_blocks_sequential(fobj, None, u64::MAX, bad_blocks, |i, fb| {
         pb.progress_percent((fb.pos as f64) * pf + start_percent);
 
         pp.call((i, fb));
-    }
+    });
     pb.progress_percent(end_percent);
-    let r = pp.finish().expect("finish failed");
+    let r = finish_all(&mut pp, res)?;
 
     
-    (r, ct.since())
+    Ok((r, ct.since()))
 }
 
 pub fn read_all_blocks_prog_fpos<R: Read, T, U>(
     fobj: &mut R,
     mut pp: Box<T>,
-    pg: Box<dyn ProgressBytes>
-    
-) -> (U, f64)
+    pg: Box<dyn ProgressBytes>,
+    bad_blocks: &BadBlocks,
+) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, FileBlock), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
 {
     let ct = Timer::new();
 
-    for (i, fb) in ReadFileBlocks::new_at_start(fobj).enumerate() {
+    let res = read_blocks_sequential(fobj, None, u64::MAX, bad_blocks, |i, fb| {
         
         pg.progress_bytes(fb.pos);
         pp.call((i, fb));
-    }
+    });
     
     pg.finish();
-    (pp.finish().expect("finish failed"), ct.since())
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, ct.since()))
 }
 
 pub fn read_all_blocks_prog_fpos_stop<R: Read, T, U>(
@@ -444,79 +776,103 @@ pub fn read_all_blocks_prog_fpos_stop<R: Read, T, U>(
     stop_at: u64,
     mut pp: Box<T>,
     pb: Box<dyn ProgressBytes>,
-) -> (U, f64)
+    bad_blocks: &BadBlocks,
+) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, FileBlock), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
 {
     let ct = Timer::new();
-    for (i, fb) in ReadFileBlocks::new_at_start_with_stop(fobj, stop_at).enumerate() {
+    let res = read_blocks_sequential(fobj, None, stop_at, bad_blocks, |i, fb| {
         pb.progress_bytes(fb.pos);
         pp.call((i, fb));
-    }
+    });
     pb.finish();
-    (pp.finish().expect("finish failed"), ct.since())
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, ct.since()))
 }
 
-pub fn read_all_blocks_with_progbar<T, U>(fname: &str, pp: Box<T>, msg: &str) -> (U, f64)
+pub fn read_all_blocks_with_progbar<T, U>(fname: &str, mut pp: Box<T>, msg: &str, bad_blocks: &BadBlocks) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, FileBlock), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
 {
-    let fl = file_length(fname);
+    let fl = try_file_length(fname)?;
     //let pb = ProgBarWrap::new_filebytes(fl);
     //pb.set_message(msg);
     let pg = progress_bytes!(msg, fl);
 
-    let fobj = File::open(fname).expect("failed to open file");
+    let fobj = open_input(fname)?;
     let mut fbuf = BufReader::new(fobj);
 
-    read_all_blocks_prog_fpos(&mut fbuf, pp, pg)
+    let ct = Timer::new();
+    let res = read_blocks_sequential(&mut fbuf, Some(fname), u64::MAX, bad_blocks, |i, fb| {
+        
+        pg.progress_bytes(fb.pos);
+        pp.call((i, fb));
+    });
+    
+    pg.finish();
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, ct.since()))
 }
 
-pub fn read_all_blocks_vec_with_progbar<T, U>(fname: &str, mut pp: Box<T>, msg: &str) -> (U, f64)
+pub fn read_all_blocks_vec_with_progbar<T, U>(fname: &str, mut pp: Box<T>, msg: &str, bad_blocks: &BadBlocks) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
 {
-    let fl = file_length(fname);
+    let fl = try_file_length(fname)?;
     //let pb = ProgBarWrap::new_filebytes(fl);
     //pb.set_message(msg);
     let pg = progress_bytes!(msg, fl);
 
-    let fobj = File::open(fname).expect("failed to open file");
+    let fobj = open_input(fname)?;
     let mut fbuf = BufReader::new(fobj);
 
     let ct = Timer::new();
 
-    for (i, fb) in ReadFileBlocks::new_at_start(&mut fbuf).enumerate() {
+    let res = read_blocks_sequential(&mut fbuf, Some(fname), u64::MAX, bad_blocks, |i, fb| {
         
         pg.progress_bytes(fb.pos);
         pp.call((i, vec![fb]));
-    }
+    });
     
     pg.finish();
-    (pp.finish().expect("finish failed"), ct.since())
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, ct.since()))
 }
 
 
 pub fn read_all_blocks_with_progbar_stop<T, U>(
     fname: &str,
     stop_after: u64,
-    pp: Box<T>,
+    mut pp: Box<T>,
     msg: &str,
-) -> (U, f64)
+    bad_blocks: &BadBlocks,
+) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, FileBlock), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
 {
     let pb = progress_bytes!(msg, stop_after);
     
 
-    let fobj = File::open(fname).expect("failed to open file");
+    let fobj = open_input(fname)?;
     let mut fbuf = BufReader::new(fobj);
 
-    read_all_blocks_prog_fpos_stop(&mut fbuf, stop_after, pp, pb)
+    let ct = Timer::new();
+    let res = read_blocks_sequential(&mut fbuf, Some(fname), stop_after, bad_blocks, |i, fb| {
+        pb.progress_bytes(fb.pos);
+        pp.call((i, fb));
+    });
+    pb.finish();
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, ct.since()))
 }
 
 
@@ -527,10 +883,12 @@ pub fn read_all_blocks_locs_prog<R, T, U>(
     mut pp: Box<T>,
     pb: &Box<dyn ProgressPercent>,
     start_percent: f64,
-    end_percent: f64
-) -> (U, f64)
+    end_percent: f64,
+    bad_blocks: &BadBlocks,
+) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, FileBlock), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
     R: Read + Seek,
 {
@@ -538,105 +896,189 @@ where
 
     let pf = (end_percent-start_percent) / (locs.len() as f64);
 
+    let mut res = Ok(());
     for (i, l) in locs.iter().enumerate() {
-        fobj.seek(SeekFrom::Start(*l))
-            .expect(&format!("failed to read {} @ {}", fname, *l));
-        let (_, fb) = read_file_block_with_pos(fobj, *l)
-            .expect(&format!("failed to read {} @ {}", fname, *l));
-
-        pb.progress_percent(((i + 1) as f64) * pf + start_percent);
+        if bad_blocks.failed() {
+            break;
+        }
+        match read_block_at(fobj, u64::MAX, *l) {
+            Ok((_, mut fb)) => {
+                pb.progress_percent(((i + 1) as f64) * pf + start_percent);
 
-        pp.call((i, fb));
+                fb.fname = fname.to_string();
+                pp.call((i, fb));
+            }
+            Err(err) => {
+                if let Err(e) = bad_blocks.check(read_error(Some(fname), *l, err)) {
+                    res = Err(e);
+                    break;
+                }
+            }
+        }
     }
     pb.progress_percent(end_percent);
-    (pp.finish().expect("finish failed"), ct.since())
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, ct.since()))
+}
+
+fn read_parallel_blocks<F: Seek + Read, Q>(
+    fbufs: &mut Vec<F>,
+    fnames: &[String],
+    locs: &Vec<(Q, Vec<(usize, u64)>)>,
+    bad_blocks: &BadBlocks,
+    mut call: impl FnMut(usize, Vec<FileBlock>, u64),
+) -> crate::Result<()> {
+    let mut fposes = Vec::new();
+    for f in fbufs.iter_mut() {
+        fposes.push(file_position(f)?);
+    }
+
+    for (j, (_, ll)) in locs.iter().enumerate() {
+        if bad_blocks.failed() {
+            break;
+        }
+        let mut fbs = Vec::new();
+        let mut len = 0;
+        for (a, b) in ll {
+            match read_block_at(&mut fbufs[*a], fposes[*a], *b) {
+                Ok((x, mut y)) => {
+                    y.fname = fnames[*a].clone();
+                    fbs.push(y);
+                    fposes[*a] = x;
+                    len += x - *b;
+                }
+                Err(err) => {
+                    bad_blocks.check(read_error(Some(fnames[*a].as_str()), *b, err))?;
+                    //force a seek before the next read from this file
+                    fposes[*a] = u64::MAX;
+                }
+            }
+        }
+        call(j, fbs, len);
+    }
+    Ok(())
 }
 
 pub fn read_all_blocks_parallel_prog<T, U, F, Q>(
     fbufs: &mut Vec<F>,
+    fnames: &[String],
     locs: &Vec<(Q, Vec<(usize, u64)>)>,
     mut pp: Box<T>,
     pb: Box<dyn ProgressPercent>,
-) -> (U, f64)
+    bad_blocks: &BadBlocks,
+) -> crate::Result<(U, f64)>
 where
     T: CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
     F: Seek + Read,
 {
     let ct = Timer::new();
 
-    let mut fposes = Vec::new();
-    for f in fbufs.iter_mut() {
-        fposes.push(file_position(f).expect("!"));
-    }
     let pf = 100.0 / (locs.len() as f64);
-    for (j, (_, ll)) in locs.iter().enumerate() {
-        let mut fbs = Vec::new();
-        for (a, b) in ll {
-            if fposes[*a] != *b {
-                fbufs[*a]
-                    .seek(SeekFrom::Start(*b))
-                    .expect(&format!("failed to read {} @ {}", *a, *b));
-            }
-
-            let (x, y) = read_file_block_with_pos(&mut fbufs[*a], *b)
-                .expect(&format!("failed to read {} @ {}", *a, *b));
-
-            fbs.push(y);
-            fposes[*a] = x;
-        }
-
+    let res = read_parallel_blocks(fbufs, fnames, locs, bad_blocks, |j, fbs, _| {
         pb.progress_percent(((j + 1) as f64) * pf);
 
         pp.call((j, fbs));
-    }
+    });
     pb.finish();
-    (pp.finish().expect("finish failed"), ct.since())
+    let r = finish_all(&mut pp, res)?;
+    Ok((r, ct.since()))
 }
 
 pub fn read_all_blocks_parallel_with_progbar<T, U, F, Q>(
     fbufs: &mut Vec<F>,
+    fnames: &[String],
     locs: &Vec<(Q, Vec<(usize, u64)>)>,
     mut pp: Box<T>,
     msg: &str,
     total_len: u64,
-) -> U
+    bad_blocks: &BadBlocks,
+) -> crate::Result<U>
 where
     T: CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = U> + ?Sized,
+    <T as CallFinish>::ErrorType: Into<crate::Error>,
     U: Send + Sync + 'static,
     F: Seek + Read,
 {
-    let mut fposes = Vec::new();
-    for f in fbufs.iter_mut() {
-        fposes.push(file_position(f).expect("!"));
-    }
-
     let pb = progress_bytes!(msg,total_len);
     
 
     let mut pos = 0;
-    for (j, (_, ll)) in locs.iter().enumerate() {
-        let mut fbs = Vec::new();
+    let res = read_parallel_blocks(fbufs, fnames, locs, bad_blocks, |j, fbs, len| {
+        pos += len;
+        pb.progress_bytes(pos);
 
-        for (a, b) in ll {
-            if fposes[*a] != *b {
-                fbufs[*a]
-                    .seek(SeekFrom::Start(*b))
-                    .expect(&format!("failed to read {} @ {}", *a, *b));
-            }
+        pp.call((j, fbs));
+    });
+    pb.finish();
+    finish_all(&mut pp, res)
+}
 
-            let (x, y) = read_file_block_with_pos(&mut fbufs[*a], *b)
-                .expect(&format!("failed to read {} @ {}", *a, *b));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            fbs.push(y);
-            fposes[*a] = x;
-            pos += x - *b;
+    fn test_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..5000u32 {
+            data.extend(format!("node {} {}\n", i, i % 17).as_bytes());
         }
+        data
+    }
 
-        pb.progress_bytes(pos);
+    fn round_trip(compression_type: CompressionType) {
+        let data = test_data();
+        let packed = pack_file_block("OSMData", &data, &compression_type).unwrap();
 
-        pp.call((j, fbs));
+        let fb = unpack_file_block(0, &packed).unwrap();
+        assert_eq!(fb.block_type, "OSMData");
+        assert_eq!(fb.len, packed.len() as u64);
+        assert_eq!(fb.data_len, data.len() as u64);
+        assert!(fb.data_raw.len() < data.len());
+        assert_eq!(fb.data(), data);
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        round_trip(CompressionType::Zstd);
+        round_trip(CompressionType::ZstdLevel(9));
+    }
+
+    #[test]
+    fn lz4_round_trip() {
+        round_trip(CompressionType::Lz4);
+    }
+
+    #[test]
+    fn read_blocks_from_file() {
+        let data = test_data();
+        let mut file = Vec::new();
+        file.extend(pack_file_block("OSMHeader", b"header", &CompressionType::Uncompressed).unwrap());
+        file.extend(pack_file_block("OSMData", &data, &CompressionType::Zstd).unwrap());
+        file.extend(pack_file_block("OSMData", &data, &CompressionType::Lz4).unwrap());
+
+        let mut cursor = Cursor::new(file);
+        let blocks = ReadFileBlocks::new(&mut cursor).collect::<Vec<FileBlock>>();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].data(), b"header");
+        assert_eq!(blocks[1].data(), data);
+        assert_eq!(blocks[2].data(), data);
+        assert_eq!(blocks[2].pos, blocks[1].pos + blocks[1].len);
+    }
+
+    #[test]
+    fn corrupt_blob_is_error() {
+        let data = test_data();
+        for compression_type in [CompressionType::Zstd, CompressionType::Lz4] {
+            let packed = pack_file_block("OSMData", &data, &compression_type).unwrap();
+            let mut fb = unpack_file_block(0, &packed).unwrap();
+            let n = fb.data_raw.len();
+            fb.data_raw.truncate(n / 2);
+            match fb.try_data() {
+                Err(crate::Error::PbfDataError(_)) => {}
+                r => panic!("expected PbfDataError for {:?}, got {:?}", compression_type, r.map(|d| d.len())),
+            }
+        }
     }
-    pb.finish();
-    pp.finish().expect("finish failed")
 }
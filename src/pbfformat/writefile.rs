@@ -1,7 +1,7 @@
 use channelled_callbacks::{CallFinish, Result as ccResult};
 use crate::elements::Bbox;
 use crate::pbfformat::pack_file_block;
-use crate::pbfformat::{make_header_block, HeaderType, CompressionType};
+use crate::pbfformat::{make_header_block, HeaderType, HeaderInfo, CompressionType};
 use crate::utils::{ThreadTimer, Error};
 
 use serde_json;
@@ -31,6 +31,12 @@ impl WriteFile {
     pub fn with_compression_type(
             outfn: &str, header_type: HeaderType,
             bbox: Option<&Bbox>, compression_type: CompressionType) -> WriteFile {
+        WriteFile::with_header_info(outfn, header_type, bbox, compression_type, &HeaderInfo::new())
+    }
+
+    pub fn with_header_info(
+            outfn: &str, header_type: HeaderType,
+            bbox: Option<&Bbox>, compression_type: CompressionType, header_info: &HeaderInfo) -> WriteFile {
         
         let mut outf = Some(File::create(outfn).expect("failed to create"));
         let mut write_external_locs = false;
@@ -40,7 +46,7 @@ impl WriteFile {
                 outf.as_mut()
                     .unwrap()
                     .write_all(
-                        &pack_file_block("OSMHeader", &make_header_block(false, bbox), &compression_type)
+                        &pack_file_block("OSMHeader", &make_header_block(false, bbox, header_info), &compression_type)
                             .expect("?"),
                     )
                     .expect("?");
@@ -49,7 +55,7 @@ impl WriteFile {
                 outf.as_mut()
                     .unwrap()
                     .write_all(
-                        &pack_file_block("OSMHeader", &make_header_block(true, bbox), &compression_type)
+                        &pack_file_block("OSMHeader", &make_header_block(true, bbox, header_info), &compression_type)
                             .expect("?"),
                     )
                     .expect("?");
@@ -11,7 +11,7 @@ use crate::pbfformat::{FileBlock, ReadFileBlocks};
 use channelled_callbacks::{CallFinish,CallAll, Result as ccResult, Error as ccError};
 use crate::elements::{ElementType, Quadtree, QuadtreeBlock};
 
-use crate::utils::{ThreadTimer, Error};
+use crate::utils::{ThreadTimer, Error, Result};
 
 use crate::sortblocks::Timings;
 
@@ -106,8 +106,10 @@ impl Iterator for ChannelQuadtreeBlockFlatIter {
 pub struct AddQuadtree<T> {
     qts: Box<ChannelQuadtreeBlockFlatIter>,
     curr: Option<(ElementType, i64, Quadtree)>,
+    last: Option<(ElementType, i64, Quadtree)>,
     tot: f64,
     out: Box<T>,
+    error: Option<Error>,
 }
 
 impl<T> AddQuadtree<T>
@@ -121,71 +123,101 @@ where
         AddQuadtree {
             qts,
             curr,
+            last: None,
             out,
             tot,
+            error: None,
         }
     }
-}
 
-impl<T> CallFinish for AddQuadtree<T>
-where
-    T: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings, ErrorType=Error>,
-{
-    type CallType = PrimitiveBlock;
-    type ReturnType = Timings;
-    type ErrorType = Error;
+    //full-history files have several versions of each object: the qts file
+    //may have an entry for each version, or just one for each id
+    fn next_quadtree(&mut self, ty: ElementType, id: i64) -> Option<Quadtree> {
+        match &self.curr {
+            Some((t, i, q)) if *t == ty && *i == id => {
+                let q = *q;
+                self.last = self.curr.take();
+                self.curr = self.qts.next();
+                return Some(q);
+            }
+            _ => {}
+        }
+        match &self.last {
+            Some((t, i, q)) if *t == ty && *i == id => Some(*q),
+            _ => None,
+        }
+    }
 
-    fn call(&mut self, mut bl: PrimitiveBlock) {
-        let ct = ThreadTimer::new();
+    fn missing_quadtree(&self, ty: ElementType, id: i64) -> Error {
+        match &self.curr {
+            None => Error::PbfDataError(format!("ran out of qts at {:?} {}", ty, id)),
+            Some((t, i, _)) => Error::PbfDataError(format!(
+                "qts out of sync at {:?} {}: next qt is for {:?} {}",
+                ty, id, t, i
+            )),
+        }
+    }
+
+    fn add_quadtrees(&mut self, bl: &mut PrimitiveBlock) -> Result<()> {
         for n in bl.nodes.iter_mut() {
-            match &self.curr {
-                None => {
-                    panic!("ran out of qts");
+            match self.next_quadtree(ElementType::Node, n.id) {
+                Some(qt) => {
+                    n.quadtree = qt;
                 }
-                Some((ty, id, qt)) => {
-                    if *ty == ElementType::Node && *id == n.id {
-                        n.quadtree = *qt;
-                        self.curr = self.qts.next();
-                    } else {
-                        panic!("out of sync");
-                    }
+                None => {
+                    return Err(self.missing_quadtree(ElementType::Node, n.id));
                 }
             }
         }
 
         for w in bl.ways.iter_mut() {
-            match &self.curr {
-                None => {
-                    panic!("ran out of qts");
+            match self.next_quadtree(ElementType::Way, w.id) {
+                Some(qt) => {
+                    w.quadtree = qt;
                 }
-                Some((ty, id, qt)) => {
-                    if *ty == ElementType::Way && *id == w.id {
-                        w.quadtree = *qt;
-                        self.curr = self.qts.next();
-                    } else {
-                        panic!("out of sync");
-                    }
+                None => {
+                    return Err(self.missing_quadtree(ElementType::Way, w.id));
                 }
             }
         }
 
         for r in bl.relations.iter_mut() {
-            match &self.curr {
-                None => {
-                    panic!("ran out of qts");
+            match self.next_quadtree(ElementType::Relation, r.id) {
+                Some(qt) => {
+                    r.quadtree = qt;
                 }
-                Some((ty, id, qt)) => {
-                    if *ty == ElementType::Relation {
-                        if *id > r.id {
-                            r.quadtree = Quadtree::new(0);
-                        } else if *id == r.id {
-                            r.quadtree = *qt;
-                            self.curr = self.qts.next();
-                        } else {
-                            panic!("out of sync");
-                        }
+                None => match &self.curr {
+                    Some((ElementType::Relation, id, _)) if *id > r.id => {
+                        r.quadtree = Quadtree::new(0);
                     }
-                }
+                    _ => {
+                        return Err(self.missing_quadtree(ElementType::Relation, r.id));
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> CallFinish for AddQuadtree<T>
+where
+    T: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings, ErrorType=Error>,
+{
+    type CallType = PrimitiveBlock;
+    type ReturnType = Timings;
+    type ErrorType = Error;
+
+    fn call(&mut self, mut bl: PrimitiveBlock) {
+        if self.error.is_some() {
+            return;
+        }
+        let ct = ThreadTimer::new();
+        match self.add_quadtrees(&mut bl) {
+            Ok(()) => {}
+            Err(e) => {
+                self.error = Some(e);
+                return;
             }
         }
         self.tot += ct.since();
@@ -193,6 +225,12 @@ where
     }
 
     fn finish(&mut self) -> ccResult<Self::ReturnType, Self::ErrorType> {
+        match self.error.take() {
+            Some(e) => {
+                return Err(ccError::OtherError(e));
+            }
+            None => {}
+        }
         match self.curr {
             None => {
                 let mut x = self.out.finish()?;
@@ -6,8 +6,8 @@ use std::sync::Arc;
 use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, MergeTimings,ReplaceNoneWithTimings, Result as ccResult};
 use crate::elements::PrimitiveBlock;
 
-use crate::pbfformat::HeaderType;
-use crate::pbfformat::{read_all_blocks_with_progbar, FileBlock, CompressionType};
+use crate::pbfformat::{HeaderInfo, HeaderType};
+use crate::pbfformat::{read_all_blocks_with_progbar, BadBlocks, FileBlock, CompressionType};
 use crate::sortblocks::addquadtree::{make_unpackprimblock, AddQuadtree};
 use crate::sortblocks::writepbf::{make_packprimblock_qtindex, WriteFile};
 use crate::sortblocks::{OtherData, QuadtreeTree, Timings};
@@ -74,7 +74,7 @@ fn get_blocks(
 
         Box::new(CallbackMerge::new(pps, Box::new(MergeTimings::new())))
     };
-    let (mut res, d) = read_all_blocks_with_progbar(infn, pp, "get_blocks");
+    let (mut res, d) = read_all_blocks_with_progbar(infn, pp, "get_blocks", &BadBlocks::abort())?;
     let mut blocks: Option<Vec<PrimitiveBlock>> = None;
 
     for o in std::mem::take(&mut res.others) {
@@ -103,7 +103,18 @@ pub fn write_blocks(
     timestamp: i64,
     compression_type: CompressionType,
 ) -> Result<()> {
-    let wf = Box::new(WriteFile::with_compression_type(&outfn, HeaderType::ExternalLocs, None, compression_type));
+    write_blocks_with_header_info(outfn, blocks, numchan, timestamp, compression_type, &HeaderInfo::new())
+}
+
+pub fn write_blocks_with_header_info(
+    outfn: &str,
+    blocks: Vec<PrimitiveBlock>,
+    numchan: usize,
+    timestamp: i64,
+    compression_type: CompressionType,
+    header_info: &HeaderInfo,
+) -> Result<()> {
+    let wf = Box::new(WriteFile::with_header_info(&outfn, HeaderType::ExternalLocs, None, compression_type, header_info));
 
     
 
@@ -151,6 +162,8 @@ pub fn sort_blocks_inmem(
         "call get_blocks({}, {}, {}, {})",
         infn, qtsfn, groups, numchan
     );
+    //keeps the HistoricalInformation feature of full-history input files
+    let header_info = HeaderInfo::from_file(infn)?;
     let blocks = get_blocks(infn, qtsfn, groups, numchan)?;
     lt.add("read data");
     message!(
@@ -161,7 +174,7 @@ pub fn sort_blocks_inmem(
         timestamp
     );
     //Err(io::Error::new(io::ErrorKind::Other,"not impl"))
-    write_blocks(outfn, blocks, numchan, timestamp, compression_type)?;
+    write_blocks_with_header_info(outfn, blocks, numchan, timestamp, compression_type, &header_info)?;
     lt.add("write blocks");
     Ok(())
 }
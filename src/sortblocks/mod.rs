@@ -26,9 +26,9 @@ pub enum OtherData {
 
 pub type Timings = channelled_callbacks::Timings<OtherData>;
 
-pub use inmem::{sort_blocks_inmem, write_blocks};
+pub use inmem::{sort_blocks_inmem, write_blocks, write_blocks_with_header_info};
 pub use prepgraph::{find_groups,prepare_quadtree_tree};
-pub use quadtreetree::{find_tree_groups, QuadtreeTree, QuadtreeTreeItem};
+pub use quadtreetree::{find_tree_groups, find_tree_groups_within, QuadtreeTree, QuadtreeTreeItem};
 pub use tempfile::{
     read_temp_data, read_tempfile_locs, read_tempfilesplit_locs, sort_blocks, write_tempfile_locs,
     write_tempfilesplit_locs, WriteTempData, WriteTempFile, WriteTempFileSplit, WriteTempNull
@@ -4,7 +4,7 @@ use std::io::{Result,Error,ErrorKind};
 use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, CallAll, MergeTimings, ReplaceNoneWithTimings, Result as ccResult};
 use crate::elements::Quadtree;
 use crate::elements::QuadtreeBlock;
-use crate::pbfformat::{read_all_blocks_with_progbar, FileBlock};
+use crate::pbfformat::{read_all_blocks_with_progbar, BadBlocks, FileBlock};
 use crate::sortblocks::{find_tree_groups, QuadtreeTree};
 use crate::utils::{LogTimes, Timer};
 use crate::message;
@@ -139,7 +139,10 @@ pub fn prepare_quadtree_tree(qtsfn: &str, numchan: usize, maxdepth: usize) -> Re
         make_convertquadtreeblock(Box::new(AddAll::new()), maxdepth)
     };
 
-    let (mut t, _) = read_all_blocks_with_progbar(qtsfn, cc, "prepare quadtreetree");
+    let (mut t, _) = match read_all_blocks_with_progbar(qtsfn, cc, "prepare quadtreetree", &BadBlocks::abort()) {
+        Ok(r) => r,
+        Err(e) => { return Err(Error::new(ErrorKind::Other, format!("{}", e))); }
+    };
 
     message!("{}", t);
 
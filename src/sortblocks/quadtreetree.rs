@@ -354,22 +354,19 @@ fn find_within(
     }
 }
 
-pub fn find_tree_groups(
-    mut tree: Box<QuadtreeTree>,
+//removes groups of about target weight from tree until it is empty
+fn take_tree_groups<F: FnMut(&QuadtreeTree)>(
+    tree: &mut QuadtreeTree,
     target: i64,
     absmintarget: i64,
-) -> io::Result<Box<QuadtreeTree>> {
-    let pb = progress_percent!("find_tree_groups");
-    let pf = 100.0 / (tree.total_weight() as f64);
-
-    let mut res = Box::new(QuadtreeTree::new());
-
+    mut progress: F,
+) -> Vec<(Quadtree, i64)> {
     let mut mintarget = target - 50;
     let mut maxtarget = target + 50;
 
     let mut all = Vec::new();
     while tree.total_weight() > 0 {
-        pb.progress_percent(100.0 - pf * (tree.total_weight() as f64));
+        progress(tree);
         let vv = find_within(&tree, mintarget, maxtarget, absmintarget);
 
         if vv.is_empty() {
@@ -384,6 +381,22 @@ pub fn find_tree_groups(
             }
         }
     }
+    all
+}
+
+pub fn find_tree_groups(
+    mut tree: Box<QuadtreeTree>,
+    target: i64,
+    absmintarget: i64,
+) -> io::Result<Box<QuadtreeTree>> {
+    let pb = progress_percent!("find_tree_groups");
+    let pf = 100.0 / (tree.total_weight() as f64);
+
+    let mut res = Box::new(QuadtreeTree::new());
+
+    let mut all = take_tree_groups(&mut tree, target, absmintarget, |t| {
+        pb.progress_percent(100.0 - pf * (t.total_weight() as f64))
+    });
     pb.finish();
     all.sort();
     for (a, b) in all {
@@ -395,3 +408,37 @@ pub fn find_tree_groups(
 
     Ok(res)
 }
+
+/// Finds groups for a tree whose entries all lie within the tile `root`.
+/// Unlike [`find_tree_groups`], any remaining entries too small to form a
+/// group are given to `root` rather than the root quadtree, so every group
+/// is `root` or one of its descendants. No progress is shown.
+pub fn find_tree_groups_within(
+    mut tree: Box<QuadtreeTree>,
+    root: &Quadtree,
+    target: i64,
+    absmintarget: i64,
+) -> io::Result<Box<QuadtreeTree>> {
+    let mut res = Box::new(QuadtreeTree::new());
+
+    let mut all = take_tree_groups(&mut tree, target, absmintarget, |_| {});
+    all.sort();
+    for (a, b) in all {
+        let a = if a.as_int() == 0 { *root } else { a };
+        if !root.is_parent(&a) || a.depth() < root.depth() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("group {} not within {}", a, root),
+            ));
+        }
+        if b >= (u32::MAX as i64) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("can't add {} to {}", a, b),
+            ));
+        }
+        res.add(&a, b as u32);
+    }
+
+    Ok(res)
+}
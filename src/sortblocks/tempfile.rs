@@ -10,7 +10,7 @@ use crate::elements::{ PrimitiveBlock};
 
 use crate::pbfformat::{
     file_length, pack_file_block, read_all_blocks_with_progbar, read_file_block_with_pos,
-    unpack_file_block, FileBlock,CompressionType,HeaderType
+    unpack_file_block, BadBlocks, FileBlock,CompressionType,HeaderInfo,HeaderType
 };
 pub use crate::sortblocks::addquadtree::{make_unpackprimblock, AddQuadtree};
 pub use crate::sortblocks::writepbf::{
@@ -449,7 +449,8 @@ fn write_temp_blocks(
         infn,
         pp,
         &format!("write_temp_blocks to {}, numchan={}", tempfn, numchan),
-    );
+        &BadBlocks::abort(),
+    )?;
 
     message!("write_temp_blocks {} {}", res, d);
     //let mut groups: Option<Box<QuadtreeTree>> = None;
@@ -696,8 +697,9 @@ fn write_blocks_from_temp(
     timestamp: i64,
     keep_temps: bool,
     compression_type: CompressionType,
+    header_info: &HeaderInfo,
 ) -> Result<()> {
-    let wf = Box::new(WriteFile::with_compression_type(&outfn, HeaderType::ExternalLocs, None, compression_type));
+    let wf = Box::new(WriteFile::with_header_info(&outfn, HeaderType::ExternalLocs, None, compression_type, header_info));
 
     let t = if numchan == 0 {
         let cq = Box::new(CollectBlocksTemp::new(wf, groups, timestamp, compression_type));
@@ -818,6 +820,9 @@ pub fn sort_blocks(
         keep_temps
     );
 
+    //keeps the HistoricalInformation feature of full-history input files
+    let header_info = HeaderInfo::from_file(infn)?;
+
     let mut tempfn = String::from("NONE");
     if !tempinmem {
         tempfn = format!("{}-temp.pbf", String::from(&outfn[0..outfn.len() - 4]));
@@ -893,7 +898,7 @@ pub fn sort_blocks(
         }
     }
     lt.add("write temp files");
-    write_blocks_from_temp(xx, outfn, groups, numchan, timestamp, keep_temps, compression_type)?;
+    write_blocks_from_temp(xx, outfn, groups, numchan, timestamp, keep_temps, compression_type, &header_info)?;
     lt.add("write blocks");
     Ok(())
     //Err(io::Error::new(io::ErrorKind::Other,"not impl"))
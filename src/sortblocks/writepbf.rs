@@ -7,7 +7,7 @@ use std::io::Write;
 use channelled_callbacks::{CallFinish, CallAll, Result as ccResult};
 use crate::elements::{Bbox, Block, PrimitiveBlock, Quadtree};
 use crate::pbfformat::{pack_file_block, CompressionType};
-use crate::pbfformat::{make_header_block_stored_locs, HeaderType};
+use crate::pbfformat::{make_header_block_stored_locs, HeaderType, HeaderInfo};
 
 use crate::utils::{ThreadTimer, Error};
 
@@ -33,6 +33,11 @@ impl WriteFile {
             writefile: crate::pbfformat::WriteFile::with_compression_type(outfn, header_type, bbox, compression_type),
         }
     }
+    pub fn with_header_info(outfn: &str, header_type: HeaderType, bbox: Option<&Bbox>, compression_type: CompressionType, header_info: &HeaderInfo) -> WriteFile {
+        WriteFile {
+            writefile: crate::pbfformat::WriteFile::with_header_info(outfn, header_type, bbox, compression_type, header_info),
+        }
+    }
 }
 
 impl CallFinish for WriteFile {
@@ -61,15 +66,20 @@ pub struct WriteFileInternalLocs {
     ischange: bool,
     data: Vec<(Quadtree, Vec<u8>)>,
     compression_type: CompressionType,
+    header_info: HeaderInfo,
 }
 
 impl WriteFileInternalLocs {
     pub fn new(fname: &str, ischange: bool) -> WriteFileInternalLocs {
+        WriteFileInternalLocs::with_header_info(fname, ischange, HeaderInfo::new())
+    }
+    pub fn with_header_info(fname: &str, ischange: bool, header_info: HeaderInfo) -> WriteFileInternalLocs {
         WriteFileInternalLocs {
             fname: String::from(fname),
             ischange: ischange,
             data: Vec::new(),
             compression_type: CompressionType::Zlib,
+            header_info: header_info,
         }
     }
 }
@@ -92,7 +102,7 @@ impl CallFinish for WriteFileInternalLocs {
         let mut outf = File::create(&self.fname).expect("failed to create");
         let hb = pack_file_block(
             "OSMHeader",
-            &make_header_block_stored_locs(self.ischange, locs),
+            &make_header_block_stored_locs(self.ischange, locs, &self.header_info),
             &self.compression_type,
         )
         .expect("?");
@@ -137,6 +147,7 @@ pub fn make_packprimblock_zeroindex<
 >(
     out: Box<T>,
     includeqts: bool,
+    include_locations: bool,
     compression_type: CompressionType
 ) -> Box<impl CallFinish<CallType = PrimitiveBlock, ReturnType = Timings, ErrorType=Error>> {
 
@@ -145,7 +156,7 @@ pub fn make_packprimblock_zeroindex<
         if bl.len() == 0 {
             return vec![];
         }
-        let xx = bl.pack(includeqts, false).expect("failed to pack");
+        let xx = bl.pack_with_locations(includeqts, false, include_locations).expect("failed to pack");
         let ob = pack_file_block("OSMData", &xx, &compression_type).expect("failed to pack fb");
 
         vec![(0, ob)]
@@ -0,0 +1,438 @@
+use channelled_callbacks::{
+    CallFinish, Callback, CallbackMerge, CallbackSync, Error as ccError, MergeTimings, ReplaceNoneWithTimings,
+    Result as ccResult,
+};
+use crate::elements::{Block, IdSet, IdSetAll, PrimitiveBlock, Quadtree, WithQuadtree};
+use crate::pbfformat::make_read_primitive_blocks_combine_call_all_idset;
+use crate::mergechanges::get_file_locs_and_header;
+use crate::pbfformat::{read_filelist, write_filelist, FilelistEntry};
+use crate::pbfformat::{read_all_blocks_parallel_with_progbar, BadBlocks, CompressionType, FileBlock, HeaderType};
+use crate::sortblocks::{find_tree_groups_within, make_packprimblock_qtindex, QuadtreeTree, Timings, WriteFile};
+use crate::update::{recover_update, write_index_file};
+use crate::utils::{date_string, parse_timestamp, write_file_atomic, LogTimes, ThreadTimer};
+use crate::utils::{Error, Result};
+use crate::message;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+const COMPACTED_FILENAME: &str = "compacted.json";
+
+//depth to which element quadtrees are rounded when regrouping a tile
+const SPLIT_MAX_DEPTH: usize = 17;
+
+fn block_quadtrees(pb: &PrimitiveBlock) -> Vec<Quadtree> {
+    let mut res = Vec::with_capacity(pb.len());
+    res.extend(pb.nodes.iter().map(|n| n.quadtree));
+    res.extend(pb.ways.iter().map(|w| w.quadtree));
+    res.extend(pb.relations.iter().map(|r| r.quadtree));
+    res
+}
+
+//returns the quadtree of the block within tile root which element quadtree q
+//is assigned to
+fn split_group(groups: &QuadtreeTree, root: &Quadtree, q: &Quadtree) -> Quadtree {
+    if q.as_int() < 0 || q.depth() < root.depth() || !root.is_parent(q) {
+        return *root;
+    }
+    let (_, t) = groups.find(q);
+    if t.weight > 0 {
+        t.qt
+    } else {
+        *root
+    }
+}
+
+//regroups the elements of tile pb into blocks of about target elements. The
+//new blocks are pb.quadtree or its descendants, so are still in quadtree
+//order.
+fn split_tile(pb: PrimitiveBlock, target: i64) -> Result<Vec<PrimitiveBlock>> {
+    let root = pb.quadtree;
+    let depth = usize::max(SPLIT_MAX_DEPTH, root.depth());
+    let mut tree = Box::new(QuadtreeTree::new());
+    for q in block_quadtrees(&pb) {
+        let q = if q.as_int() < 0 || q.depth() < root.depth() || !root.is_parent(&q) {
+            root
+        } else {
+            q.round(depth)
+        };
+        tree.add(&q, 1);
+    }
+    let groups = find_tree_groups_within(tree, &root, target, -1)?;
+
+    let mut blocks = BTreeMap::new();
+    let (start_date, end_date) = (pb.start_date, pb.end_date);
+    for e in pb {
+        let q = split_group(&groups, &root, e.get_quadtree());
+        if !blocks.contains_key(&q) {
+            let mut b = PrimitiveBlock::with_quadtree(q);
+            b.start_date = start_date;
+            b.end_date = end_date;
+            blocks.insert(q, b);
+        }
+        blocks.get_mut(&q).unwrap().add_object(e)?;
+    }
+
+    let mut res = Vec::with_capacity(blocks.len());
+    for (_, mut b) in blocks {
+        b.sort();
+        res.push(b);
+    }
+    Ok(res)
+}
+
+/// Splits merged tiles with more than twice `target` elements. Tiles are
+/// passed on unchanged if `target` is None.
+struct SplitTiles<T: ?Sized> {
+    out: Box<T>,
+    target: Option<i64>,
+    num_split: usize,
+    error: Option<Error>,
+    tm: f64,
+}
+
+impl<T> SplitTiles<T>
+where
+    T: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings, ErrorType = Error> + ?Sized,
+{
+    pub fn new(out: Box<T>, target: Option<i64>) -> SplitTiles<T> {
+        SplitTiles {
+            out: out,
+            target: target,
+            num_split: 0,
+            error: None,
+            tm: 0.0,
+        }
+    }
+}
+
+impl<T> CallFinish for SplitTiles<T>
+where
+    T: CallFinish<CallType = PrimitiveBlock, ReturnType = Timings, ErrorType = Error> + ?Sized,
+{
+    type CallType = PrimitiveBlock;
+    type ReturnType = Timings;
+    type ErrorType = Error;
+
+    fn call(&mut self, pb: PrimitiveBlock) {
+        let target = match self.target {
+            Some(t) if self.error.is_none() && (pb.len() as i64) > 2 * t => t,
+            _ => {
+                self.out.call(pb);
+                return;
+            }
+        };
+        let tx = ThreadTimer::new();
+        let res = split_tile(pb, target);
+        self.tm += tx.since();
+        match res {
+            Ok(bls) => {
+                self.num_split += 1;
+                for b in bls {
+                    self.out.call(b);
+                }
+            }
+            Err(e) => {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn finish(&mut self) -> ccResult<Timings, Error> {
+        let mut tm = self.out.finish()?;
+        match self.error.take() {
+            Some(e) => Err(ccError::OtherError(e)),
+            None => {
+                if self.num_split > 0 {
+                    message!("split {} tiles", self.num_split);
+                }
+                tm.add("split tiles", self.tm);
+                Ok(tm)
+            }
+        }
+    }
+}
+
+fn read_compacted(prfx: &str) -> Result<Vec<FilelistEntry>> {
+    let fname = format!("{}{}", prfx, COMPACTED_FILENAME);
+    if !Path::new(&fname).exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_reader(File::open(&fname)?)?)
+}
+
+/// Merges the first `num_entries` entries of the osmquadtree directory `prfx`
+/// (defaults to all entries) into a new base file `outfn`, and rewrites
+/// filelist.json with the new base in place of the merged entries. The
+/// replaced files are listed in compacted.json and are only removed by
+/// [`purge_compacted`].
+///
+/// If `split_target` is given, tiles which have grown to more than twice
+/// `split_target` elements are regrouped into smaller tiles. This changes
+/// the tiles used by later updates, so is only allowed when compacting all
+/// entries.
+pub fn run_compact(
+    prfx: &str,
+    num_entries: Option<usize>,
+    outfn: Option<&str>,
+    split_target: Option<i64>,
+    compression_type: CompressionType,
+    numchan: usize,
+) -> Result<()> {
+    let mut tx = LogTimes::new();
+    recover_update(prfx)?;
+
+    let filelist = read_filelist(prfx)?;
+    let num_entries = num_entries.unwrap_or(filelist.len());
+    if num_entries < 2 || num_entries > filelist.len() {
+        return Err(Error::UserSelectionError(format!(
+            "can't compact {} entries: {}filelist.json has {} entries",
+            num_entries,
+            prfx,
+            filelist.len()
+        )));
+    }
+    if split_target.is_some() && num_entries < filelist.len() {
+        return Err(Error::UserSelectionError(
+            "can only regroup tiles when compacting all entries".to_string(),
+        ));
+    }
+
+    let last = &filelist[num_entries - 1];
+    let timestamp = parse_timestamp(&last.end_date)?;
+    let outfn = match outfn {
+        Some(o) => String::from(o),
+        None => format!("compact-{}.pbf", date_string(timestamp)),
+    };
+    if filelist.iter().any(|fle| fle.filename == outfn) || Path::new(&format!("{}{}", prfx, outfn)).exists() {
+        return Err(Error::UserSelectionError(format!("{}{} already exists", prfx, outfn)));
+    }
+
+    //full-history archives keep every version
+    let (mut pfilelocs, history, header_info) =
+        get_file_locs_and_header(prfx, None, Some(last.end_date.as_str()), None, None)?;
+    if pfilelocs.0.len() != num_entries {
+        return Err(Error::InvalidDataError(format!(
+            "expected {} files up to {}, found {}",
+            num_entries,
+            last.end_date,
+            pfilelocs.0.len()
+        )));
+    }
+    tx.add("get_file_locs");
+
+    let outpath = format!("{}{}", prfx, outfn);
+    let wf = Box::new(WriteFile::with_header_info(
+        &outpath, HeaderType::ExternalLocs, None, compression_type, &header_info,
+    ));
+
+    let ids: Arc<dyn IdSet> = Arc::new(IdSetAll());
+    let bad_blocks = BadBlocks::abort();
+    let pp: Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType = Error>> =
+        if numchan == 0 {
+            let pc = make_packprimblock_qtindex(wf, true, compression_type);
+            let st = Box::new(SplitTiles::new(pc, split_target));
+            make_read_primitive_blocks_combine_call_all_idset(st, ids.clone(), true, history, &bad_blocks)
+        } else {
+            let wfs = CallbackSync::new(wf, numchan);
+            let mut pps: Vec<
+                Box<dyn CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings, ErrorType = Error>>,
+            > = Vec::new();
+            for w in wfs {
+                let w2 = Box::new(ReplaceNoneWithTimings::new(w));
+                let pc = make_packprimblock_qtindex(w2, true, compression_type);
+                let st = Box::new(SplitTiles::new(pc, split_target));
+                pps.push(Box::new(Callback::new(
+                    make_read_primitive_blocks_combine_call_all_idset(st, ids.clone(), true, history, &bad_blocks),
+                )))
+            }
+            Box::new(CallbackMerge::new(pps, Box::new(MergeTimings::new())))
+        };
+
+    let msg = format!("compact {} entries, numchan={}", num_entries, numchan);
+    let tm = read_all_blocks_parallel_with_progbar(&mut pfilelocs.0, &pfilelocs.3, &pfilelocs.1, pp, &msg, pfilelocs.2, &bad_blocks)?;
+    message!("{}", tm);
+    tx.add("write merged blocks");
+
+    let num_tiles = write_index_file(&outpath, &format!("{}-index.pbf", outpath), numchan)?;
+    tx.add("write index");
+
+    let entry = FilelistEntry::new_checked(prfx, outfn, last.end_date.clone(), num_tiles, last.state)?;
+
+    //record the replaced entries before they leave filelist.json, so that
+    //an interrupted compact can't leave files which nothing refers to
+    let mut compacted = read_compacted(prfx)?;
+    compacted.extend(filelist[..num_entries].iter().cloned());
+    write_file_atomic(
+        &format!("{}{}", prfx, COMPACTED_FILENAME),
+        &serde_json::to_vec(&compacted)?,
+    )?;
+
+    let mut new_filelist = vec![entry];
+    new_filelist.extend(filelist[num_entries..].iter().cloned());
+    write_filelist(prfx, &new_filelist)?;
+    tx.add("write filelist");
+
+    message!(
+        "replaced {} entries with {}: {} entries remain",
+        num_entries,
+        new_filelist[0].filename,
+        new_filelist.len()
+    );
+    message!("{}", tx);
+    Ok(())
+}
+
+/// Removes the files replaced by [`run_compact`], with their index and
+/// filelocs files. Files still in filelist.json are kept.
+pub fn purge_compacted(prfx: &str) -> Result<usize> {
+    let compacted = read_compacted(prfx)?;
+    let filelist = read_filelist(prfx)?;
+
+    let mut count = 0;
+    for fle in &compacted {
+        if filelist.iter().any(|f| f.filename == fle.filename) {
+            message!("{} still in filelist: not removed", fle.filename);
+            continue;
+        }
+        for fname in [
+            format!("{}{}", prfx, fle.filename),
+            format!("{}{}-index.pbf", prfx, fle.filename),
+            format!("{}{}-filelocs.json", prfx, fle.filename),
+        ] {
+            if Path::new(&fname).exists() {
+                std::fs::remove_file(&fname)?;
+                count += 1;
+            }
+        }
+    }
+
+    let fname = format!("{}{}", prfx, COMPACTED_FILENAME);
+    if Path::new(&fname).exists() {
+        std::fs::remove_file(&fname)?;
+    }
+    message!("removed {} files", count);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elements::{Changetype, Node};
+
+    fn test_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("osmquadtree-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        format!("{}/", dir.to_str().unwrap())
+    }
+
+    fn entry(filename: &str, state: i64) -> FilelistEntry {
+        FilelistEntry::new(String::from(filename), String::from("2024-01-01T00:00:00"), 1, state)
+    }
+
+    fn descendant(root: &Quadtree, path: &[usize]) -> Quadtree {
+        let mut q = *root;
+        for p in path {
+            q = q.children()[*p];
+        }
+        q
+    }
+
+    fn make_tile(root: &Quadtree, quadtrees: &[(Quadtree, usize)]) -> PrimitiveBlock {
+        let mut pb = PrimitiveBlock::new(0, 0);
+        pb.quadtree = *root;
+        let mut id = 1;
+        for (q, n) in quadtrees {
+            for _ in 0..*n {
+                let mut nd = Node::new(id, Changetype::Normal);
+                nd.quadtree = *q;
+                pb.nodes.push(nd);
+                id += 1;
+            }
+        }
+        pb
+    }
+
+    fn check_split(root: &Quadtree, blocks: &[PrimitiveBlock], num_elements: usize) {
+        assert!(blocks.len() > 1);
+        assert_eq!(blocks.iter().map(|b| b.len()).sum::<usize>(), num_elements);
+        for b in blocks {
+            assert!(root.is_parent(&b.quadtree) && b.quadtree.depth() >= root.depth());
+        }
+        for pair in blocks.windows(2) {
+            assert!(pair[0].quadtree < pair[1].quadtree);
+        }
+    }
+
+    #[test]
+    fn split_tile_keeps_blocks_within_tile() {
+        let root = descendant(&Quadtree::new(0), &[1, 2, 3]);
+        let mut quadtrees = Vec::new();
+        for a in 0..4 {
+            for b in 0..4 {
+                quadtrees.push((descendant(&root, &[a, b, 1, 2]), 200));
+            }
+        }
+        let pb = make_tile(&root, &quadtrees);
+        let blocks = split_tile(pb, 500).unwrap();
+        check_split(&root, &blocks, 3200);
+    }
+
+    #[test]
+    fn split_tile_gives_leftover_elements_to_tile() {
+        let root = descendant(&Quadtree::new(0), &[0, 3]);
+        let pb = make_tile(
+            &root,
+            &[
+                (root, 30),
+                (descendant(&root, &[0, 0, 0]), 1200),
+                (descendant(&root, &[2, 1, 1]), 1200),
+                (descendant(&root, &[3]), 20),
+                (Quadtree::empty(), 5),
+            ],
+        );
+        let blocks = split_tile(pb, 500).unwrap();
+        check_split(&root, &blocks, 2455);
+        assert_eq!(blocks[0].quadtree, root);
+        assert!(blocks.iter().all(|b| b.quadtree.as_int() != 0));
+    }
+
+    #[test]
+    fn compact_rejects_bad_num_entries() {
+        let prfx = test_dir("compact-num-entries");
+        write_filelist(&prfx, &vec![entry("a.pbf", 1), entry("b.pbfc", 2)]).unwrap();
+
+        for n in [1, 3] {
+            match run_compact(&prfx, Some(n), None, None, CompressionType::Zlib, 0) {
+                Err(Error::UserSelectionError(_)) => {}
+                r => panic!("expected UserSelectionError, got {:?}", r.err()),
+            }
+        }
+        std::fs::remove_dir_all(&prfx).unwrap();
+    }
+
+    #[test]
+    fn purge_compacted_keeps_files_in_filelist() {
+        let prfx = test_dir("compact-purge");
+        for f in ["a.pbf", "a.pbf-index.pbf", "b.pbfc", "b.pbfc-index.pbf", "c.pbf"] {
+            std::fs::write(format!("{}{}", prfx, f), b"x").unwrap();
+        }
+        write_filelist(&prfx, &vec![entry("c.pbf", 2), entry("b.pbfc", 3)]).unwrap();
+        write_file_atomic(
+            &format!("{}{}", prfx, COMPACTED_FILENAME),
+            &serde_json::to_vec(&vec![entry("a.pbf", 1), entry("b.pbfc", 2)]).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(purge_compacted(&prfx).unwrap(), 2);
+        assert!(!Path::new(&format!("{}a.pbf", prfx)).exists());
+        assert!(!Path::new(&format!("{}a.pbf-index.pbf", prfx)).exists());
+        assert!(Path::new(&format!("{}b.pbfc", prfx)).exists());
+        assert!(Path::new(&format!("{}c.pbf", prfx)).exists());
+        assert!(!Path::new(&format!("{}{}", prfx, COMPACTED_FILENAME)).exists());
+        std::fs::remove_dir_all(&prfx).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use crate::update::read_xml_change;
+use crate::utils::{Error, Result};
+use crate::message;
+
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::time::Duration;
+
+const FETCH_RETRIES: usize = 5;
+const FETCH_BACKOFF_SECS: u64 = 2;
+const FETCH_CONNECT_TIMEOUT_SECS: u64 = 30;
+const FETCH_READ_TIMEOUT_SECS: u64 = 120;
+
+/// Returns the local path for a `file://` url or plain path, or None for an
+/// `http://` or `https://` url.
+pub fn local_path(url: &str) -> Option<&str> {
+    if url.starts_with("file://") {
+        Some(&url[7..])
+    } else if url.starts_with("http://") || url.starts_with("https://") {
+        None
+    } else {
+        Some(url)
+    }
+}
+
+//connection failures, interrupted or corrupt transfers, and 429 or 5xx
+//responses may succeed if tried again
+fn is_retryable(e: &Error) -> bool {
+    match e {
+        Error::Ureq(ureq::Error::Status(code, _)) => *code == 429 || *code >= 500,
+        Error::Ureq(ureq::Error::Transport(_)) => true,
+        Error::Io(_) => true,
+        Error::XmlDataError(_) => true,
+        _ => false,
+    }
+}
+
+fn backoff(url: &str, attempt: usize, e: &Error) {
+    let secs = FETCH_BACKOFF_SECS << attempt;
+    message!("fetch {} failed: {}, retrying in {}s", url, e, secs);
+    std::thread::sleep(Duration::from_secs(secs));
+}
+
+//a stalled server gives a (retryable) transport error rather than hanging
+fn make_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(FETCH_CONNECT_TIMEOUT_SECS))
+        .timeout_read(Duration::from_secs(FETCH_READ_TIMEOUT_SECS))
+        .build()
+}
+
+fn fetch_once(url: &str) -> Result<Vec<u8>> {
+    match local_path(url) {
+        Some(p) => Ok(std::fs::read(p)?),
+        None => {
+            let resp = make_agent().get(url).call()?;
+            let mut data = Vec::new();
+            resp.into_reader().read_to_end(&mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+/// Returns the contents of `url`. Http requests are retried with exponential
+/// backoff, local files are read once.
+pub fn fetch_source(url: &str) -> Result<Vec<u8>> {
+    let mut attempt = 0;
+    loop {
+        match fetch_once(url) {
+            Ok(data) => {
+                return Ok(data);
+            }
+            Err(e) => {
+                if local_path(url).is_some() || attempt + 1 >= FETCH_RETRIES || !is_retryable(&e) {
+                    return Err(e);
+                }
+                backoff(url, attempt, &e);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+//a truncated or corrupt download gives an XmlDataError, and is fetched again
+fn check_osc_gz(fname: &str) -> Result<()> {
+    let fbuf = BufReader::new(File::open(fname)?);
+    let mut gzbuf = BufReader::new(flate2::bufread::GzDecoder::new(fbuf));
+    match read_xml_change(&mut gzbuf) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::XmlDataError(format!("{}", e))),
+    }
+}
+
+/// Fetches the osmChange file `url` to `outfn`. The data is written to
+/// `outfn.part`, and only renamed to `outfn` once it has been read back as
+/// gzipped osmChange xml. An invalid download is fetched again.
+pub fn fetch_osc_gz(url: &str, outfn: &str) -> Result<()> {
+    let tmpfn = format!("{}.part", outfn);
+    let mut attempt = 0;
+    loop {
+        let data = fetch_source(url)?;
+        {
+            let mut ff = File::create(&tmpfn)?;
+            ff.write_all(&data)?;
+            ff.sync_all()?;
+        }
+
+        match check_osc_gz(&tmpfn) {
+            Ok(()) => {
+                std::fs::rename(&tmpfn, outfn)?;
+                return Ok(());
+            }
+            Err(e) => {
+                std::fs::remove_file(&tmpfn)?;
+                if local_path(url).is_some() || attempt + 1 >= FETCH_RETRIES || !is_retryable(&e) {
+                    return Err(Error::InvalidDataError(format!(
+                        "{} is not a valid osc.gz file: {}",
+                        url, e
+                    )));
+                }
+                backoff(url, attempt, &e);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    const CHANGE: &str = "<osmChange version=\"0.6\"><delete><node id=\"1\" version=\"2\"/></delete></osmChange>";
+
+    fn write_gz(fname: &str, data: &[u8]) {
+        let mut gz = GzEncoder::new(File::create(fname).unwrap(), Compression::default());
+        gz.write_all(data).unwrap();
+        gz.finish().unwrap();
+    }
+
+    #[test]
+    fn fetch_local_osc_gz() {
+        let dir = std::env::temp_dir().join(format!("osmquadtree-fetch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("src.osc.gz").to_str().unwrap().to_string();
+        let out = dir.join("out.osc.gz").to_str().unwrap().to_string();
+
+        write_gz(&src, CHANGE.as_bytes());
+        fetch_osc_gz(&format!("file://{}", src), &out).unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), std::fs::read(&src).unwrap());
+        std::fs::remove_file(&out).unwrap();
+
+        //truncated diffs are rejected without leaving a partial file
+        write_gz(&src, &CHANGE.as_bytes()[..CHANGE.len() - 15]);
+        match fetch_osc_gz(&src, &out) {
+            Err(Error::InvalidDataError(_)) => {}
+            r => panic!("expected InvalidDataError, got {:?}", r),
+        }
+        assert!(!std::path::Path::new(&out).exists());
+        assert!(!std::path::Path::new(&format!("{}.part", out)).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,10 @@
-use channelled_callbacks::{CallFinish, Callback, CallbackMerge, MergeTimings, Timings, Result as ccResult};
+use channelled_callbacks::{CallFinish, Callback, CallbackMerge, MergeTimings, Timings, Error as ccError, Result as ccResult};
 use crate::elements::{
     Bbox, Changetype, ElementType, IdSetSet, Node, PrimitiveBlock, Quadtree, Relation, Way,
 };
 use crate::pbfformat::{
     pack_file_block, read_all_blocks_locs_prog, read_file_block_with_pos,
-    FileBlock, HeaderBlock,FilelistEntry,CompressionType
+    BadBlocks, FileBlock, HeaderBlock,FilelistEntry,CompressionType
 };
 use crate::sortblocks::{QuadtreeTree, WriteFileInternalLocs};
 
@@ -200,6 +200,7 @@ struct ReadPB {
 
     ids: Arc<IdSetSet>,
     ischange: bool,
+    error: Option<Error>,
 
     tm: f64,
 }
@@ -209,9 +210,21 @@ impl ReadPB {
             origdata: Some(OrigData::new()),
             ids: ids,
             ischange: ischange,
+            error: None,
             tm: 0.0,
         }
     }
+
+    fn read_block(&self, idx_blocks: (usize, FileBlock)) -> Result<PrimitiveBlock> {
+        PrimitiveBlock::read_check_ids(
+            idx_blocks.0 as i64,
+            idx_blocks.1.pos,
+            &idx_blocks.1.try_data()?,
+            self.ischange,
+            false,
+            Some(self.ids.as_ref()),
+        )
+    }
 }
 
 impl CallFinish for ReadPB {
@@ -220,22 +233,25 @@ impl CallFinish for ReadPB {
     type ErrorType = Error;
 
     fn call(&mut self, idx_blocks: (usize, FileBlock)) {
+        if self.error.is_some() {
+            return;
+        }
         let tx = ThreadTimer::new();
-        let b = PrimitiveBlock::read_check_ids(
-            idx_blocks.0 as i64,
-            idx_blocks.1.pos,
-            &idx_blocks.1.data(),
-            self.ischange,
-            false,
-            Some(self.ids.as_ref()),
-        )
-        .expect("?");
-
-        self.origdata.as_mut().unwrap().add(b, self.ids.as_ref());
+        match self.read_block(idx_blocks) {
+            Ok(b) => {
+                self.origdata.as_mut().unwrap().add(b, self.ids.as_ref());
+            }
+            Err(e) => {
+                self.error = Some(e);
+            }
+        }
 
         self.tm += tx.since();
     }
     fn finish(&mut self) -> ccResult<Self::ReturnType, Self::ErrorType> {
+        if let Some(e) = self.error.take() {
+            return Err(ccError::OtherError(e));
+        }
         let mut tm = Timings::new(); //self.out.finish()?;
         tm.add("read_primitive_blocks_combine", self.tm);
         tm.add_other("origdata", self.origdata.take().unwrap());
@@ -258,7 +274,7 @@ fn read_change_tiles(
     if fb.block_type != "OSMHeader" {
         return Err(Error::PbfDataError("first block not an OSMHeader".to_string()));
     }
-    let head = HeaderBlock::read(p, &fb.data(), fname)?;
+    let head = HeaderBlock::read(p, &fb.try_data()?, fname)?;
     if head.index.is_empty() {
         if at_end_of_file(&mut file)? {
             message!("file {} empty", fname);
@@ -275,7 +291,7 @@ fn read_change_tiles(
     }
     let (mut tm, b) = if numchan == 0 {
         let convert = Box::new(ReadPB::new(ischange, idset));
-        read_all_blocks_locs_prog(&mut file, fname, locs, convert, pb, start_percent, end_percent)
+        read_all_blocks_locs_prog(&mut file, fname, locs, convert, pb, start_percent, end_percent, &BadBlocks::abort())?
     } else {
         let mut convs: Vec<
             Box<dyn CallFinish<CallType = (usize, FileBlock), ReturnType = Timings<OrigData>, ErrorType = Error>>,
@@ -287,12 +303,17 @@ fn read_change_tiles(
             )))));
         }
         let convsm = Box::new(CallbackMerge::new(convs, Box::new(MergeTimings::new())));
-        read_all_blocks_locs_prog(&mut file, fname, locs, convsm, pb, start_percent, end_percent)
+        read_all_blocks_locs_prog(&mut file, fname, locs, convsm, pb, start_percent, end_percent, &BadBlocks::abort())?
     };
 
-    let mut tls = tm.others.pop().unwrap().1;
-    while !tm.others.is_empty() {
-        tls.extend(tm.others.pop().unwrap().1);
+    let mut tls = match tm.others.pop() {
+        Some((_, t)) => t,
+        None => {
+            return Err(Error::MissingDataError(format!("no data read from {}", fname)));
+        }
+    };
+    while let Some((_, t)) = tm.others.pop() {
+        tls.extend(t);
     }
     Ok((tls, b))
 }
@@ -682,7 +703,7 @@ fn prep_tree(prfx: &str, filelist: &Vec<FilelistEntry>) -> Result<QuadtreeTree>
     if fb.block_type != "OSMHeader" {
         return Err(Error::PbfDataError("first block not an OSMHeader".to_string()));
     }
-    let head = HeaderBlock::read(x, &fb.data(), &fname)?;
+    let head = HeaderBlock::read(x, &fb.try_data()?, &fname)?;
 
     let mut tree = QuadtreeTree::new();
     for ii in &head.index {
@@ -734,7 +755,7 @@ fn prep_idset(changeblock: &ChangeBlock) -> Arc<IdSetSet> {
 pub fn find_update(
     prfx: &str,
     filelist: &Vec<FilelistEntry>,
-    change_filename: &str,
+    change_filenames: &[String],
     prev_ts: i64,
     ts: i64,
     max_qt_level: usize,
@@ -742,17 +763,21 @@ pub fn find_update(
     fname: &str,
     numchan: usize,
 ) -> Result<(f64, usize)> {
-    let mut chgf = BufReader::new(File::open(change_filename)?);
-
     let compression_type = CompressionType::Zlib;
 
     let tx = Timer::new();
 
-    let mut changeblock = if change_filename.ends_with(".gz") {
-        read_xml_change(&mut BufReader::new(flate2::bufread::GzDecoder::new(chgf)))
-    } else {
-        read_xml_change(&mut chgf)
-    }?;
+    //later diffs replace earlier versions of the same element
+    let mut changeblock = ChangeBlock::new();
+    for change_filename in change_filenames {
+        let mut chgf = BufReader::new(File::open(change_filename)?);
+        let cb = if change_filename.ends_with(".gz") {
+            read_xml_change(&mut BufReader::new(flate2::bufread::GzDecoder::new(chgf)))
+        } else {
+            read_xml_change(&mut chgf)
+        }?;
+        changeblock.extend(cb);
+    }
 
     let a = tx.since();
 
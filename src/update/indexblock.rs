@@ -1,8 +1,8 @@
-use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, CallAll,MergeTimings, ReplaceNoneWithTimings, Result as ccResult};
+use channelled_callbacks::{CallFinish, Callback, CallbackMerge, CallbackSync, CallAll,MergeTimings, ReplaceNoneWithTimings, Error as ccError, Result as ccResult};
 use crate::elements::{ElementType, IdSet, MinimalBlock, Quadtree};
 use crate::pbfformat::{
-    file_length, pack_file_block, read_all_blocks, read_all_blocks_prog,
-    read_all_blocks_with_progbar, FileBlock,CompressionType
+    pack_file_block, try_file_length, read_all_blocks, read_all_blocks_prog,
+    read_all_blocks_with_progbar, BadBlocks, FileBlock,CompressionType
 };
 use crate::logging::ProgressPercent;
 use simple_protocolbuffers::{
@@ -84,33 +84,54 @@ struct WF {
     f: File,
     nt: usize,
     tm: f64,
+    error: Option<Error>,
 }
 
 impl WF {
-    pub fn new(outfn: &str) -> WF {
-        WF {
-            f: File::create(outfn).expect("failed to create file"),
+    pub fn new(outfn: &str) -> Result<WF> {
+        Ok(WF {
+            f: File::create(outfn)?,
             nt: 0,
             tm: 0.0,
-        }
+            error: None,
+        })
     }
 }
 
 impl CallFinish for WF {
-    type CallType = Vec<u8>;
+    type CallType = Result<Vec<u8>>;
     type ReturnType = Timings;
     type ErrorType = Error;
-    fn call(&mut self, d: Vec<u8>) {
+    fn call(&mut self, d: Result<Vec<u8>>) {
+        if self.error.is_some() {
+            return;
+        }
+        let d = match d {
+            Ok(d) => d,
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        };
         if d.is_empty() {
             return;
         }
         let tx = ThreadTimer::new();
-        self.f.write_all(&d).expect("failed to write data");
+        match self.f.write_all(&d) {
+            Ok(()) => {
+                self.nt += 1;
+            }
+            Err(e) => {
+                self.error = Some(Error::from(e));
+            }
+        }
         self.tm += tx.since();
-        self.nt += 1;
     }
 
     fn finish(&mut self) -> ccResult<Timings, Error> {
+        if let Some(e) = self.error.take() {
+            return Err(ccError::OtherError(e));
+        }
         let mut tms = Timings::new();
         tms.add("write", self.tm);
         tms.add_other("num_tiles", ResultType::NumTiles(self.nt));
@@ -118,23 +139,24 @@ impl CallFinish for WF {
     }
 }
 
-fn convert_indexblock(i_fb: (usize, FileBlock)) -> Vec<u8> {
+fn convert_indexblock(i_fb: (usize, FileBlock)) -> Result<Vec<u8>> {
     if i_fb.1.block_type != "OSMData" {
-        return Vec::new();
+        return Ok(Vec::new());
     }
 
-    let mb = MinimalBlock::read(i_fb.0 as i64, i_fb.1.pos, &i_fb.1.data(), false)
-        .expect("MinimalBlock::read failed");
+    let mb = MinimalBlock::read(i_fb.0 as i64, i_fb.1.pos, &i_fb.1.try_data()?, false)?;
     let d = prep_index_block(&mb);
-    pack_file_block("IndexBlock", &d, &CompressionType::Zlib).expect("pack_file_block failed")
+    Ok(pack_file_block("IndexBlock", &d, &CompressionType::Zlib)?)
 }
 
-pub fn write_index_file(infn: &str, outfn: &str, numchan: usize) -> usize {
+/// Writes the index file `outfn` for the pbf file `infn`, with one
+/// IndexBlock for each OSMData block. Returns the number of tiles.
+pub fn write_index_file(infn: &str, outfn: &str, numchan: usize) -> Result<usize> {
     let pack: CallFinishFileBlocks = if numchan == 0 {
-        let wf = Box::new(WF::new(outfn));
+        let wf = Box::new(WF::new(outfn)?);
         Box::new(CallAll::new(wf, "convert", Box::new(convert_indexblock)))
     } else {
-        let wfs = CallbackSync::new(Box::new(WF::new(outfn)), numchan);
+        let wfs = CallbackSync::new(Box::new(WF::new(outfn)?), numchan);
 
         let mut packs: Vec<
             Box<dyn CallFinish<CallType = (usize, FileBlock), ReturnType = Timings, ErrorType=Error>>,
@@ -150,17 +172,18 @@ pub fn write_index_file(infn: &str, outfn: &str, numchan: usize) -> usize {
         }
         Box::new(CallbackMerge::new(packs, Box::new(MergeTimings::new())))
     };
-    let (mut tm, _) =
-        read_all_blocks_with_progbar(infn, pack, &format!("write_index_file for {}", infn));
+    let (tm, _) =
+        read_all_blocks_with_progbar(infn, pack, &format!("write_index_file for {}", infn), &BadBlocks::abort())?;
 
-    match tm.others.pop().unwrap().1 {
-        ResultType::NumTiles(nt) => {
-            return nt;
-        }
-        _ => {
-            panic!("??");
+    for (_, x) in tm.others {
+        match x {
+            ResultType::NumTiles(nt) => {
+                return Ok(nt);
+            }
+            _ => {}
         }
     }
+    Err(Error::IndexFileError(format!("write_index_file for {}: no tile count", infn)))
 }
 
 struct CheckIndexFile {
@@ -232,12 +255,12 @@ pub fn check_index_file(
     };
 
     let (tm, x) = match pb {
-        None => read_all_blocks(indexfn, ca),
+        None => read_all_blocks(indexfn, ca, &BadBlocks::abort())?,
         Some((pb,start_pc,end_pc)) => {
-            let flen = file_length(indexfn);
-            let f = File::open(indexfn).expect("fail");
+            let flen = try_file_length(indexfn)?;
+            let f = File::open(indexfn)?;
             let mut fbuf = BufReader::new(f);
-            read_all_blocks_prog(&mut fbuf, flen, ca, pb, start_pc,end_pc)
+            read_all_blocks_prog(&mut fbuf, flen, ca, pb, start_pc,end_pc, &BadBlocks::abort())?
         }
     };
 
@@ -0,0 +1,117 @@
+use crate::pbfformat::{read_filelist, write_filelist, FilelistEntry};
+use crate::utils::{write_file_atomic, Result};
+use crate::message;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+const JOURNAL_FILENAME: &str = "update-journal.json";
+
+/// Records the progress of `run_update`, so that an update which is
+/// interrupted can be completed or undone by the next run.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UpdateJournal {
+    /// updates whose pbfc and index files have been written, but which may
+    /// not have been added to filelist.json
+    pub completed: Vec<FilelistEntry>,
+    /// filename of the update currently being written
+    pub in_progress: Option<String>,
+}
+
+impl UpdateJournal {
+    pub fn new() -> UpdateJournal {
+        UpdateJournal {
+            completed: Vec::new(),
+            in_progress: None,
+        }
+    }
+
+    fn filename(prfx: &str) -> String {
+        format!("{}{}", prfx, JOURNAL_FILENAME)
+    }
+
+    pub fn read(prfx: &str) -> Result<Option<UpdateJournal>> {
+        let fname = UpdateJournal::filename(prfx);
+        if !Path::new(&fname).exists() {
+            return Ok(None);
+        }
+        let ff = File::open(&fname)?;
+        Ok(Some(serde_json::from_reader(ff)?))
+    }
+
+    pub fn write(&self, prfx: &str) -> Result<()> {
+        let data = serde_json::to_vec(self)?;
+        write_file_atomic(&UpdateJournal::filename(prfx), &data)?;
+        Ok(())
+    }
+
+    pub fn remove(prfx: &str) -> Result<()> {
+        let fname = UpdateJournal::filename(prfx);
+        if Path::new(&fname).exists() {
+            std::fs::remove_file(&fname)?;
+        }
+        Ok(())
+    }
+
+    /// Records that the update `fname` is about to be written.
+    pub fn start(&mut self, prfx: &str, fname: &str) -> Result<()> {
+        self.in_progress = Some(String::from(fname));
+        self.write(prfx)
+    }
+
+    /// Records that the update `entry` has been fully written.
+    pub fn complete(&mut self, prfx: &str, entry: FilelistEntry) -> Result<()> {
+        self.in_progress = None;
+        self.completed.push(entry);
+        self.write(prfx)
+    }
+}
+
+fn remove_update_files(prfx: &str, fname: &str) -> Result<()> {
+    for f in [format!("{}{}", prfx, fname), format!("{}{}-index.pbf", prfx, fname)] {
+        if Path::new(&f).exists() {
+            message!("remove {}", f);
+            std::fs::remove_file(&f)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the osmquadtree directory `prfx` for an interrupted update. Updates
+/// which were fully written are added to filelist.json (rolled forward), and
+/// the files of an update which was only partly written are removed (rolled
+/// back). Returns true if an interrupted update was found.
+pub fn recover_update(prfx: &str) -> Result<bool> {
+    let journal = match UpdateJournal::read(prfx)? {
+        Some(j) => j,
+        None => {
+            return Ok(false);
+        }
+    };
+
+    let mut filelist = read_filelist(prfx)?;
+
+    if let Some(fname) = &journal.in_progress {
+        if !filelist.iter().any(|f| &f.filename == fname) {
+            message!("roll back interrupted update {}", fname);
+            remove_update_files(prfx, fname)?;
+        }
+    }
+
+    let mut changed = false;
+    for entry in journal.completed {
+        if filelist.iter().any(|f| f.filename == entry.filename) {
+            continue;
+        }
+        message!("roll forward interrupted update {}", entry.filename);
+        filelist.push(entry);
+        changed = true;
+    }
+    if changed {
+        write_filelist(prfx, &filelist)?;
+    }
+
+    UpdateJournal::remove(prfx)?;
+    Ok(true)
+}
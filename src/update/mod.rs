@@ -1,10 +1,16 @@
+mod compact;
+mod fetch;
 mod find_update;
 mod indexblock;
+mod journal;
 mod read_xml;
 mod run_update;
 
 
+pub use compact::{purge_compacted, run_compact};
+pub use fetch::{fetch_osc_gz, fetch_source, local_path};
 pub use find_update::find_update;
 pub use indexblock::{check_index_file, write_index_file};
+pub use journal::recover_update;
 pub use read_xml::{read_xml_change, ChangeBlock};
-pub use run_update::{run_update, run_update_initial,get_state};
+pub use run_update::{run_update, run_update_initial, run_update_rollback, get_state, get_replication_header_info, UpdatePeriod};
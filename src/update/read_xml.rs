@@ -29,11 +29,22 @@ fn read_timestamp(ts: &str) -> Result<i64> {
 }
 */
 
-fn get_key<'a>(kv: &quick_xml::events::attributes::Attribute<'a>) -> String {
-    String::from(std::str::from_utf8(kv.key.as_ref()).expect("?"))
+fn get_key<'a>(kv: &quick_xml::events::attributes::Attribute<'a>) -> Result<String> {
+    Ok(String::from(std::str::from_utf8(kv.key.as_ref())?))
 }
-fn get_value<'a>(kv: &quick_xml::events::attributes::Attribute<'a>) -> String {
-    String::from(kv.unescape_value().as_ref().expect("?").as_ref())
+fn get_value<'a>(kv: &quick_xml::events::attributes::Attribute<'a>) -> Result<String> {
+    Ok(String::from(kv.unescape_value()?.as_ref()))
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, val: &str) -> Result<T> {
+    match val.parse() {
+        Ok(v) => Ok(v),
+        Err(_) => Err(Error::XmlDataError(format!("invalid {} value \"{}\"", key, val))),
+    }
+}
+
+fn name_str(n: &[u8]) -> String {
+    String::from_utf8_lossy(n).to_string()
 }
 
 
@@ -41,11 +52,13 @@ fn ele_str(w: &str, e: &BytesStart) -> Result<String> {
     let n = String::from(std::str::from_utf8(e.name().as_ref())?);
     let mut sl: Vec<String> = Vec::new();
     for a in e.attributes() {
-        sl.push(format!(
-            "{} = \"{}\"",
-            get_key(&a.as_ref().expect("?")),
-            get_value(&a.as_ref().expect("?"))
-        ));
+        let kv = match a {
+            Ok(kv) => kv,
+            Err(_e) => {
+                return Err(Error::XmlDataError(format!("failed to read attribute")));
+            }
+        };
+        sl.push(format!("{} = \"{}\"", get_key(&kv)?, get_value(&kv)?));
     }
     Ok(format!("{} {}: {}", w, n, sl.join("; ")))
 }
@@ -77,31 +90,31 @@ fn read_node<T: BufRead>(
     for a in e.attributes() {
         match a {
             Ok(kv) => {
-                let val = get_value(&kv);
+                let val = get_value(&kv)?;
                 match kv.key.as_ref() {
                     b"id" => {
-                        n.id = val.parse().expect("not an int");
+                        n.id = parse_value("id", &val)?;
                     }
                     b"timestamp" => {
                         info.timestamp = parse_timestamp(&val)?;
                     }
                     b"changeset" => {
-                        info.changeset = val.parse().expect("not an int");
+                        info.changeset = parse_value("changeset", &val)?;
                     }
                     b"version" => {
-                        info.version = val.parse().expect("not an int");
+                        info.version = parse_value("version", &val)?;
                     }
                     b"uid" => {
-                        info.user_id = val.parse().expect("not an int");
+                        info.user_id = parse_value("uid", &val)?;
                     }
                     b"user" => {
                         info.user = val.to_string();
                     }
                     b"lon" => {
-                        n.lon = as_int(val.parse().expect("not a float"));
+                        n.lon = as_int(parse_value("lon", &val)?);
                     }
                     b"lat" => {
-                        n.lat = as_int(val.parse().expect("not a float"));
+                        n.lat = as_int(parse_value("lat", &val)?);
                     }
                     k => {
                         return Err(Error::XmlDataError(
@@ -133,7 +146,7 @@ fn read_node<T: BufRead>(
                     }
                     n => {
                         return Err(Error::XmlDataError(
-                            format!("unexpected empty {}", std::str::from_utf8(n).expect("?")),
+                            format!("unexpected empty {}", name_str(n)),
                         ));
                     }
                 },
@@ -145,7 +158,7 @@ fn read_node<T: BufRead>(
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected end {}",
-                                std::str::from_utf8(x).expect("?")
+                                name_str(x)
                             ),
                         ));
                     }
@@ -157,7 +170,7 @@ fn read_node<T: BufRead>(
                                 "unexpected text {}: {:?}",
                                 reader.buffer_position(),
                                 //e.unescape_and_decode(&reader).unwrap()
-                                e.unescape().unwrap()
+                                e.unescape()?
                             ),
                         ));
                     }
@@ -186,7 +199,7 @@ fn read_tag(e: &BytesStart) -> Result<Tag> {
     for a in e.attributes() {
         match a {
             Ok(kv) => {
-                let val = get_value(&kv);
+                let val = get_value(&kv)?;
                 match kv.key.as_ref() {
                     b"k" => {
                         k = Some(val);
@@ -198,7 +211,7 @@ fn read_tag(e: &BytesStart) -> Result<Tag> {
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected attribute {} {}",
-                                std::str::from_utf8(k).expect("?"),
+                                name_str(k),
                                 val
                             ),
                         ));
@@ -213,32 +226,29 @@ fn read_tag(e: &BytesStart) -> Result<Tag> {
         }
     }
 
-    if k == None {
-        return Err(Error::XmlDataError("tag missing key".to_string()));
-    }
-    if v == None {
-        return Err(Error::XmlDataError(
-            format!("tag missing val [key={}]", k.unwrap()),
-        ));
+    match (k, v) {
+        (Some(k), Some(v)) => Ok(Tag::new(k, v)),
+        (None, _) => Err(Error::XmlDataError("tag missing key".to_string())),
+        (Some(k), None) => Err(Error::XmlDataError(
+            format!("tag missing val [key={}]", k),
+        )),
     }
-
-    Ok(Tag::new(k.unwrap(), v.unwrap()))
 }
 
 fn read_ref(e: &BytesStart) -> Result<i64> {
     for a in e.attributes() {
         match a {
             Ok(kv) => {
-                let val = get_value(&kv);
+                let val = get_value(&kv)?;
                 match kv.key.as_ref() {
                     b"ref" => {
-                        return Ok(val.parse().expect("not an int"));
+                        return parse_value("ref", &val);
                     }
                     k => {
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected attribute {} {}",
-                                std::str::from_utf8(k).expect("?"),
+                                name_str(k),
                                 val
                             ),
                         ));
@@ -263,13 +273,13 @@ fn read_member(e: &BytesStart) -> Result<Member> {
     for a in e.attributes() {
         match a {
             Ok(kv) => {
-                let val = get_value(&kv);
+                let val = get_value(&kv)?;
                 match kv.key.as_ref() {
                     b"role" => {
                         role = val;
                     }
                     b"ref" => {
-                        mem_ref = val.parse().expect("not an int");
+                        mem_ref = parse_value("ref", &val)?;
                     }
                     b"type" => match val.as_str() {
                         "node" => {
@@ -291,7 +301,7 @@ fn read_member(e: &BytesStart) -> Result<Member> {
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected attribute {} {}",
-                                std::str::from_utf8(k).expect("?"),
+                                name_str(k),
                                 val
                             ),
                         ));
@@ -326,22 +336,22 @@ fn read_way<T: BufRead>(
     for a in e.attributes() {
         match a {
             Ok(kv) => {
-                let val = get_value(&kv);
+                let val = get_value(&kv)?;
                 match kv.key.as_ref() {
                     b"id" => {
-                        w.id = val.parse().expect("not an int");
+                        w.id = parse_value("id", &val)?;
                     }
                     b"timestamp" => {
                         info.timestamp = parse_timestamp(&val)?;
                     }
                     b"changeset" => {
-                        info.changeset = val.parse().expect("not an int");
+                        info.changeset = parse_value("changeset", &val)?;
                     }
                     b"version" => {
-                        info.version = val.parse().expect("not an int");
+                        info.version = parse_value("version", &val)?;
                     }
                     b"uid" => {
-                        info.user_id = val.parse().expect("not an int");
+                        info.user_id = parse_value("uid", &val)?;
                     }
                     b"user" => {
                         info.user = val.to_string();
@@ -351,7 +361,7 @@ fn read_way<T: BufRead>(
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected attribute {} {}",
-                                std::str::from_utf8(k).expect("?"),
+                                name_str(k),
                                 val
                             ),
                         ));
@@ -400,7 +410,7 @@ fn read_way<T: BufRead>(
                             format!(
                                 "unexpected text {}: {:?}",
                                 reader.buffer_position(),
-                                e.unescape().unwrap()
+                                e.unescape()?
                             ),
                         ));
                     }
@@ -440,22 +450,22 @@ fn read_relation<T: BufRead>(
     for a in e.attributes() {
         match a {
             Ok(kv) => {
-                let val = get_value(&kv);
+                let val = get_value(&kv)?;
                 match kv.key.as_ref() {
                     b"id" => {
-                        r.id = val.parse().expect("not an int");
+                        r.id = parse_value("id", &val)?;
                     }
                     b"timestamp" => {
                         info.timestamp = parse_timestamp(&val)?;
                     }
                     b"changeset" => {
-                        info.changeset = val.parse().expect("not an int");
+                        info.changeset = parse_value("changeset", &val)?;
                     }
                     b"version" => {
-                        info.version = val.parse().expect("not an int");
+                        info.version = parse_value("version", &val)?;
                     }
                     b"uid" => {
-                        info.user_id = val.parse().expect("not an int");
+                        info.user_id = parse_value("uid", &val)?;
                     }
                     b"user" => {
                         info.user = val.to_string();
@@ -465,7 +475,7 @@ fn read_relation<T: BufRead>(
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected attribute {} {}",
-                                std::str::from_utf8(k).expect("?"),
+                                name_str(k),
                                 val
                             ),
                         ));
@@ -515,7 +525,7 @@ fn read_relation<T: BufRead>(
                             format!(
                                 "unexpected text {}: {}",
                                 reader.buffer_position(),
-                                e.unescape().unwrap()
+                                e.unescape()?
                             ),
                         ));
                     }
@@ -537,6 +547,14 @@ fn read_relation<T: BufRead>(
     Ok(r)
 }
 
+//elements without an info are treated as version 0
+fn info_version(info: &Option<Info>) -> i64 {
+    match info {
+        Some(i) => i.version,
+        None => 0,
+    }
+}
+
 pub struct ChangeBlock {
     pub nodes: BTreeMap<i64, Node>,
     pub ways: BTreeMap<i64, Way>,
@@ -558,7 +576,7 @@ impl ChangeBlock {
         }
 
         let curr = self.nodes.get_mut(&n.id).unwrap();
-        if n.info.as_ref().unwrap().version > curr.info.as_ref().unwrap().version {
+        if info_version(&n.info) > info_version(&curr.info) {
             *curr = n;
         }
         false
@@ -571,7 +589,7 @@ impl ChangeBlock {
         }
 
         let curr = self.ways.get_mut(&w.id).unwrap();
-        if w.info.as_ref().unwrap().version > curr.info.as_ref().unwrap().version {
+        if info_version(&w.info) > info_version(&curr.info) {
             *curr = w;
         }
         false
@@ -584,11 +602,24 @@ impl ChangeBlock {
         }
 
         let curr = self.relations.get_mut(&r.id).unwrap();
-        if r.info.as_ref().unwrap().version > curr.info.as_ref().unwrap().version {
+        if info_version(&r.info) > info_version(&curr.info) {
             *curr = r;
         }
         false
     }
+
+    /// Adds all elements of `other`, keeping the highest version of each id.
+    pub fn extend(&mut self, other: ChangeBlock) {
+        for (_, n) in other.nodes {
+            self.add_node(n);
+        }
+        for (_, w) in other.ways {
+            self.add_way(w);
+        }
+        for (_, r) in other.relations {
+            self.add_relation(r);
+        }
+    }
 }
 
 pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
@@ -600,6 +631,7 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
     let mut buf2 = Vec::new();
     let mut ct: Option<Changetype> = None;
     let mut cktm = Checktime::new();
+    let mut finished = false;
 
     loop {
         match reader.read_event_into(&mut buf) {
@@ -610,7 +642,7 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
                             "{:5.1}s {} {}",
                             d,
                             reader.buffer_position(),
-                            ele_str("start", e).expect("?")
+                            ele_str("start", e)?
                         );
                     }
                     None => {}
@@ -639,7 +671,7 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected start tag {} {}",
-                                std::str::from_utf8(n).expect("?"),
+                                name_str(n),
                                 reader.buffer_position()
                             ),
                         ));
@@ -653,7 +685,7 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
                             "{:5.1}s {} {}",
                             d,
                             reader.buffer_position(),
-                            ele_str("empty", e).expect("?")
+                            ele_str("empty", e)?
                         );
                     }
                     None => {}
@@ -672,7 +704,7 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
                         return Err(Error::XmlDataError(
                             format!(
                                 "unexpected empty tag {} {}",
-                                std::str::from_utf8(n).expect("?"),
+                                name_str(n),
                                 reader.buffer_position()
                             ),
                         ));
@@ -680,7 +712,9 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
                 }
             }
             Ok(Event::End(ref e)) => match e.name().as_ref() {
-                b"osmChange" => {}
+                b"osmChange" => {
+                    finished = true;
+                }
                 b"delete" => {
                     ct = None;
                 }
@@ -694,7 +728,7 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
                     return Err(Error::XmlDataError(
                         format!(
                             "unexpected end tag {} {}",
-                            std::str::from_utf8(n).expect("?"),
+                            name_str(n),
                             reader.buffer_position()
                         ),
                     ));
@@ -711,7 +745,7 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
                         format!(
                             "unexpected text {}: {}",
                             reader.buffer_position(),
-                            e.unescape().unwrap()
+                            e.unescape()?
                         ),
                     ));
                 }
@@ -735,6 +769,12 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
         }
         buf.clear();
     }
+    if !finished {
+        return Err(Error::XmlDataError(format!(
+            "missing osmChange end tag at {}: truncated?",
+            reader.buffer_position()
+        )));
+    }
     message!(
         "{:5.1}s: {} nodes, {} ways, {} relations",
         cktm.gettime(),
@@ -744,3 +784,56 @@ pub fn read_xml_change<T: BufRead>(inf: &mut T) -> Result<ChangeBlock> {
     );
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osmChange version="0.6" generator="test">
+<create>
+ <node id="1" version="1" timestamp="2024-01-01T00:00:00Z" uid="5" user="u" changeset="10" lat="51.5" lon="-0.1">
+  <tag k="amenity" v="cafe"/>
+ </node>
+</create>
+<modify>
+ <way id="2" version="3" timestamp="2024-01-01T00:00:01Z" uid="5" user="u" changeset="10">
+  <nd ref="1"/>
+  <nd ref="3"/>
+ </way>
+</modify>
+<delete>
+ <relation id="4" version="2" timestamp="2024-01-01T00:00:02Z" uid="5" user="u" changeset="10"/>
+</delete>
+</osmChange>
+"#;
+
+    fn read(data: &str) -> Result<ChangeBlock> {
+        read_xml_change(&mut data.as_bytes())
+    }
+
+    #[test]
+    fn reads_change() {
+        let cb = read(CHANGE).unwrap();
+        assert_eq!(cb.nodes[&1].changetype, Changetype::Create);
+        assert_eq!(cb.nodes[&1].tags.len(), 1);
+        assert_eq!(cb.ways[&2].refs, vec![1, 3]);
+        assert_eq!(cb.ways[&2].changetype, Changetype::Modify);
+        assert_eq!(cb.relations[&4].changetype, Changetype::Delete);
+    }
+
+    #[test]
+    fn invalid_value_is_error() {
+        match read(&CHANGE.replace("id=\"2\"", "id=\"x2\"")) {
+            Err(Error::XmlDataError(_)) => {}
+            r => panic!("expected XmlDataError, got {:?}", r.err()),
+        }
+    }
+
+    #[test]
+    fn truncated_change_is_error() {
+        for l in [CHANGE.len() / 2, CHANGE.len() - 20] {
+            assert!(read(&CHANGE[..l]).is_err());
+        }
+    }
+}
@@ -1,17 +1,80 @@
-use crate::update::{write_index_file,find_update};
-use crate::pbfformat::{read_filelist, write_filelist, FilelistEntry};
+use crate::update::{write_index_file,find_update,fetch_osc_gz,fetch_source};
+use crate::update::journal::{recover_update, UpdateJournal};
+use crate::pbfformat::{read_filelist, write_filelist, FilelistEntry, HeaderInfo};
+use crate::pbfformat::{file_position, read_file_block, HeaderBlock};
 use crate::utils::{
-    date_string, parse_timestamp, timestamp_string, timestamp_string_alt, LogTimes,
+    date_string, parse_timestamp, timestamp_string, timestamp_string_alt, write_file_atomic, LogTimes,
 };
 use crate::message;
 use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
-use std::io::Write;
+use std::io::{BufReader, Write};
 use crate::utils::{Error,Result};
 
 
 const DEFAULT_SOURCE_PRFX: &'static str = "https://planet.openstreetmap.org/replication/day/";
 
+/// Granularity of replication diffs, and of the update files made from them.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum UpdatePeriod {
+    Minute,
+    Hour,
+    #[default]
+    Day,
+}
+
+impl UpdatePeriod {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            UpdatePeriod::Minute => 60,
+            UpdatePeriod::Hour => 60 * 60,
+            UpdatePeriod::Day => 24 * 60 * 60,
+        }
+    }
+
+    /// Returns the end of the period containing `ts`. A timestamp on a
+    /// period boundary is the end of the preceding period.
+    pub fn period_end(&self, ts: i64) -> i64 {
+        let s = self.seconds();
+        (ts + s - 1).div_euclid(s) * s
+    }
+
+    /// Guesses the period from a replication url such as
+    /// `https://planet.openstreetmap.org/replication/minute/`.
+    pub fn from_source_prfx(source_prfx: &str) -> UpdatePeriod {
+        let s = source_prfx.trim_end_matches("/");
+        if s.ends_with("minute") {
+            UpdatePeriod::Minute
+        } else if s.ends_with("hour") {
+            UpdatePeriod::Hour
+        } else {
+            UpdatePeriod::Day
+        }
+    }
+
+    fn update_filename(&self, ts: i64) -> String {
+        match self {
+            UpdatePeriod::Day => date_string(ts),
+            _ => timestamp_string_alt(ts),
+        }
+    }
+}
+
+impl std::str::FromStr for UpdatePeriod {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<UpdatePeriod> {
+        match s {
+            "minute" => Ok(UpdatePeriod::Minute),
+            "hour" => Ok(UpdatePeriod::Hour),
+            "day" => Ok(UpdatePeriod::Day),
+            _ => Err(Error::UserSelectionError(format!(
+                "unknown period {}: use minute, hour or day",
+                s
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct Settings {
@@ -20,7 +83,13 @@ struct Settings {
     pub source_prfx: String,
     pub round_time: bool,
     pub max_qt_level: usize,
-    pub qt_buffer: f64
+    pub qt_buffer: f64,
+    /// period of the diffs at source_prfx
+    #[serde(default)]
+    pub replication_period: UpdatePeriod,
+    /// period of each update file: diffs are merged until a period is complete
+    #[serde(default)]
+    pub update_period: UpdatePeriod,
 }
 
 impl Settings {
@@ -31,20 +100,66 @@ impl Settings {
             source_prfx: String::from(DEFAULT_SOURCE_PRFX),
             round_time: true,
             max_qt_level: max_qt_level,
-            qt_buffer: qt_buffer
+            qt_buffer: qt_buffer,
+            replication_period: UpdatePeriod::Day,
+            update_period: UpdatePeriod::Day,
+        }
+    }
+
+    pub fn from_file(prfx: &str) -> Result<Settings> {
+        let fname = format!("{}settings.json", prfx);
+        let ff = match File::open(&fname) {
+            Ok(ff) => ff,
+            Err(e) => {
+                return Err(Error::SettingsError(format!("failed to open {}: {}", fname, e)));
+            }
+        };
+        match serde_json::from_reader(ff) {
+            Ok(settings) => Ok(settings),
+            Err(e) => Err(Error::SettingsError(format!("failed to read {}: {}", fname, e))),
         }
     }
 
-    pub fn from_file(prfx: &str) -> Settings {
-        let ff =
-            File::open(format!("{}settings.json", prfx)).expect("failed to open settings file");
-        serde_json::from_reader(ff).expect("failed to parse json")
+    pub fn write(&self, prfx: &str) -> Result<()> {
+        let data = serde_json::to_vec(self)?;
+        write_file_atomic(&format!("{}settings.json", prfx), &data)?;
+        Ok(())
     }
+}
+
+/// Returns a [`HeaderInfo`] with the osmosis replication fields set from the
+/// last filelist entry of the osmquadtree directory `prfx` (ignoring entries
+/// after `timestamp`) and the source_prfx in its settings.json. If `prfx` is
+/// a single file, the replication and unknown fields are copied from its
+/// header.
+pub fn get_replication_header_info(prfx: &str, timestamp: Option<i64>) -> Result<HeaderInfo> {
+    if std::path::Path::new(prfx).is_file() {
+        let mut fbuf = BufReader::new(File::open(prfx)?);
+        let fb = read_file_block(&mut fbuf)?;
+        let head = HeaderBlock::read(file_position(&mut fbuf)?, &fb.try_data()?, prfx)?;
+
+        return Ok(HeaderInfo::from_header_block(&head));
+    }
+
+    let filelist = read_filelist(prfx)?;
+    let mut last = None;
+    for fle in &filelist {
+        let fle_ts = parse_timestamp(&fle.end_date)?;
+        if !timestamp.is_none() && fle_ts > timestamp.unwrap() {
+            break;
+        }
+        last = Some((fle_ts, fle.state));
+    }
+
+    let base_url = if std::path::Path::new(&format!("{}settings.json", prfx)).exists() {
+        Some(Settings::from_file(prfx)?.source_prfx)
+    } else {
+        None
+    };
 
-    pub fn write(&self, prfx: &str) {
-        let ff =
-            File::create(format!("{}settings.json", prfx)).expect("failed to create settings file");
-        serde_json::to_writer(ff, self).expect("failed to write json");
+    match last {
+        Some((ts, state)) => Ok(HeaderInfo::with_replication(ts, state, base_url.as_deref())),
+        None => Ok(HeaderInfo::new()),
     }
 }
 
@@ -85,19 +200,7 @@ fn fetch_diff(
     let diff_url = get_diff_url(source_prfx, state);
     let outfn = format!("{}{}.osc.gz", diffs_location, state);
 
-    let output = std::process::Command::new("wget")
-        .arg("-O")
-        .arg(&outfn)
-        .arg(&diff_url)
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .output()?;
-
-    if !output.status.success() {
-        return Err(Error::ExternalCallError(
-            format!("wget -O {} {} failed", outfn, diff_url),
-        ));
-    }
+    fetch_osc_gz(&diff_url, &outfn)?;
 
     OpenOptions::new()
         .append(true)
@@ -130,7 +233,8 @@ fn get_diff_url(source_prfx: &str, state: i64) -> String {
 pub fn get_state(source_prfx: &str, state: Option<i64>) -> Result<(i64, i64)> {
     let state_url = get_diff_state_url(source_prfx, state);
 
-    let state_response = ureq::get(&state_url).call()?.into_string()?;
+    let state_data = fetch_source(&state_url)?;
+    let state_response = std::str::from_utf8(&state_data)?;
 
     let mut seq_num: Option<i64> = None;
     let mut timestamp: Option<i64> = None;
@@ -164,7 +268,7 @@ pub fn get_state(source_prfx: &str, state: Option<i64>) -> Result<(i64, i64)> {
 }
 
 fn find_initial_state(source_prfx: Option<&str>, diffs_location: &str, timestamp: i64) -> Result<i64> {
-    let csv_list = read_csv_list(diffs_location, 0);
+    let csv_list = read_csv_list(diffs_location, 0)?;
     for pair in csv_list.windows(2) {
         if pair[1].2 == timestamp {
             return Ok(pair[1].1);
@@ -193,43 +297,64 @@ fn find_initial_state(source_prfx: Option<&str>, diffs_location: &str, timestamp
 }
 
 
-fn read_csv_list(diffs_location: &str, last_state: i64) -> Vec<(String, i64, i64)> {
+fn read_csv_list(diffs_location: &str, last_state: i64) -> Result<Vec<(String, i64, i64)>> {
     let mut res = Vec::new();
 
-    let state_ff =
-        File::open(format!("{}state.csv", diffs_location)).expect("failed to open state.csv file");
+    let fname = format!("{}state.csv", diffs_location);
+    let state_ff = match File::open(&fname) {
+        Ok(ff) => ff,
+        Err(e) => {
+            return Err(Error::StateCsvError(format!("failed to open {}: {}", fname, e)));
+        }
+    };
 
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false)
         .from_reader(state_ff);
 
     for row in rdr.records() {
-        let row = row.expect("?");
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                return Err(Error::StateCsvError(format!("failed to read {}: {}", fname, e)));
+            }
+        };
 
         if row.len() == 2 {
-            let state: i64 = row[0].parse().unwrap();
+            let state: i64 = match row[0].parse() {
+                Ok(state) => state,
+                Err(_) => {
+                    return Err(Error::StateCsvError(format!(
+                        "{}: invalid state {}",
+                        fname, &row[0]
+                    )));
+                }
+            };
 
             if state > last_state {
-                let timestamp = parse_timestamp(&row[1]).expect("?");
+                let timestamp = parse_timestamp(&row[1])?;
                 let fname = format!("{}{}.osc.gz", diffs_location, state);
                 res.push((fname, state, timestamp));
             }
         }
     }
-    res
+    Ok(res)
 }
 
 fn check_state(
     settings: &Settings,
     filelist: &Vec<FilelistEntry>,
-) -> (LogTimes, Vec<(String, i64, i64)>, i64) {
+) -> Result<(LogTimes, Vec<(String, i64, i64)>, i64)> {
     let mut tms = LogTimes::new();
-    if filelist.is_empty() {
-        panic!("empty filelist");
-    }
-    let last_state = filelist.last().unwrap().state;
-    let prev_ts = parse_timestamp(&filelist.last().unwrap().end_date).expect("?");
-    let mut csv_rec = read_csv_list(&settings.diffs_location, last_state);
+    let last = match filelist.last() {
+        Some(last) => last,
+        None => {
+            return Err(Error::FilelistError("empty filelist".to_string()));
+        }
+    };
+    let last_state = last.state;
+    let prev_ts = parse_timestamp(&last.end_date)?;
+    let mut csv_rec = read_csv_list(&settings.diffs_location, last_state)?;
     let last_state_available = if csv_rec.is_empty() {
         last_state
     } else {
@@ -243,10 +368,9 @@ fn check_state(
         last_state_available,
         &mut csv_rec,
         &mut tms,
-    )
-    .expect("!!");
+    )?;
 
-    (tms, csv_rec, prev_ts)
+    Ok((tms, csv_rec, prev_ts))
 }
 
 pub fn run_update_initial(
@@ -258,13 +382,34 @@ pub fn run_update_initial(
     diffs_location: &str,
     max_qt_level: usize,
     qt_buffer: f64,
+    replication_period: Option<&str>,
+    update_period: Option<&str>,
     numchan: usize,
 ) -> Result<()> {
     let timestamp = parse_timestamp(timestamp)?;
 
+    let replication_period = match replication_period {
+        Some(p) => p.parse()?,
+        None => UpdatePeriod::from_source_prfx(diffs_src.unwrap_or(DEFAULT_SOURCE_PRFX)),
+    };
+    let update_period = match update_period {
+        Some(p) => p.parse()?,
+        None => UpdatePeriod::Day,
+    };
+    if update_period < replication_period {
+        return Err(Error::UserSelectionError(format!(
+            "update period {:?} shorter than replication period {:?}",
+            update_period, replication_period
+        )));
+    }
+
+    //source_prfx may be an http(s) url, a file:// url or a local directory
+    let diffs_src = diffs_src.map(|d| if d.ends_with("/") { String::from(d) } else { format!("{}/", d) });
+    let diffs_src = diffs_src.as_deref();
+
     let outfn = format!("{}{}-index.pbf", prfx, infn);
     let infn2 = format!("{}{}", prfx, infn);
-    let num_tiles = write_index_file(&infn2, &outfn, numchan);
+    let num_tiles = write_index_file(&infn2, &outfn, numchan)?;
     
     
     
@@ -279,24 +424,86 @@ pub fn run_update_initial(
     if let Some(diff) = diffs_src {
         settings.source_prfx = diff.to_string();
     };
+    settings.replication_period = replication_period;
+    settings.update_period = update_period;
     message!("{:?}", settings);
-    settings.write(prfx);
+    settings.write(prfx)?;
 
     write_filelist(
         prfx,
-        &vec![FilelistEntry::new(
+        &vec![FilelistEntry::new_checked(
+            prfx,
             String::from(infn),
             timestamp_string(timestamp),
             num_tiles,
             initial_state,
-        )],
-    );
+        )?],
+    )?;
     Ok(())
 }
 
+struct UpdateGroup {
+    change_filenames: Vec<String>,
+    state: i64,
+    timestamp: i64,
+    fname_timestamp: i64,
+}
+
+//groups the diffs in to_update into one update per settings.update_period.
+//diffs are only merged once their period is complete, so the last group may
+//be held back until the next run
+fn group_updates(settings: &Settings, to_update: Vec<(String, i64, i64)>) -> Vec<UpdateGroup> {
+    let mut res: Vec<UpdateGroup> = Vec::new();
+    if settings.update_period == settings.replication_period {
+        for (chgfn, state, ts) in to_update {
+            res.push(UpdateGroup {
+                change_filenames: vec![chgfn],
+                state: state,
+                timestamp: ts,
+                fname_timestamp: ts,
+            });
+        }
+        return res;
+    }
+
+    for (chgfn, state, ts) in to_update {
+        let end = settings.update_period.period_end(ts);
+        match res.last_mut() {
+            Some(g) if g.fname_timestamp == end => {
+                g.change_filenames.push(chgfn);
+                g.state = state;
+                g.timestamp = ts;
+            }
+            _ => {
+                res.push(UpdateGroup {
+                    change_filenames: vec![chgfn],
+                    state: state,
+                    timestamp: ts,
+                    fname_timestamp: end,
+                });
+            }
+        }
+    }
+    if let Some(g) = res.last() {
+        if g.timestamp < g.fname_timestamp {
+            message!(
+                "period ending {} incomplete: {} diffs up to {}",
+                timestamp_string(g.fname_timestamp),
+                g.change_filenames.len(),
+                timestamp_string(g.timestamp)
+            );
+            res.pop();
+        }
+    }
+    res
+}
+
 pub fn run_update(prfx: &str, limit: usize, as_demo: bool, numchan: usize) -> Result<()> {
-    let settings = Settings::from_file(prfx);
-    let mut filelist = read_filelist(prfx);
+    if !as_demo {
+        recover_update(prfx)?;
+    }
+    let settings = Settings::from_file(prfx)?;
+    let mut filelist = read_filelist(prfx)?;
     let mut suffix = String::new();
     if as_demo {
         filelist.pop();
@@ -308,9 +515,10 @@ pub fn run_update(prfx: &str, limit: usize, as_demo: bool, numchan: usize) -> Re
         suffix = String::from("-rust");
     }
 
-    let (mut logtimes, mut to_update, mut prev_ts) = check_state(&settings, &filelist);
+    let (mut logtimes, to_update, mut prev_ts) = check_state(&settings, &filelist)?;
+    let mut to_update = group_updates(&settings, to_update);
     if limit > 0 && to_update.len() > limit {
-        to_update = to_update[..limit].to_vec();
+        to_update.truncate(limit);
     }
     message!(
         "have {} in filelist, {} to update",
@@ -319,13 +527,19 @@ pub fn run_update(prfx: &str, limit: usize, as_demo: bool, numchan: usize) -> Re
     );
 
     if !to_update.is_empty() {
-        for (chgfn, state, ts) in to_update {
-            let fname = format!("{}{}.pbfc", date_string(ts), suffix);
+        //the demo files are never added to the filelist, so aren't journaled
+        let mut journal = UpdateJournal::new();
+        for group in to_update {
+            let ts = group.timestamp;
+            let fname = format!("{}{}.pbfc", settings.update_period.update_filename(group.fname_timestamp), suffix);
+            if !as_demo {
+                journal.start(prfx, &fname)?;
+            }
             message!(
-                "call find_update('{}',{} entries,'{}', {}, {}, {}, {}, {}, {})",
+                "call find_update('{}',{} entries,{:?}, {}, {}, {}, {}, {}, {})",
                 prfx,
                 filelist.len(),
-                chgfn,
+                group.change_filenames,
                 prev_ts,
                 ts,
                 settings.max_qt_level,
@@ -334,21 +548,212 @@ pub fn run_update(prfx: &str, limit: usize, as_demo: bool, numchan: usize) -> Re
                 numchan
             );
 
-            let (_tx, nt) = find_update(prfx, &filelist, &chgfn, prev_ts, ts, settings.max_qt_level, settings.qt_buffer, &fname, numchan)?;
+            let (_tx, nt) = find_update(prfx, &filelist, &group.change_filenames, prev_ts, ts, settings.max_qt_level, settings.qt_buffer, &fname, numchan)?;
             logtimes.add(&fname);
 
             let idxfn = format!("{}{}-index.pbf", prfx, fname);
             //let txx = ThreadTimer::new();
-            write_index_file(&format!("{}{}", prfx, fname), &idxfn, numchan);
+            write_index_file(&format!("{}{}", prfx, fname), &idxfn, numchan)?;
             logtimes.add(&format!("{}-index.pbf", fname));
 
-            filelist.push(FilelistEntry::new(fname, timestamp_string(ts), nt, state));
+            let entry = FilelistEntry::new_checked(prfx, fname, timestamp_string(ts), nt, group.state)?;
+            if !as_demo {
+                journal.complete(prfx, entry.clone())?;
+            }
+            filelist.push(entry);
             prev_ts = ts;
         }
         if !as_demo {
-            write_filelist(prfx, &filelist);
+            write_filelist(prfx, &filelist)?;
+            UpdateJournal::remove(prfx)?;
         }
     }
     message!("{}", logtimes);
     Ok(())
 }
+
+/// Removes the updates in the osmquadtree directory `prfx` after `to`, which
+/// is either a replication state number or a timestamp. The filelist entries, their
+/// pbfc and index files, the downloaded diffs and the matching lines of
+/// state.csv are removed, so that the next update starts again from `to`.
+/// Returns the names of the files removed, or which would be removed if
+/// `dry_run` is true.
+pub fn run_update_rollback(prfx: &str, to: &str, dry_run: bool) -> Result<Vec<String>> {
+    if !dry_run {
+        recover_update(prfx)?;
+    }
+    let settings = Settings::from_file(prfx)?;
+    let filelist = read_filelist(prfx)?;
+
+    let mut keep = 0;
+    match to.parse::<i64>() {
+        Ok(state) => {
+            for fle in &filelist {
+                if fle.state > state {
+                    break;
+                }
+                keep += 1;
+            }
+        }
+        Err(_) => {
+            let timestamp = parse_timestamp(to)?;
+            for fle in &filelist {
+                if parse_timestamp(&fle.end_date)? > timestamp {
+                    break;
+                }
+                keep += 1;
+            }
+        }
+    }
+    if filelist.is_empty() {
+        return Err(Error::FilelistError(format!("{}filelist.json is empty", prfx)));
+    }
+    if keep == 0 {
+        return Err(Error::UserSelectionError(format!(
+            "can't roll back to {}: before {}",
+            to, filelist[0].filename
+        )));
+    }
+
+    let last_state = filelist[keep - 1].state;
+    let mut removed = Vec::new();
+    for fle in &filelist[keep..] {
+        removed.push(format!("{}{}", prfx, fle.filename));
+        removed.push(format!("{}{}-index.pbf", prfx, fle.filename));
+    }
+
+    let state_csv = format!("{}state.csv", settings.diffs_location);
+    let state_lines = if std::path::Path::new(&state_csv).exists() {
+        std::fs::read_to_string(&state_csv)?
+    } else {
+        String::new()
+    };
+    let mut kept_lines = Vec::new();
+    let mut num_trimmed = 0;
+    for l in state_lines.lines() {
+        match l.split(',').next().and_then(|s| s.parse::<i64>().ok()) {
+            Some(state) if state > last_state => {
+                removed.push(format!("{}{}.osc.gz", settings.diffs_location, state));
+                num_trimmed += 1;
+            }
+            _ => {
+                kept_lines.push(l);
+            }
+        }
+    }
+    let removed: Vec<String> = removed.into_iter().filter(|f| std::path::Path::new(f).exists()).collect();
+
+    message!(
+        "{} {} filelist entries after state {} ({}), {} lines of {}",
+        if dry_run { "would remove" } else { "removing" },
+        filelist.len() - keep,
+        last_state,
+        filelist[keep - 1].end_date,
+        num_trimmed,
+        state_csv
+    );
+    for f in &removed {
+        message!("{} {}", if dry_run { "would remove" } else { "remove" }, f);
+    }
+    if dry_run {
+        return Ok(removed);
+    }
+
+    //rewrite filelist.json first, so that the directory stays usable if
+    //interrupted
+    write_filelist(prfx, &filelist[..keep].to_vec())?;
+    if num_trimmed > 0 {
+        let mut data = String::new();
+        for l in kept_lines {
+            data.push_str(l);
+            data.push('\n');
+        }
+        write_file_atomic(&state_csv, data.as_bytes())?;
+    }
+    for f in &removed {
+        std::fs::remove_file(f)?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILES: [(&str, i64, &str); 3] = [
+        ("base.pbf", 10, "2024-01-01T00:00:00"),
+        ("20240102.pbfc", 11, "2024-01-02T00:00:00"),
+        ("20240103.pbfc", 12, "2024-01-03T00:00:00"),
+    ];
+
+    fn setup_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("osmquadtree-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("diffs")).unwrap();
+        let prfx = format!("{}/", dir.to_str().unwrap());
+        let diffs = format!("{}diffs/", prfx);
+
+        Settings::new(10, &diffs, 18, 0.05).write(&prfx).unwrap();
+        let mut filelist = Vec::new();
+        let mut state_csv = String::new();
+        for (fname, state, end_date) in FILES {
+            std::fs::write(format!("{}{}", prfx, fname), b"x").unwrap();
+            std::fs::write(format!("{}{}-index.pbf", prfx, fname), b"x").unwrap();
+            filelist.push(FilelistEntry::new(String::from(fname), String::from(end_date), 1, state));
+            state_csv.push_str(&format!("{},{}\n", state, end_date));
+            if state > 10 {
+                std::fs::write(format!("{}{}.osc.gz", diffs, state), b"x").unwrap();
+            }
+        }
+        write_filelist(&prfx, &filelist).unwrap();
+        std::fs::write(format!("{}state.csv", diffs), state_csv).unwrap();
+        prfx
+    }
+
+    fn expected_removed(prfx: &str) -> Vec<String> {
+        vec![
+            format!("{}20240103.pbfc", prfx),
+            format!("{}20240103.pbfc-index.pbf", prfx),
+            format!("{}diffs/12.osc.gz", prfx),
+        ]
+    }
+
+    #[test]
+    fn rollback_dry_run_changes_nothing() {
+        let prfx = setup_dir("rollback-dry-run");
+        assert_eq!(run_update_rollback(&prfx, "11", true).unwrap(), expected_removed(&prfx));
+        assert_eq!(read_filelist(&prfx).unwrap().len(), 3);
+        for f in expected_removed(&prfx) {
+            assert!(std::path::Path::new(&f).exists());
+        }
+        std::fs::remove_dir_all(&prfx).unwrap();
+    }
+
+    #[test]
+    fn rollback_to_timestamp() {
+        let prfx = setup_dir("rollback-timestamp");
+        assert_eq!(
+            run_update_rollback(&prfx, "2024-01-02T12:00:00", false).unwrap(),
+            expected_removed(&prfx)
+        );
+        let filelist = read_filelist(&prfx).unwrap();
+        assert_eq!(filelist.len(), 2);
+        assert_eq!(filelist[1].state, 11);
+        for f in expected_removed(&prfx) {
+            assert!(!std::path::Path::new(&f).exists());
+        }
+        assert_eq!(read_csv_list(&format!("{}diffs/", prfx), 0).unwrap().len(), 2);
+        std::fs::remove_dir_all(&prfx).unwrap();
+    }
+
+    #[test]
+    fn rollback_before_first_entry_is_error() {
+        let prfx = setup_dir("rollback-too-early");
+        match run_update_rollback(&prfx, "5", false) {
+            Err(Error::UserSelectionError(_)) => {}
+            r => panic!("expected UserSelectionError, got {:?}", r),
+        }
+        assert_eq!(read_filelist(&prfx).unwrap().len(), 3);
+        std::fs::remove_dir_all(&prfx).unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use std::fmt;
-use std::io::Read;
+use std::io::{Read, Write};
 //use std::io::{Error, ErrorKind, Result};
 
 
@@ -25,7 +25,12 @@ pub enum Error {
     ExternalCallError(std::string::String),
     UnexpectedResponseError(std::string::String),
     ChannelledCallbackError(std::string::String),
-    InvalidDataError(std::string::String)
+    InvalidDataError(std::string::String),
+
+    FilelistError(std::string::String),
+    SettingsError(std::string::String),
+    StateCsvError(std::string::String),
+    IndexFileError(std::string::String),
 }
 
 impl std::error::Error for Error {
@@ -97,6 +102,10 @@ impl std::fmt::Display for Error {
             Error::UnexpectedResponseError(e) => write!(f, "UnexpectedResponseError: {}", e),
             Error::ChannelledCallbackError(e) => write!(f, "ChannelledCallbackError: {}", e),
             Error::InvalidDataError(e) => write!(f, "InvalidDataError: {}", e),
+            Error::FilelistError(e) => write!(f, "FilelistError: {}", e),
+            Error::SettingsError(e) => write!(f, "SettingsError: {}", e),
+            Error::StateCsvError(e) => write!(f, "StateCsvError: {}", e),
+            Error::IndexFileError(e) => write!(f, "IndexFileError: {}", e),
         }
         
     }
@@ -325,3 +334,47 @@ pub fn at_end_of_file<R: Read>(f: &mut R) -> Result<bool> {
         Err(e) => Err(Error::Io(e))
     }
 }
+
+static ATOMIC_WRITE_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// Writes `data` to `fname` by writing a temporary file alongside it and then
+/// renaming it into place, so that `fname` is never left partly written. The
+/// temporary file name is unique to this process and call, and the directory
+/// is synced after the rename so that the new entry is durable.
+pub fn write_file_atomic(fname: &str, data: &[u8]) -> std::io::Result<()> {
+    let tmpfn = format!(
+        "{}.{}-{}.tmp",
+        fname,
+        std::process::id(),
+        ATOMIC_WRITE_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    );
+    let res = write_and_rename(&tmpfn, fname, data);
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmpfn);
+    }
+    res
+}
+
+fn write_and_rename(tmpfn: &str, fname: &str, data: &[u8]) -> std::io::Result<()> {
+    {
+        let mut ff = std::fs::OpenOptions::new().write(true).create_new(true).open(tmpfn)?;
+        ff.write_all(data)?;
+        ff.sync_all()?;
+    }
+    std::fs::rename(tmpfn, fname)?;
+    sync_parent_dir(fname)
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(fname: &str) -> std::io::Result<()> {
+    let parent = match std::path::Path::new(fname).parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => std::path::Path::new("."),
+    };
+    std::fs::File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_fname: &str) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,551 @@
+use channelled_callbacks::{CallFinish, Callback, CallbackMerge, MergeTimings, Timings, Result as ccResult};
+use serde::Serialize;
+use simple_protocolbuffers::{read_delta_packed_int, read_packed_int, IterTags, PbfTag};
+
+use crate::elements::{Changetype, Info, PrimitiveBlock, Quadtree};
+use crate::pbfformat::{
+    file_position, try_file_length, get_file_locs, read_all_blocks_parallel_with_progbar,
+    read_all_blocks_vec_with_progbar, read_file_block, read_filelist, BadBlocks, FileBlock, HeaderBlock,
+};
+use crate::utils::{ThreadTimer, Error, Result};
+use crate::message;
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ProblemType {
+    ReadBlock,
+    Decompress,
+    StringTable,
+    ParseBlock,
+    IdOrder,
+    IndexLocation,
+    IndexLength,
+    DuplicateTile,
+    Quadtree,
+}
+
+/// A single problem found by [`verify`]. `offset` is the file position of
+/// the block concerned, and `tile` the quadtree of that block (as a string)
+/// where known.
+#[derive(Debug, Serialize)]
+pub struct VerifyProblem {
+    pub filename: String,
+    pub offset: u64,
+    pub tile: Option<String>,
+    pub problem: ProblemType,
+    pub message: String,
+}
+
+impl VerifyProblem {
+    pub fn new(filename: &str, offset: u64, tile: Option<&Quadtree>, problem: ProblemType, message: String) -> VerifyProblem {
+        VerifyProblem {
+            filename: filename.to_string(),
+            offset: offset,
+            tile: tile.map(|q| q.as_string()),
+            problem: problem,
+            message: message,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub files: Vec<String>,
+    pub num_blocks: usize,
+    pub num_problems: usize,
+    pub problems: Vec<VerifyProblem>,
+}
+
+//the bool is true for full-history files
+enum IndexCheck {
+    Unreadable,
+    NoIndex(bool),
+    Checked(BTreeSet<u64>, bool),
+}
+
+fn read_block_length<F: Read + Seek>(fobj: &mut F, pos: u64) -> std::io::Result<u64> {
+    fobj.seek(SeekFrom::Start(pos))?;
+    let mut a = [0u8; 4];
+    fobj.read_exact(&mut a)?;
+    let (l, _) = simple_protocolbuffers::read_uint32(&a, 0)?;
+
+    let mut b = vec![0u8; l as usize];
+    fobj.read_exact(&mut b)?;
+    let mut ln = 0;
+    for tg in IterTags::new(&b) {
+        match tg {
+            PbfTag::Value(3, v) => ln = v,
+            _ => {}
+        }
+    }
+    Ok(4 + l + ln)
+}
+
+fn check_index(fname: &str, problems: &mut Vec<VerifyProblem>) -> Result<IndexCheck> {
+    let flen = try_file_length(fname)?;
+    let mut fbuf = BufReader::new(File::open(fname)?);
+
+    let fb = match read_file_block(&mut fbuf) {
+        Ok(fb) => fb,
+        Err(e) => {
+            problems.push(VerifyProblem::new(fname, 0, None, ProblemType::ReadBlock, format!("failed to read header block: {}", e)));
+            return Ok(IndexCheck::Unreadable);
+        }
+    };
+    let data = match fb.try_data() {
+        Ok(d) => d,
+        Err(e) => {
+            problems.push(VerifyProblem::new(fname, 0, None, ProblemType::Decompress, format!("{}", e)));
+            return Ok(IndexCheck::Unreadable);
+        }
+    };
+    let filepos = file_position(&mut fbuf)?;
+    let head = match HeaderBlock::read(filepos, &data, fname) {
+        Ok(h) => h,
+        Err(e) => {
+            problems.push(VerifyProblem::new(fname, 0, None, ProblemType::ParseBlock, format!("failed to read header block: {}", e)));
+            return Ok(IndexCheck::Unreadable);
+        }
+    };
+
+    let history = head.is_history();
+    if head.index.is_empty() {
+        return Ok(IndexCheck::NoIndex(history));
+    }
+
+    let mut bad = BTreeSet::new();
+    let mut seen = BTreeSet::new();
+    let mut expected = filepos;
+
+    for item in &head.index {
+        let qt = Some(&item.quadtree);
+        if !seen.insert(item.quadtree.clone()) {
+            problems.push(VerifyProblem::new(fname, item.location, qt, ProblemType::DuplicateTile,
+                format!("tile {} appears more than once in index", item.quadtree.as_string())));
+        }
+        if item.location != expected {
+            problems.push(VerifyProblem::new(fname, item.location, qt, ProblemType::IndexLocation,
+                format!("index location {}, expected block at {}", item.location, expected)));
+        }
+        if item.location >= flen {
+            problems.push(VerifyProblem::new(fname, item.location, qt, ProblemType::IndexLocation,
+                format!("index location {} past end of file [{}]", item.location, flen)));
+            bad.insert(item.location);
+            continue;
+        }
+
+        match read_block_length(&mut fbuf, item.location) {
+            Ok(len) => {
+                if len != item.length {
+                    problems.push(VerifyProblem::new(fname, item.location, qt, ProblemType::IndexLength,
+                        format!("index length {}, block length {}", item.length, len)));
+                }
+                expected = item.location + len;
+            }
+            Err(e) => {
+                problems.push(VerifyProblem::new(fname, item.location, qt, ProblemType::ReadBlock,
+                    format!("failed to read block: {}", e)));
+                bad.insert(item.location);
+                expected = item.location + item.length;
+            }
+        }
+    }
+    if expected != flen {
+        problems.push(VerifyProblem::new(fname, expected, None, ProblemType::IndexLocation,
+            format!("index ends at {}, file length {}", expected, flen)));
+    }
+    Ok(IndexCheck::Checked(bad, history))
+}
+
+fn check_string_index(what: &str, idx: i64, num_strings: usize) -> std::result::Result<(), String> {
+    if idx < 0 || (idx as usize) >= num_strings {
+        return Err(format!("{} string index {} out of range [stringtable has {} entries]", what, idx, num_strings));
+    }
+    Ok(())
+}
+
+fn check_element_strings(data: &[u8], num_strings: usize) -> std::result::Result<(), String> {
+    for x in IterTags::new(data) {
+        match x {
+            PbfTag::Data(2, d) => {
+                for k in read_packed_int(d) { check_string_index("key", k as i64, num_strings)?; }
+            }
+            PbfTag::Data(3, d) => {
+                for v in read_packed_int(d) { check_string_index("val", v as i64, num_strings)?; }
+            }
+            PbfTag::Data(4, d) => {
+                for y in IterTags::new(d) {
+                    match y {
+                        PbfTag::Value(5, u) => check_string_index("user", u as i64, num_strings)?,
+                        _ => {}
+                    }
+                }
+            }
+            PbfTag::Data(8, d) => {
+                for r in read_packed_int(d) { check_string_index("role", r as i64, num_strings)?; }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn check_dense_strings(data: &[u8], num_strings: usize) -> std::result::Result<(), String> {
+    for x in IterTags::new(data) {
+        match x {
+            PbfTag::Data(5, d) => {
+                for y in IterTags::new(d) {
+                    match y {
+                        PbfTag::Data(5, us) => {
+                            for u in read_delta_packed_int(us) { check_string_index("user", u, num_strings)?; }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            PbfTag::Data(10, d) => {
+                for kv in read_packed_int(d) { check_string_index("key/val", kv as i64, num_strings)?; }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+//PrimitiveBlock::read indexes the stringtable directly, so check the raw
+//data first rather than let a bad index panic
+fn check_string_indices(data: &[u8]) -> std::result::Result<(), String> {
+    let mut num_strings = 0;
+    let mut groups = Vec::new();
+    for x in IterTags::new(data) {
+        match x {
+            PbfTag::Data(1, d) => {
+                for y in IterTags::new(d) {
+                    match y {
+                        PbfTag::Data(1, _) => num_strings += 1,
+                        _ => {}
+                    }
+                }
+            }
+            PbfTag::Data(2, d) => groups.push(d),
+            _ => {}
+        }
+    }
+
+    for g in groups {
+        for x in IterTags::new(g) {
+            match x {
+                PbfTag::Data(1, d) | PbfTag::Data(3, d) | PbfTag::Data(4, d) => check_element_strings(d, num_strings)?,
+                PbfTag::Data(2, d) => check_dense_strings(d, num_strings)?,
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+//full-history files have several versions of each id, ordered by version
+fn history_version(history: bool, info: &Option<Info>) -> i64 {
+    match (history, info) {
+        (true, Some(info)) => info.version,
+        _ => 0,
+    }
+}
+
+fn check_id_order(elements: impl Iterator<Item = (i64, i64, Changetype)>) -> Option<(usize, i64, i64)> {
+    let mut num_bad = 0;
+    let mut first = None;
+    let mut prev: Option<(i64, i64, Changetype)> = None;
+    for (id, version, ct) in elements {
+        match prev {
+            Some((pid, pversion, pct)) if pct == ct && (id, version) <= (pid, pversion) => {
+                num_bad += 1;
+                if first.is_none() {
+                    first = Some((pid, id));
+                }
+            }
+            _ => {}
+        }
+        prev = Some((id, version, ct));
+    }
+    first.map(|(a, b)| (num_bad, a, b))
+}
+
+fn within_block(qt: &Quadtree, block: &Quadtree) -> bool {
+    qt.is_empty() || (qt.depth() >= block.depth() && qt.round(block.depth()) == *block)
+}
+
+fn check_quadtrees<'a>(elements: impl Iterator<Item = (&'static str, i64, &'a Quadtree)>, block: &Quadtree) -> Option<(usize, String)> {
+    let mut num_bad = 0;
+    let mut first = None;
+    for (ty, id, qt) in elements {
+        if !within_block(qt, block) {
+            num_bad += 1;
+            if first.is_none() {
+                first = Some(format!("{} {} [{}]", ty, id, qt.as_string()));
+            }
+        }
+    }
+    first.map(|f| (num_bad, f))
+}
+
+fn verify_block(fname: &str, ischange: bool, history: bool, tile: Option<&Quadtree>, fb: &FileBlock, problems: &mut Vec<VerifyProblem>) {
+    if fb.block_type != "OSMData" {
+        return;
+    }
+
+    let data = match fb.try_data() {
+        Ok(d) => d,
+        Err(e) => {
+            problems.push(VerifyProblem::new(fname, fb.pos, tile, ProblemType::Decompress, format!("{}", e)));
+            return;
+        }
+    };
+
+    match check_string_indices(&data) {
+        Ok(()) => {}
+        Err(e) => {
+            problems.push(VerifyProblem::new(fname, fb.pos, tile, ProblemType::StringTable, e));
+            return;
+        }
+    }
+
+    let pb = match PrimitiveBlock::read(0, fb.pos, &data, ischange, false) {
+        Ok(pb) => pb,
+        Err(e) => {
+            problems.push(VerifyProblem::new(fname, fb.pos, tile, ProblemType::ParseBlock, format!("{}", e)));
+            return;
+        }
+    };
+
+    let tile = match tile {
+        Some(t) => Some(t),
+        None if !pb.quadtree.is_empty() => Some(&pb.quadtree),
+        None => None,
+    };
+
+    for (ty, res) in [
+        ("node", check_id_order(pb.nodes.iter().map(|n| (n.id, history_version(history, &n.info), n.changetype)))),
+        ("way", check_id_order(pb.ways.iter().map(|w| (w.id, history_version(history, &w.info), w.changetype)))),
+        ("relation", check_id_order(pb.relations.iter().map(|r| (r.id, history_version(history, &r.info), r.changetype)))),
+    ] {
+        match res {
+            Some((num_bad, prev, id)) => {
+                problems.push(VerifyProblem::new(fname, fb.pos, tile, ProblemType::IdOrder,
+                    format!("{} {} {} ids out of order, first {} after {}", num_bad, ty, if num_bad == 1 { "id" } else { "ids" }, id, prev)));
+            }
+            None => {}
+        }
+    }
+
+    if pb.quadtree.is_empty() {
+        //unsorted file: nothing to check element quadtrees against
+        return;
+    }
+
+    match tile {
+        Some(t) if *t != pb.quadtree => {
+            problems.push(VerifyProblem::new(fname, fb.pos, tile, ProblemType::Quadtree,
+                format!("block quadtree {} doesn't match index tile", pb.quadtree.as_string())));
+        }
+        _ => {}
+    }
+
+    let elements = pb.nodes.iter().map(|n| ("node", n.id, &n.quadtree))
+        .chain(pb.ways.iter().map(|w| ("way", w.id, &w.quadtree)))
+        .chain(pb.relations.iter().map(|r| ("relation", r.id, &r.quadtree)));
+
+    match check_quadtrees(elements, &pb.quadtree) {
+        Some((num_bad, first)) => {
+            problems.push(VerifyProblem::new(fname, fb.pos, tile, ProblemType::Quadtree,
+                format!("{} elements outside block quadtree {}, first {}", num_bad, pb.quadtree.as_string(), first)));
+        }
+        None => {}
+    }
+}
+
+struct VerifyBlocks {
+    fname: String,
+    ischange: bool,
+    history: bool,
+    tiles: Option<Arc<Vec<Quadtree>>>,
+    num_blocks: usize,
+    problems: Vec<VerifyProblem>,
+    tm: f64,
+}
+
+impl VerifyBlocks {
+    fn new(fname: &str, ischange: bool, history: bool, tiles: Option<Arc<Vec<Quadtree>>>) -> VerifyBlocks {
+        VerifyBlocks {
+            fname: fname.to_string(),
+            ischange: ischange,
+            history: history,
+            tiles: tiles,
+            num_blocks: 0,
+            problems: Vec::new(),
+            tm: 0.0,
+        }
+    }
+}
+
+impl CallFinish for VerifyBlocks {
+    type CallType = (usize, Vec<FileBlock>);
+    type ReturnType = Timings<(usize, Vec<VerifyProblem>)>;
+    type ErrorType = Error;
+
+    fn call(&mut self, (j, fbs): (usize, Vec<FileBlock>)) {
+        let tx = ThreadTimer::new();
+        let tile = match &self.tiles {
+            Some(t) => Some(&t[j]),
+            None => None,
+        };
+        for fb in fbs {
+            if fb.block_type == "OSMData" {
+                self.num_blocks += 1;
+            }
+            verify_block(&self.fname, self.ischange, self.history, tile, &fb, &mut self.problems);
+        }
+        self.tm += tx.since();
+    }
+
+    fn finish(&mut self) -> ccResult<Self::ReturnType, Self::ErrorType> {
+        let mut tm = Self::ReturnType::new();
+        tm.add("verify", self.tm);
+        tm.add_other("verify", (self.num_blocks, std::mem::take(&mut self.problems)));
+        Ok(tm)
+    }
+}
+
+fn make_verify_blocks(
+    fname: &str,
+    ischange: bool,
+    history: bool,
+    tiles: Option<Arc<Vec<Quadtree>>>,
+    numchan: usize,
+) -> Box<impl CallFinish<CallType = (usize, Vec<FileBlock>), ReturnType = Timings<(usize, Vec<VerifyProblem>)>, ErrorType = Error>> {
+    let mut pps: Vec<
+        Box<
+            dyn CallFinish<
+                CallType = (usize, Vec<FileBlock>),
+                ReturnType = Timings<(usize, Vec<VerifyProblem>)>,
+                ErrorType = Error,
+            >,
+        >,
+    > = Vec::new();
+
+    if numchan == 0 {
+        pps.push(Box::new(VerifyBlocks::new(fname, ischange, history, tiles)));
+    } else {
+        for _ in 0..numchan {
+            pps.push(Box::new(Callback::new(Box::new(VerifyBlocks::new(fname, ischange, history, tiles.clone())))));
+        }
+    }
+    Box::new(CallbackMerge::new(pps, Box::new(MergeTimings::new())))
+}
+
+fn verify_file(fname: &str, numchan: usize, problems: &mut Vec<VerifyProblem>) -> Result<usize> {
+    let ischange = fname.ends_with(".pbfc");
+
+    let res = match check_index(fname, problems)? {
+        IndexCheck::Unreadable => {
+            return Ok(0);
+        }
+        IndexCheck::NoIndex(history) => {
+            let msg = format!("verify {}, numchan={}", fname, numchan);
+            let pp = make_verify_blocks(fname, ischange, history, None, numchan);
+            match read_all_blocks_vec_with_progbar(fname, pp, &msg, &BadBlocks::abort()) {
+                Ok((r, _)) => Ok(r),
+                Err(e) => Err(e),
+            }
+        }
+        IndexCheck::Checked(bad, history) => {
+            let (mut fbufs, mut locs, total_len, fnames) = get_file_locs(fname, None, None)?;
+
+            //blocks which couldn't be read have already been reported
+            locs.retain(|(_, ll)| !ll.iter().any(|(_, p)| bad.contains(p)));
+
+            let tiles = Arc::new(locs.iter().map(|(q, _)| q.clone()).collect::<Vec<Quadtree>>());
+            let msg = format!("verify {}, {} tiles, numchan={}", fname, locs.len(), numchan);
+            let pp = make_verify_blocks(fname, ischange, history, Some(tiles), numchan);
+            read_all_blocks_parallel_with_progbar(&mut fbufs, &fnames, &locs, pp, &msg, total_len, &BadBlocks::abort())
+        }
+    };
+
+    match res {
+        Ok(tm) => {
+            let mut num_blocks = 0;
+            for (_, (nb, pp)) in tm.others {
+                num_blocks += nb;
+                problems.extend(pp);
+            }
+            Ok(num_blocks)
+        }
+        Err(e) => {
+            //can't find the next block after a bad block header when reading
+            //sequentially, so this ends the check of this file
+            problems.push(VerifyProblem::new(fname, 0, None, ProblemType::ReadBlock, format!("{}", e)));
+            Ok(0)
+        }
+    }
+}
+
+/// Checks the integrity of a pbf file, or every file in an osmquadtree
+/// directory (with a trailing slash). Every blob is decompressed and parsed,
+/// and the header index, stringtable indices, id ordering and element
+/// quadtrees are checked. Problems are collected into the returned
+/// [`VerifyReport`] rather than returned as errors.
+pub fn verify(input: &str, numchan: usize) -> Result<VerifyReport> {
+    let files = if std::path::Path::new(input).is_file() {
+        vec![input.to_string()]
+    } else {
+        read_filelist(input)?
+            .iter()
+            .map(|fle| format!("{}{}", input, fle.filename))
+            .collect::<Vec<String>>()
+    };
+
+    let mut problems = Vec::new();
+    let mut num_blocks = 0;
+    for fname in &files {
+        num_blocks += verify_file(fname, numchan, &mut problems)?;
+    }
+
+    Ok(VerifyReport {
+        files: files,
+        num_blocks: num_blocks,
+        num_problems: problems.len(),
+        problems: problems,
+    })
+}
+
+/// Runs [`verify`] and writes the report as json to `outfn`, or to stdout
+/// if `outfn` is None. Returns an error if any problems were found.
+pub fn run_verify(input: &str, outfn: Option<&str>, numchan: usize) -> Result<()> {
+    let report = verify(input, numchan)?;
+
+    match outfn {
+        Some(outfn) => {
+            let ff = File::create(outfn)?;
+            serde_json::to_writer_pretty(ff, &report)?;
+        }
+        None => {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+
+    message!(
+        "checked {} blocks in {} files: {} problems",
+        report.num_blocks,
+        report.files.len(),
+        report.num_problems
+    );
+
+    if report.num_problems > 0 {
+        return Err(Error::InvalidDataError(format!("{} problems found", report.num_problems)));
+    }
+    Ok(())
+}
@@ -34,7 +34,7 @@ impl Mode {
 
 #[derive(Args, Debug)]
 pub struct Calcqts {
-    ///Sets the input file (or directory) to use
+    ///Sets the input file (or directory) to use, or - to read from stdin (mode inmem only)
     #[arg(value_hint=ValueHint::AnyPath)]
     input: String,
     
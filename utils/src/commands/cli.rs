@@ -4,9 +4,10 @@ use crate::commands::{RunCmd,Defaults};
 use crate::commands::Count;
 use crate::commands::{Calcqts,CalcqtsPrelim,CalcqtsLoadExisting};
 use crate::commands::{Sortblocks, SortblocksInmem};
-use crate::commands::{Update, UpdateDemo, UpdateDropLast};
+use crate::commands::{Update, UpdateDemo, UpdateDropLast, UpdateRollback, Compact, CompactPurge};
 use crate::commands::{UpdateInitial, WriteIndexFile};
-use crate::commands::{Mergechanges, MergechangesSort, MergechangesSortInmem, MergechangesSortFromExisting};
+use crate::commands::{Mergechanges, MergechangesSort, MergechangesSortInmem, MergechangesSortFromExisting, MergechangesGeojson, Diff};
+use crate::commands::Verify;
 use crate::error::Result;
 
 
@@ -52,6 +53,15 @@ pub enum Commands {
     /// Remove last upate
     UpdateDropLast(UpdateDropLast),
     
+    /// Remove updates after a state or timestamp, with their files and state.csv lines
+    UpdateRollback(UpdateRollback),
+    
+    /// Merge the base file and updates into a new base file, keeping the replaced files until compact-purge
+    Compact(Compact),
+    
+    /// Remove files replaced by compact
+    CompactPurge(CompactPurge),
+    
     /// Write index file for sorted pbf
     WriteIndexFile(WriteIndexFile),
     
@@ -66,7 +76,16 @@ pub enum Commands {
     MergechangesSortInmem(MergechangesSortInmem),
     
     /// Merge and filter from temporary files retained after mergechanges-sort
-    MergechangesSortFromExisting(MergechangesSortFromExisting)
+    MergechangesSortFromExisting(MergechangesSortFromExisting),
+    
+    /// Merge and filter sorted planet and updates, write as GeoJSON-seq. Run in memory.
+    MergechangesGeojson(MergechangesGeojson),
+    
+    /// Compare two pbf files, or two timestamps of an osmquadtree directory, and write the changes as .osc, .osc.gz or .pbfc
+    Diff(Diff),
+    
+    /// Check integrity of a pbf file or osmquadtree directory, writing a json report of any problems
+    Verify(Verify),
 }
 
 impl RunCmd for Cli {
@@ -88,6 +107,9 @@ impl RunCmd for Cli {
             Commands::Update(update) => update.run(defaults),
             Commands::UpdateDemo(update) => update.run(defaults),
             Commands::UpdateDropLast(update) => update.run(defaults),
+            Commands::UpdateRollback(update) => update.run(defaults),
+            Commands::Compact(compact) => compact.run(defaults),
+            Commands::CompactPurge(compact) => compact.run(defaults),
             
             Commands::UpdateInitial(update) => update.run(defaults),
             Commands::WriteIndexFile(write) => write.run(defaults),
@@ -97,6 +119,10 @@ impl RunCmd for Cli {
             Commands::MergechangesSort(merge) => merge.run(defaults),
             Commands::MergechangesSortInmem(merge) => merge.run(defaults),
             Commands::MergechangesSortFromExisting(merge) => merge.run(defaults),
+            Commands::MergechangesGeojson(merge) => merge.run(defaults),
+            Commands::Diff(diff) => diff.run(defaults),
+            
+            Commands::Verify(verify) => verify.run(defaults),
         }
     }
 }
@@ -1,12 +1,12 @@
 use osmquadtree::count::run_count;
 use clap::{Args,ValueHint};
-use crate::commands::{RunCmd,Defaults,add_trailing_slash_to_directory};
+use crate::commands::{RunCmd,Defaults,add_trailing_slash_to_directory,make_bad_blocks,report_bad_blocks};
 use crate::error::Result;
 
 
 #[derive(Args, Debug)]
 pub struct Count {
-    ///Sets the input file (or directory) to use
+    ///Sets the input file (or directory) to use, or - to read a pbf file from stdin
     #[arg(value_hint=ValueHint::AnyPath)]
     input: String,
     
@@ -19,6 +19,10 @@ pub struct Count {
     #[arg(allow_hyphen_values=true)]
     filter: Option<String>,
     
+    ///filters elements by tags, e.g. "w/highway=primary,secondary;n/amenity;!building"
+    #[arg(long)]
+    tag_filter: Option<String>,
+    
     ///includes updates up to timestamp
     #[arg(short)] #[arg(long)]
     timestamp: Option<String>,
@@ -26,20 +30,25 @@ pub struct Count {
     ///uses <NUMCHAN> parallel threads
     #[arg(short)] #[arg(long)]
     #[arg(value_parser = clap::value_parser!(u16).range(0..24))]
-    numchan: Option<u16>
+    numchan: Option<u16>,
+    
+    ///skips and reports blocks which can't be read, rather than aborting
+    #[arg(long)]
+    skip_bad_blocks: bool,
 }
 impl RunCmd for Count {
     fn run(&self, defaults: &Defaults) -> Result<()> {
-        
-        Ok(run_count(
+        let bad_blocks = make_bad_blocks(self.skip_bad_blocks);
+        run_count(
             &add_trailing_slash_to_directory(&self.input),
             self.primitive,
             match self.numchan { None => defaults.numchan_default, Some(n) => n.into() },
             self.filter.as_deref(),
+            self.tag_filter.as_deref(),
             self.timestamp.as_deref(),
-            
-        )?)
-        
-        
+            &bad_blocks,
+        )?;
+        report_bad_blocks(&bad_blocks);
+        Ok(())
     }
 }        
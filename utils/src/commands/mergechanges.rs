@@ -1,13 +1,14 @@
 
 use clap::{Args,ValueHint};
-use crate::commands::{RunCmd,Defaults, add_trailing_slash_to_directory};
+use crate::commands::{RunCmd,Defaults, add_trailing_slash_to_directory, make_bad_blocks, report_bad_blocks};
 use crate::commands::sortblocks::CompressionType;
 use crate::commands::run_sortblocks::get_compression_type;
 use crate::error::Result;
 
 use osmquadtree::mergechanges::{
         run_mergechanges,   run_mergechanges_sort,
-        run_mergechanges_sort_inmem, run_mergechanges_sort_from_existing
+        run_mergechanges_sort_inmem, run_mergechanges_sort_from_existing,
+        run_mergechanges_geojson, run_diff
 };
 
 #[derive(Args, Debug)]
@@ -24,6 +25,11 @@ pub struct MergechangesCommon {
     #[arg(short='f', long, allow_hyphen_values=true)]
     pub(crate) filter: Option<String>,
     
+    /// filter elements by tags, e.g. "w/highway=primary,secondary;n/amenity;!building",
+    /// including the nodes of matching ways and the members of matching relations
+    #[arg(long)]
+    pub(crate) tag_filter: Option<String>,
+    
     #[arg(short='F', long)]
     pub(crate) filter_objs: bool,
     
@@ -31,6 +37,10 @@ pub struct MergechangesCommon {
     #[arg(short, long)]
     pub(crate) timestamp: Option<String>,
     
+    ///for full-history input, extracts the state at timestamp rather than every version
+    #[arg(long)]
+    pub(crate) history_timestamp: Option<String>,
+    
     ///uses <NUMCHAN> parallel threads
     #[arg(short, long, value_parser = clap::value_parser!(u16).range(0..24))]
     pub(crate) numchan: Option<u16>,
@@ -43,6 +53,13 @@ pub struct MergechangesCommon {
     #[arg(short='C', long, value_parser=clap::value_parser!(u32).range(0..10))]
     pub(crate) compression_level: Option<u32>,    
     
+    /// skip and report blocks which can't be read, rather than aborting
+    #[arg(long)]
+    pub(crate) skip_bad_blocks: bool,
+    
+    /// writingprogram value for the output file header
+    #[arg(long)]
+    pub(crate) writing_program: Option<String>,
 }
 
 #[derive(Args, Debug)]
@@ -55,22 +72,33 @@ pub struct MergechangesSortInmem {
     #[arg(value_parser = clap::value_parser!(u16).range(0..32))]
     ram_gb: Option<u16>,
     
+    /// Store node locations on ways (the LocationsOnWays feature)
+    #[arg(long)]
+    locations_on_ways: bool,
     
 }
 
 impl RunCmd for MergechangesSortInmem {
     fn run(&self, defaults: &Defaults) -> Result<()> {
-        Ok(run_mergechanges_sort_inmem(
+        let bad_blocks = make_bad_blocks(self.mergechanges.skip_bad_blocks);
+        run_mergechanges_sort_inmem(
             &add_trailing_slash_to_directory(&self.mergechanges.input), 
             &self.mergechanges.outfn,
             self.mergechanges.filter.as_deref(),
+            self.mergechanges.tag_filter.as_deref(),
             self.mergechanges.filter_objs,
             self.mergechanges.timestamp.as_deref(),
             match self.mergechanges.numchan { Some(n) => n.into(), None => defaults.numchan_default},
             match self.ram_gb { Some(n) => n.into(), None => defaults.numchan_default},
             
-            get_compression_type(&self.mergechanges.compression_type, &self.mergechanges.compression_level)
-        )?)
+            get_compression_type(&self.mergechanges.compression_type, &self.mergechanges.compression_level),
+            self.locations_on_ways,
+            self.mergechanges.history_timestamp.as_deref(),
+            self.mergechanges.writing_program.as_deref(),
+            &bad_blocks,
+        )?;
+        report_bad_blocks(&bad_blocks);
+        Ok(())
     }
 }
         
@@ -98,14 +126,20 @@ pub struct MergechangesSort {
     #[arg(value_parser = clap::value_parser!(u16).range(0..32))]
     ram_gb: Option<u16>,
     
+    /// Store node locations on ways (the LocationsOnWays feature)
+    #[arg(long)]
+    locations_on_ways: bool,
+    
 }
 impl RunCmd for MergechangesSort {
     fn run(&self, defaults: &Defaults) -> Result<()> {
-        Ok(run_mergechanges_sort    (
+        let bad_blocks = make_bad_blocks(self.mergechanges.skip_bad_blocks);
+        run_mergechanges_sort    (
             &add_trailing_slash_to_directory(&self.mergechanges.input),
             &self.mergechanges.outfn,
             self.tempfn.as_deref(),
             self.mergechanges.filter.as_deref(),
+            self.mergechanges.tag_filter.as_deref(),
             self.mergechanges.filter_objs,
             self.mergechanges.timestamp.as_deref(),
             self.keeptemps,
@@ -113,8 +147,13 @@ impl RunCmd for MergechangesSort {
             match self.mergechanges.numchan { Some(n) => n.into(), None => defaults.numchan_default},
             match self.ram_gb { Some(n) => n.into(), None => defaults.numchan_default},
             self.single_temp_file,
-            
-        )?)
+            self.locations_on_ways,
+            self.mergechanges.history_timestamp.as_deref(),
+            self.mergechanges.writing_program.as_deref(),
+            &bad_blocks,
+        )?;
+        report_bad_blocks(&bad_blocks);
+        Ok(())
     }
 }
 
@@ -126,15 +165,22 @@ pub struct Mergechanges {
 
 impl RunCmd for Mergechanges {
     fn run(&self, defaults: &Defaults) -> Result<()> {
-        Ok(run_mergechanges(
+        let bad_blocks = make_bad_blocks(self.mergechanges.skip_bad_blocks);
+        run_mergechanges(
             &add_trailing_slash_to_directory(&self.mergechanges.input), 
             &self.mergechanges.outfn,
             self.mergechanges.filter.as_deref(),
+            self.mergechanges.tag_filter.as_deref(),
             self.mergechanges.filter_objs,
             self.mergechanges.timestamp.as_deref(),
             get_compression_type(&self.mergechanges.compression_type, &self.mergechanges.compression_level),
             match self.mergechanges.numchan { Some(n) => n.into(), None => defaults.numchan_default},
-        )?)
+            self.mergechanges.history_timestamp.as_deref(),
+            self.mergechanges.writing_program.as_deref(),
+            &bad_blocks,
+        )?;
+        report_bad_blocks(&bad_blocks);
+        Ok(())
     }
 }
 
@@ -182,6 +228,122 @@ impl RunCmd for MergechangesSortFromExisting {
 }
 
 
+#[derive(Args, Debug)]
+pub struct MergechangesGeojson {
+    ///Sets the input file (or directory) to use
+    #[arg(value_hint=ValueHint::AnyPath)]
+    input: String,
+    
+    /// Output filename, written as GeoJSON-seq (one feature per line)
+    #[arg(short, long, value_hint=ValueHint::FilePath)]
+    outfn: String,
+    
+    /// filters blocks by bbox or poly file FILTER
+    #[arg(short='f', long, allow_hyphen_values=true)]
+    filter: Option<String>,
+    
+    /// filter elements by tags, e.g. "w/highway=primary,secondary;n/amenity;!building",
+    /// including the nodes of matching ways and the members of matching relations
+    #[arg(long)]
+    tag_filter: Option<String>,
+    
+    /// filter objects within blocks
+    #[arg(short='F', long)]
+    filter_objs: bool,
+    
+    ///includes updates up to timestamp
+    #[arg(short, long)]
+    timestamp: Option<String>,
+    
+    ///for full-history input, extracts the state at timestamp rather than every version
+    #[arg(long)]
+    history_timestamp: Option<String>,
+    
+    ///uses <NUMCHAN> parallel threads
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(0..24))]
+    numchan: Option<u16>,
+    
+    /// try to use less than <RAM_GB> GB of ram
+    #[arg(short, long)]
+    #[arg(value_parser = clap::value_parser!(u16).range(0..32))]
+    ram_gb: Option<u16>,
+    
+    /// skip and report blocks which can't be read, rather than aborting
+    #[arg(long)]
+    skip_bad_blocks: bool,
+}
+
+impl RunCmd for MergechangesGeojson {
+    fn run(&self, defaults: &Defaults) -> Result<()> {
+        let bad_blocks = make_bad_blocks(self.skip_bad_blocks);
+        run_mergechanges_geojson(
+            &add_trailing_slash_to_directory(&self.input),
+            &self.outfn,
+            self.filter.as_deref(),
+            self.tag_filter.as_deref(),
+            self.filter_objs,
+            self.timestamp.as_deref(),
+            match self.numchan { Some(n) => n.into(), None => defaults.numchan_default},
+            match self.ram_gb { Some(n) => n.into(), None => defaults.ram_gb_default},
+            self.history_timestamp.as_deref(),
+            &bad_blocks,
+        )?;
+        report_bad_blocks(&bad_blocks);
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct Diff {
+    ///Sets the left (older) input file or directory
+    #[arg(value_hint=ValueHint::AnyPath)]
+    left: String,
+    
+    ///Sets the right (newer) input file or directory, defaults to LEFT
+    #[arg(value_hint=ValueHint::AnyPath)]
+    right: Option<String>,
+    
+    /// Output filename, ending in .osc, .osc.gz or .pbfc
+    #[arg(short, long, value_hint=ValueHint::FilePath)]
+    outfn: String,
+    
+    ///includes updates to left directory up to timestamp
+    #[arg(long)]
+    left_timestamp: Option<String>,
+    
+    ///includes updates to right directory up to timestamp
+    #[arg(long)]
+    right_timestamp: Option<String>,
+    
+    ///uses <NUMCHAN> parallel threads
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(0..24))]
+    numchan: Option<u16>,
+    
+    /// try to use less than <RAM_GB> GB of ram when sorting directories
+    #[arg(short, long)]
+    #[arg(value_parser = clap::value_parser!(u16).range(0..32))]
+    ram_gb: Option<u16>,
+}
+
+impl RunCmd for Diff {
+    fn run(&self, defaults: &Defaults) -> Result<()> {
+        let left = add_trailing_slash_to_directory(&self.left);
+        let right = match &self.right {
+            Some(r) => add_trailing_slash_to_directory(r),
+            None => left.clone(),
+        };
+        Ok(run_diff(
+            &left,
+            self.left_timestamp.as_deref(),
+            &right,
+            self.right_timestamp.as_deref(),
+            &self.outfn,
+            match self.numchan { Some(n) => n.into(), None => defaults.numchan_default},
+            match self.ram_gb { Some(n) => n.into(), None => defaults.ram_gb_default},
+        )?)
+    }
+}
+
 /*
     
     Some(("mergechanges_sort_inmem", filter)) => {
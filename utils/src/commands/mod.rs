@@ -5,6 +5,7 @@ mod run_sortblocks;
 mod update;
 mod update_initial;
 mod mergechanges;
+mod verify;
 mod cli;
 
 
@@ -13,14 +14,16 @@ use clap::{Command,CommandFactory,Parser};
 pub use count::Count;
 pub use calcqts::{Calcqts,CalcqtsPrelim,CalcqtsLoadExisting};
 pub use sortblocks::{Sortblocks,SortblocksInmem};
-pub use update::{Update,UpdateDemo,UpdateDropLast};
+pub use update::{Update,UpdateDemo,UpdateDropLast,UpdateRollback,Compact,CompactPurge};
 pub use update_initial::{UpdateInitial, WriteIndexFile};
-pub use mergechanges::{Mergechanges, MergechangesSortInmem, MergechangesSort, MergechangesSortFromExisting};
+pub use mergechanges::{Mergechanges, MergechangesSortInmem, MergechangesSort, MergechangesSortFromExisting, MergechangesGeojson, Diff};
+pub use verify::Verify;
 pub use cli::{Commands,Cli};
 
 
 use sysinfo::{System};
 use osmquadtree::message;
+use osmquadtree::pbfformat::{BadBlockPolicy, BadBlocks};
 use crate::error::Result;
 
 
@@ -68,6 +71,16 @@ pub fn run_app<T: AsRef<std::ffi::OsStr> + std::fmt::Display >(defaults: &Defaul
     cli.run(defaults)
 }
 
+pub(crate) fn make_bad_blocks(skip_bad_blocks: bool) -> BadBlocks {
+    BadBlocks::new(if skip_bad_blocks { BadBlockPolicy::SkipAndReport } else { BadBlockPolicy::Abort })
+}
+
+pub(crate) fn report_bad_blocks(bad_blocks: &BadBlocks) {
+    if bad_blocks.num_skipped() > 0 {
+        message!("skipped {} bad blocks", bad_blocks.num_skipped());
+    }
+}
+
 pub fn add_trailing_slash_to_directory(input_path: &str) -> String {
     if input_path.ends_with("/") {
         return String::from(input_path);
@@ -2,10 +2,10 @@
 use crate::commands::{Defaults};
 use crate::commands::sortblocks::{SortblocksType, SortblocksCommon, CompressionType as ClapCompresstionType};
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use osmquadtree::message;
 use osmquadtree::sortblocks::{sort_blocks, sort_blocks_inmem, find_groups, QuadtreeTree};
-use osmquadtree::pbfformat::{CompressionType,file_length};
+use osmquadtree::pbfformat::{CompressionType,file_length,is_stdin};
 use osmquadtree::utils::{LogTimes,parse_timestamp};
 use std::sync::Arc;
 
@@ -33,6 +33,17 @@ pub(crate) fn get_compression_type(compression_type: &ClapCompresstionType, leve
         } else {
             return CompressionType::Lzma;
         }
+    } else if compression_type.zstd {
+        if let Some(l) = level {
+            return CompressionType::ZstdLevel(*l);
+        } else {
+            return CompressionType::Zstd;
+        }
+    } else if compression_type.lz4 {
+        if level.is_some() {
+            panic!("lz4 compression doesn't take a compression level")
+        }
+        return CompressionType::Lz4;
     } else if compression_type.uncompressed {
         
         return CompressionType::Uncompressed;
@@ -53,7 +64,17 @@ pub(crate) fn get_compression_type(compression_type: &ClapCompresstionType, leve
 pub(crate) fn run_sortblocks(sortblocks: &SortblocksCommon, sortblocks_type: SortblocksType, defaults: &Defaults)  -> Result<()> {
     let mut lt = LogTimes::new();
 
-    
+    if is_stdin(&sortblocks.input) {
+        match sortblocks_type {
+            SortblocksType::Inmem => {},
+            _ => {
+                return Err(Error::InvalidInputError("only sortblocks-inmem can read from stdin".to_string()));
+            }
+        }
+        if sortblocks.qtsfn.is_none() || sortblocks.outfn.is_none() {
+            return Err(Error::InvalidInputError("must specify qtsfn and outfn when reading from stdin".to_string()));
+        }
+    }
     
     
     let splitat = 1500000i64 / sortblocks.target;
@@ -8,7 +8,7 @@ use crate::commands::run_sortblocks::run_sortblocks;
 
 #[derive(Args, Debug)]
 pub struct SortblocksCommon {
-    ///Sets the source planet pbf file.
+    ///Sets the source planet pbf file, or - to read from stdin (sortblocks-inmem only).
     #[arg(value_hint=ValueHint::FilePath)]
     pub(crate) input: String,
     
@@ -87,6 +87,14 @@ pub(crate) struct CompressionType {
     /// don't use any compression
     #[arg(short='U', long)]
     pub uncompressed: bool,
+    
+    /// use zstd compression algorithm
+    #[arg(short='Z', long)]
+    pub zstd: bool,
+    
+    /// use lz4 compression algorithm
+    #[arg(long)]
+    pub lz4: bool,
 }
 
 #[derive(Debug)]
@@ -1,7 +1,9 @@
-use osmquadtree::update::{run_update};
+use osmquadtree::update::{run_update, run_update_rollback, run_compact, purge_compacted};
 use osmquadtree::pbfformat::{read_filelist, write_filelist};
 use clap::{Args,ValueHint};
 use crate::commands::{RunCmd,Defaults, add_trailing_slash_to_directory};
+use crate::commands::sortblocks::CompressionType;
+use crate::commands::run_sortblocks::get_compression_type;
 use crate::error::{Error, Result};
 
 
@@ -66,14 +68,14 @@ impl RunCmd for UpdateDropLast {
         
         let input = add_trailing_slash_to_directory(&self.input);
         
-        let mut fl = read_filelist(&input);
+        let mut fl = read_filelist(&input)?;
         if fl.len() < 2 {
             return Err(Error::InvalidInputError(
                 format!("{}filelist.json only has {} entries", &self.input, fl.len())
             ));
         }
         fl.pop();
-        write_filelist(&self.input, &fl);
+        write_filelist(&input, &fl)?;
         Ok(())
         
         
@@ -81,6 +83,86 @@ impl RunCmd for UpdateDropLast {
 }
 
 
+#[derive(Args, Debug)]
+pub struct UpdateRollback {
+    ///Sets the input directory to use
+    #[arg(value_hint=ValueHint::DirPath)]
+    input: String,
+    
+    /// Keep updates up to replication state number, or timestamp (e.g. 2024-01-01T00:00:00)
+    #[arg(long)]
+    to: String,
+    
+    /// Report the files which would be removed, without changing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl RunCmd for UpdateRollback {
+    fn run(&self, _defaults: &Defaults) -> Result<()> {
+        run_update_rollback(&add_trailing_slash_to_directory(&self.input), &self.to, self.dry_run)?;
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct Compact {
+    ///Sets the input directory to use
+    #[arg(value_hint=ValueHint::DirPath)]
+    input: String,
+    
+    /// Replace the first NUM_ENTRIES filelist entries, defaults to all entries
+    #[arg(short='N', long, value_parser = clap::value_parser!(u32).range(2..))]
+    num_entries: Option<u32>,
+    
+    /// Filename for the new base file, defaults to compact-<DATE>.pbf
+    #[arg(short, long)]
+    outfn: Option<String>,
+    
+    /// Regroup tiles with more than twice SPLIT_TARGET elements into tiles of about SPLIT_TARGET elements
+    #[arg(short, long)]
+    split_target: Option<i64>,
+    
+    ///uses <NUMCHAN> parallel threads
+    #[arg(short)] #[arg(long)]
+    #[arg(value_parser = clap::value_parser!(u16).range(0..24))]
+    numchan: Option<u16>,
+    
+    #[command(flatten)]
+    compression_type: CompressionType,
+    
+    /// compression level
+    #[arg(short='C', long, value_parser=clap::value_parser!(u32).range(0..10))]
+    compression_level: Option<u32>,
+}
+
+impl RunCmd for Compact {
+    fn run(&self, defaults: &Defaults) -> Result<()> {
+        Ok(run_compact(
+            &add_trailing_slash_to_directory(&self.input),
+            self.num_entries.map(|n| n as usize),
+            self.outfn.as_deref(),
+            self.split_target,
+            get_compression_type(&self.compression_type, &self.compression_level),
+            match self.numchan { None => defaults.numchan_default, Some(n) => n.into() },
+        )?)
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct CompactPurge {
+    ///Sets the input directory to use
+    #[arg(value_hint=ValueHint::DirPath)]
+    input: String,
+}
+
+impl RunCmd for CompactPurge {
+    fn run(&self, _defaults: &Defaults) -> Result<()> {
+        purge_compacted(&add_trailing_slash_to_directory(&self.input))?;
+        Ok(())
+    }
+}
+
 
 /*
         .subcommand(
@@ -22,7 +22,8 @@ pub struct UpdateInitial {
     #[arg(short, long)]
     initial_state: Option<i64>,
     
-    /// Replication xml-change source to download, default planet.openstreetmap.org/replication/day/
+    /// Replication xml-change source to download, default planet.openstreetmap.org/replication/day/.
+    /// May also be a file:// url or local directory holding a replication mirror
     #[arg(short='x', long)]
     diffs_source: Option<String>,
     
@@ -39,6 +40,14 @@ pub struct UpdateInitial {
     #[arg(short='b',long, default_value_t = QT_BUFFER_DEFAULT)]
     qt_buffer: f64,
     
+    /// Period of the replication diffs (minute, hour or day), guessed from DIFFS_SOURCE if not specified
+    #[arg(long)]
+    replication_period: Option<String>,
+    
+    /// Period of each update file (minute, hour or day): diffs are merged until a period is complete. Defaults to day
+    #[arg(long)]
+    update_period: Option<String>,
+    
     ///uses <NUMCHAN> parallel threads
     #[arg(short)] #[arg(long)]
     #[arg(value_parser = clap::value_parser!(u16).range(0..24))]
@@ -57,6 +66,8 @@ impl RunCmd for UpdateInitial {
             &self.diffs_location,
             self.qt_level.into(),
             self.qt_buffer,
+            self.replication_period.as_deref(),
+            self.update_period.as_deref(),
             match self.numchan { None => defaults.numchan_default, Some(n) => n.into() },
         )?)        
     }
@@ -81,11 +92,15 @@ pub struct WriteIndexFile {
 
 impl RunCmd for WriteIndexFile {
     fn run(&self, defaults: &Defaults) -> Result<()> {
+        let outfn = match &self.outfn {
+            Some(o) => o.clone(),
+            None => format!("{}-index.pbf", self.input),
+        };
         write_index_file(
-            &add_trailing_slash_to_directory(&self.input),
-            match &self.outfn { Some(o) => o, None => "" },
+            &self.input,
+            &outfn,
             match self.numchan { None => defaults.numchan_default, Some(n) => n.into() },
-        );
+        )?;
         Ok(())
     }
 }
@@ -0,0 +1,32 @@
+use osmquadtree::verify::run_verify;
+use clap::{Args,ValueHint};
+use crate::commands::{RunCmd,Defaults,add_trailing_slash_to_directory};
+use crate::error::Result;
+
+
+#[derive(Args, Debug)]
+pub struct Verify {
+    ///Sets the input file (or directory) to use
+    #[arg(value_hint=ValueHint::AnyPath)]
+    input: String,
+    
+    ///write json report to OUTFN, defaults to stdout
+    #[arg(short, long, value_hint=ValueHint::FilePath)]
+    outfn: Option<String>,
+    
+    ///uses <NUMCHAN> parallel threads
+    #[arg(short)] #[arg(long)]
+    #[arg(value_parser = clap::value_parser!(u16).range(0..24))]
+    numchan: Option<u16>
+}
+impl RunCmd for Verify {
+    fn run(&self, defaults: &Defaults) -> Result<()> {
+        
+        Ok(run_verify(
+            &add_trailing_slash_to_directory(&self.input),
+            self.outfn.as_deref(),
+            match self.numchan { None => defaults.numchan_default, Some(n) => n.into() },
+        )?)
+        
+    }
+}        
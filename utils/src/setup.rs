@@ -299,7 +299,7 @@ pub(crate) fn run(ram_gb_default: usize, numchan_default:usize) -> Result<()> {
     if let Some((replication_src, repl_diff_location)) = update_spec {
         
         run_update_initial(&dest_root, &out_filename_file, &timestamp_string(timestamp), None, Some(&replication_src), &repl_diff_location,
-            17, 0.05, numchan)?;
+            17, 0.05, None, None, numchan)?;
             
             
         run_update(&dest_root, 0, false, numchan)?;